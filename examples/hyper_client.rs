@@ -39,6 +39,7 @@ fn write_body(multi: &mut Multipart<Request<Streaming>>) -> hyper::Result<()> {
     multi.write_text("text", "Hello, world!")?;
     multi.write_file("file", "lorem_ipsum.txt")?;
     // &[u8] impl Read
-    multi.write_stream("binary", &mut binary, None, None)
-        .and(Ok(()))
+    let written = multi.write_stream("binary", &mut binary, None, None)?;
+    println!("wrote {} bytes of binary data", written);
+    Ok(())
 }
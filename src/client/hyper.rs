@@ -7,6 +7,8 @@
 //! Client-side integration with [Hyper](https://github.com/hyperium/hyper). 
 //! Enabled with the `hyper` feature (on by default).
 //!
+//! Supports Hyper 0.9 and 0.10; for Hyper 1.x, see the `hyper1` module.
+//!
 //! Contains `impl HttpRequest for Request<Fresh>` and `impl HttpStream for Request<Streaming>`.
 //!
 //! Also see: [`lazy::Multipart::client_request()`](../lazy/struct.Multipart.html#method.client_request)
@@ -0,0 +1,138 @@
+// Copyright 2016 `multipart` Crate Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//! Client-side integration with [Hyper](https://github.com/hyperium/hyper) 1.x.
+//! Enabled with the `hyper1` feature, which is independent of the `hyper` feature for
+//! Hyper 0.9 and 0.10.
+//!
+//! Contains `Body`, an implementation of `hyper::body::Body` over `lazy::PreparedFields`, and
+//! `request()`, which builds a `hyper::Request` with it.
+//!
+//! ### Note: Blocking
+//! The fields are read synchronously when the body is polled, so a field streamed from a slow
+//! source blocks the task sending the request. Fields held in memory or in local files are
+//! fine for most uses.
+//!
+//! ### Note: Not `Send`
+//! `Body` is not `Send`, because the streams in `PreparedFields` aren't required to be. It can't
+//! be used with clients which spawn connections onto a multithreaded executor, such as
+//! `hyper_util::client::legacy::Client`; instead, open a connection with
+//! `hyper::client::conn::http1::handshake()` and drive it on the current thread, e.g. with
+//! `tokio::task::spawn_local()`. See `tests/hyper1_client_server.rs` for an example.
+use bytes_1::Bytes;
+
+use hyper_1::body::{Body as HttpBody, Frame, SizeHint};
+use hyper_1::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper_1::http::request::Builder;
+use hyper_1::http::Result as HttpResult;
+use hyper_1::Request;
+
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::lazy::PreparedFields;
+
+/// The most data read from the fields for each frame of the body.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// A `hyper::body::Body` which reads a `PreparedFields` in chunks.
+///
+/// Its size hint is exact if `PreparedFields::content_len()` is known. Not `Send`; see the
+/// [module docs](index.html).
+pub struct Body<'d> {
+    fields: PreparedFields<'d>,
+    /// The length of the body left to send, if known.
+    remaining: Option<u64>,
+    done: bool,
+}
+
+impl<'d> Body<'d> {
+    /// Wrap `fields` in a body.
+    pub fn new(fields: PreparedFields<'d>) -> Self {
+        Body {
+            remaining: fields.content_len(),
+            fields: fields,
+            done: false,
+        }
+    }
+
+    fn read_chunk(&mut self) -> io::Result<Option<Bytes>> {
+        let mut chunk = vec![0; CHUNK_SIZE];
+
+        let read = loop {
+            match self.fields.read(&mut chunk) {
+                Ok(read) => break read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        };
+
+        if read == 0 {
+            return Ok(None);
+        }
+
+        chunk.truncate(read);
+
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining = remaining.saturating_sub(read as u64);
+        }
+
+        Ok(Some(Bytes::from(chunk)))
+    }
+}
+
+/// #### Feature: `hyper1`
+impl<'d> HttpBody for Body<'d> {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(self: Pin<&mut Self>, _cx: &mut Context)
+                  -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        let body = self.get_mut();
+
+        if body.done {
+            return Poll::Ready(None);
+        }
+
+        Poll::Ready(match body.read_chunk() {
+            Ok(Some(chunk)) => Some(Ok(Frame::data(chunk))),
+            Ok(None) => {
+                body.done = true;
+                None
+            },
+            Err(e) => {
+                body.done = true;
+                Some(Err(e))
+            },
+        })
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.done || self.remaining == Some(0)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.remaining {
+            Some(remaining) => SizeHint::with_exact(remaining),
+            None => SizeHint::default(),
+        }
+    }
+}
+
+/// Finish `builder` with `fields` as the body, e.g. `request(Request::post(uri), fields)`.
+///
+/// Sets the `Content-Type` header, and `Content-Length` if all fields are sized
+/// (`PreparedFields::content_len()`); otherwise Hyper sends the body chunked.
+pub fn request<'d>(builder: Builder, fields: PreparedFields<'d>) -> HttpResult<Request<Body<'d>>> {
+    let mut builder = builder.header(CONTENT_TYPE, fields.content_type_header());
+
+    if let Some(content_len) = fields.content_len() {
+        builder = builder.header(CONTENT_LENGTH, content_len);
+    }
+
+    builder.body(Body::new(fields))
+}
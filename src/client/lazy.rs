@@ -1,16 +1,22 @@
 //! Multipart requests which write out their data in one fell swoop.
 use mime::Mime;
 
+use std::any::Any;
 use std::borrow::Cow;
 use std::error::Error;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::path::{Path, PathBuf};
 
 use std::io::prelude::*;
-use std::io::Cursor;
-use std::{fmt, io, mem};
+use std::io::{Cursor, SeekFrom};
+use std::{fmt, io, mem, slice};
 
-use super::{HttpRequest, HttpStream, MultipartWriter};
+use tempdir::TempDir;
+
+use super::{HttpRequest, HttpStream, MultipartWriter, Tee};
+
+#[cfg(feature = "server")]
+use server::{self, MultipartData, MultipartField, ReadEntry, ReadEntryResult};
 
 macro_rules! try_lazy (
     ($field:expr, $try:expr) => (
@@ -118,6 +124,13 @@ impl<'a, E: fmt::Display> fmt::Display for LazyError<'a, E> {
 #[derive(Debug, Default)]
 pub struct Multipart<'n, 'd> {
     fields: Vec<Field<'n, 'd>>,
+    part_content_len: bool,
+    default_file_mime: Option<Mime>,
+    webkit_boundary: bool,
+    skip_collision_check: bool,
+    trailing_crlf: bool,
+    /// Set by `extended_filenames(false)`.
+    basic_filenames: bool,
 }
 
 impl<'n, 'd> Multipart<'n, 'd> {
@@ -140,6 +153,9 @@ impl<'n, 'd> Multipart<'n, 'd> {
 
     /// Add a file field to this request.
     ///
+    /// The content type is guessed from the file extension, falling back to the type set with
+    /// `set_default_file_mime()` or `application/octet-stream`.
+    ///
     /// ### Note
     /// Does not check if `path` exists.
     pub fn add_file<N, P>(&mut self, name: N, path: P) -> &mut Self where N: Into<Cow<'n, str>>, P: IntoCowPath<'d> {
@@ -153,15 +169,101 @@ impl<'n, 'd> Multipart<'n, 'd> {
         self
     }
 
-    /// Add a generic stream field to this request,
+    /// Add a generic stream field to this request.
+    ///
+    /// If `mime` is `None`, the type set with `set_default_file_mime()` is used, or
+    /// `application/octet-stream` if it wasn't set.
     pub fn add_stream<N, R, F>(&mut self, name: N, stream: R, filename: Option<F>, mime: Option<Mime>) -> &mut Self where N: Into<Cow<'n, str>>, R: Read + 'd, F: Into<Cow<'n, str>> {
         self.fields.push(
             Field {
                 name: name.into(),
                 data: Data::Stream(Stream {
-                    content_type: mime.unwrap_or_else(::mime_guess::octet_stream),
+                    content_type: mime,
+                    filename: filename.map(|f| f.into()),
+                    transfer_encoding: None,
+                    stream: Box::new(stream),
+                    meta: None,
+                    disposition: Some("form-data"),
+                }),
+            }
+        );
+
+        self
+    }
+
+    /// Add a generic stream field to this request, as with `add_stream()`, but with the
+    /// disposition type `disposition` instead of `form-data`, e.g. `attachment` or `inline` for
+    /// the parts of a `multipart/mixed` or `multipart/related` body.
+    ///
+    /// If `disposition` is `None`, the `Content-Disposition` header only has the `name` and
+    /// `filename` parameters. The type isn't checked or escaped, so it must be a valid token.
+    pub fn add_stream_with_disposition<N, R, F>(&mut self, disposition: Option<&'n str>, name: N,
+                                                stream: R, filename: Option<F>, mime: Option<Mime>)
+                                                -> &mut Self
+    where N: Into<Cow<'n, str>>, R: Read + 'd, F: Into<Cow<'n, str>> {
+        self.fields.push(
+            Field {
+                name: name.into(),
+                data: Data::Stream(Stream {
+                    content_type: mime,
+                    filename: filename.map(|f| f.into()),
+                    transfer_encoding: None,
+                    stream: Box::new(stream),
+                    meta: None,
+                    disposition: disposition,
+                }),
+            }
+        );
+
+        self
+    }
+
+    /// Add a generic stream field to this request, as with `add_stream()`, and attach `meta` to
+    /// it, e.g. to correlate the field with the object it was built from.
+    ///
+    /// The metadata isn't sent; retrieve it with `PreparedFields::field_meta()` after `prepare()`.
+    pub fn add_stream_with_meta<N, R, F, T>(&mut self, name: N, stream: R, filename: Option<F>,
+                                            mime: Option<Mime>, meta: T) -> &mut Self
+    where N: Into<Cow<'n, str>>, R: Read + 'd, F: Into<Cow<'n, str>>, T: Any {
+        self.fields.push(
+            Field {
+                name: name.into(),
+                data: Data::Stream(Stream {
+                    content_type: mime,
+                    filename: filename.map(|f| f.into()),
+                    transfer_encoding: None,
+                    stream: Box::new(stream),
+                    meta: Some(Box::new(meta)),
+                    disposition: Some("form-data"),
+                }),
+            }
+        );
+
+        self
+    }
+
+    /// Add a stream field to this request which is sent base64-encoded, with a
+    /// `Content-Transfer-Encoding: base64` header, for servers which require it.
+    ///
+    /// If `wrap` is `Some(n)`, a CRLF is inserted after every `n` characters of the encoded data,
+    /// e.g. `Some(76)` as required by MIME ([IETF RFC 2045][rfc2045]); if it is `None` (or
+    /// `Some(0)`), the data is a single line. Otherwise the same as `add_stream()`; in particular,
+    /// the length of the field is unknown so `PreparedFields::content_len()` will be `None`.
+    ///
+    /// [rfc2045]: https://tools.ietf.org/html/rfc2045#section-6.8
+    pub fn add_stream_base64_wrapped<N, R, F>(&mut self, name: N, stream: R, filename: Option<F>,
+                                              mime: Option<Mime>, wrap: Option<usize>) -> &mut Self
+    where N: Into<Cow<'n, str>>, R: Read + 'd, F: Into<Cow<'n, str>> {
+        self.fields.push(
+            Field {
+                name: name.into(),
+                data: Data::Stream(Stream {
+                    content_type: mime,
                     filename: filename.map(|f| f.into()),
-                    stream: Box::new(stream)
+                    transfer_encoding: Some("base64"),
+                    stream: Box::new(Base64Encoder::new(stream, wrap)),
+                    meta: None,
+                    disposition: Some("form-data"),
                 }),
             }
         );
@@ -169,6 +271,145 @@ impl<'n, 'd> Multipart<'n, 'd> {
         self
     }
 
+    /// Add a stream field to this request whose data is `sources` read one after another, e.g.
+    /// for a file assembled from several chunks.
+    ///
+    /// Otherwise the same as `add_stream()`; in particular, the length of the field is unknown
+    /// so `PreparedFields::content_len()` will be `None`.
+    pub fn add_chained_stream<N, F>(&mut self, name: N, mut sources: Vec<Box<Read + 'd>>, filename: Option<F>,
+                                    mime: Option<Mime>) -> &mut Self where N: Into<Cow<'n, str>>, F: Into<Cow<'n, str>> {
+        // read from the back so finished sources can be popped
+        sources.reverse();
+
+        self.add_stream(name, ChainedReaders { sources: sources }, filename, mime)
+    }
+
+    /// Add several files under the single field `name`, sent as a nested `multipart/mixed`
+    /// part with its own boundary, as described in [IETF RFC 2388, section 4.6][rfc2388].
+    ///
+    /// Each file is a `(stream, filename, mime)` tuple; if `mime` is `None`, it is guessed from
+    /// the filename's extension. Filenames are written as with `extended_filenames()`, as set
+    /// when this is called.
+    ///
+    /// This form was deprecated by [IETF RFC 7578][rfc7578] and most servers expect one field
+    /// per file with the same name instead, so only use it for servers which require it. As with
+    /// `add_stream()`, the length of the field is unknown so `PreparedFields::content_len()` will
+    /// be `None`.
+    ///
+    /// [rfc2388]: https://tools.ietf.org/html/rfc2388#section-4.6
+    /// [rfc7578]: https://tools.ietf.org/html/rfc7578#section-4.3
+    pub fn add_file_group<N, R, F>(&mut self, name: N, files: Vec<(R, F, Option<Mime>)>) -> &mut Self
+    where N: Into<Cow<'n, str>>, R: Read + 'd, F: AsRef<str> {
+        let boundary = super::gen_boundary();
+        let mut sources: Vec<Box<Read + 'd>> = Vec::new();
+
+        for (stream, filename, mime) in files {
+            let filename = filename.as_ref();
+            let content_type = mime.unwrap_or_else(|| ::mime_guess::guess_mime_type(filename));
+
+            let mut header = Vec::new();
+            write!(header, "\r\n--{}\r\nContent-Disposition: file", boundary).unwrap();
+            super::write_filename(&mut header, filename, !self.basic_filenames).unwrap();
+            write!(header, "\r\nContent-Type: {}\r\n\r\n", content_type).unwrap();
+
+            sources.push(Box::new(Cursor::new(header)));
+            sources.push(Box::new(stream));
+        }
+
+        let end_boundary = if sources.is_empty() {
+            format!("--{}--", boundary)
+        } else {
+            format!("\r\n--{}--", boundary)
+        };
+
+        sources.push(Box::new(Cursor::new(end_boundary)));
+
+        let content_type = format!("multipart/mixed; boundary={}", boundary).parse()
+            .expect("generated boundary is a valid parameter value");
+
+        self.add_chained_stream(name, sources, None::<&'n str>, Some(content_type))
+    }
+
+    /// Relay the rest of the fields of `server` in this request, preserving their names,
+    /// filenames and content types (or `text/plain` if they didn't have one), as with
+    /// `forward_field()`; more fields may be added afterwards.
+    ///
+    /// Nothing is read from `server` until this request is sent: each field is then read from
+    /// it and written out in turn, so none of them are buffered. As with stream fields, the
+    /// request has no `content_len()`, and can't be prepared with
+    /// `Multipart::emit_part_content_length()` set or more than once.
+    ///
+    /// #### Features: `client` and `server`
+    #[cfg(feature = "server")]
+    pub fn extend_from_server<R: Read + 'd>(&mut self, server: &'d mut server::Multipart<R>)
+                                            -> &mut Self {
+        self.fields.push(Field {
+            name: Cow::Borrowed(""),
+            data: Data::Relay(Box::new(ServerRelay { server: server })),
+        });
+
+        self
+    }
+
+    /// Set the content type for stream fields added with no MIME type, and for files whose type
+    /// can't be guessed from their extension, instead of `application/octet-stream`.
+    ///
+    /// Applies to all such fields in the request, whether added before or after this call.
+    pub fn set_default_file_mime(&mut self, mime: Mime) -> &mut Self {
+        self.default_file_mime = Some(mime);
+        self
+    }
+
+    /// Generate boundaries in the style of WebKit-based browsers, `----WebKitFormBoundary`
+    /// followed by 16 random alphanumeric characters, for servers which only accept boundaries
+    /// that look like a browser's.
+    pub fn use_webkit_style_boundary(&mut self) -> &mut Self {
+        self.webkit_boundary = true;
+        self
+    }
+
+    /// If `true`, don't check that a newly generated boundary doesn't occur in any of the text
+    /// fields, which means scanning all of them each time the request is serialized.
+    ///
+    /// ### Warning: Possible Data Loss
+    /// A text field containing the boundary would be cut short at that point by the server
+    /// without any error. With a random boundary this is only likely if the text was crafted
+    /// to match it, e.g. by echoing back a previous request. Off by default.
+    pub fn skip_boundary_collision_check(&mut self, skip: bool) -> &mut Self {
+        self.skip_collision_check = skip;
+        self
+    }
+
+    /// If `true`, end the request body with a CRLF after the closing boundary
+    /// (`--{boundary}--\r\n`), for servers which expect one; other servers may reject it.
+    ///
+    /// `PreparedFields::content_len()` includes the CRLF if it is written. Off by default.
+    pub fn trailing_crlf(&mut self, emit: bool) -> &mut Self {
+        self.trailing_crlf = emit;
+        self
+    }
+
+    /// If `true`, a filename containing non-ASCII characters is written both as the plain
+    /// `filename` parameter and as a percent-encoded `filename*=UTF-8''...` parameter
+    /// ([IETF RFC 5987][rfc5987]), which servers that support it prefer.
+    ///
+    /// If `false`, only the plain `filename` is written, as raw UTF-8. On by default.
+    ///
+    /// [rfc5987]: https://tools.ietf.org/html/rfc5987
+    pub fn extended_filenames(&mut self, extended: bool) -> &mut Self {
+        self.basic_filenames = !extended;
+        self
+    }
+
+    /// If `true`, write a `Content-Length` header for each field in this request.
+    ///
+    /// Some servers expect each field to carry its own length. In this mode, every field must
+    /// have a known size, so `prepare()` will return an error if any generic streams were added.
+    pub fn emit_part_content_length(&mut self, emit: bool) -> &mut Self {
+        self.part_content_len = emit;
+        self
+    }
+
     /// Convert `req` to `HttpStream`, write out the fields in this request, and finish the
     /// request, returning the response if successful, or the first error encountered.
     ///
@@ -185,12 +426,161 @@ impl<'n, 'd> Multipart<'n, 'd> {
         stream.finish().map_err(LazyError::without_field)
     }
 
+    /// Write out the fields in this request directly to `dest`, returning the number of bytes
+    /// written, or the first error encountered.
+    ///
+    /// Unlike `send()`, this does not apply any headers; use `prepare()` and
+    /// `PreparedFields::content_type_header()` if you need the boundary.
+    ///
+    /// If any files were added by path they will now be opened for reading.
+    pub fn send_to<W: Write>(&mut self, mut dest: W) -> LazyIoResult<'n, u64> {
+        let mut prepared = self.prepare()?;
+
+        Ok(try_lazy!(io::copy(&mut prepared, &mut dest)))
+    }
+
+    /// Like `send_to()`, but also copy everything written to `dest` to `log`, e.g. to record
+    /// outgoing requests for debugging.
+    ///
+    /// Returns the first error from either `dest` or `log`.
+    pub fn send_tee<W: Write, L: Write>(&mut self, dest: W, log: L) -> LazyIoResult<'n, u64> {
+        self.send_to(Tee::new(dest, log))
+    }
+
     /// Export the multipart data contained in this lazy request as an adaptor which implements `Read`.
     ///
     /// During this step, if any files were added by path then they will be opened for reading
     /// and their length measured.
     pub fn prepare(&mut self) -> LazyIoResult<'n, PreparedFields<'d>> {
-        PreparedFields::from_fields(&mut self.fields)
+        let boundary = self.gen_boundary();
+
+        PreparedFields::from_fields(&mut self.fields, boundary, self.part_content_len,
+                                    self.trailing_crlf, !self.basic_filenames,
+                                    self.default_file_mime.as_ref())
+    }
+
+    /// Like `prepare()`, but guarantee that `content_len()` of the result is `Some`, for
+    /// servers which require a `Content-Length` and check it against the body.
+    ///
+    /// Generic streams have no known length, so if any were added, an error of kind
+    /// `InvalidInput` is returned for the first of them, before any files are opened.
+    pub fn prepare_fixed_size(&mut self) -> LazyIoResult<'n, PreparedFields<'d>> {
+        if let Some(name) = self.fields.iter().find(|field| match field.data {
+            Data::Stream(_) | Data::Relay(_) => true,
+            _ => false,
+        }).map(|field| field.name.clone()) {
+            return Err(LazyError::with_field(name, io::Error::new(
+                io::ErrorKind::InvalidInput, "cannot determine the length of a stream field"
+            )));
+        }
+
+        self.prepare()
+    }
+
+    /// Like `prepare()`, but keep the fields in this request so it can be prepared again,
+    /// e.g. to send the same form to several servers.
+    ///
+    /// Only text fields and files added by path can be prepared more than once; if any
+    /// streams were added, an error of kind `InvalidInput` is returned for the first of them.
+    pub fn prepare_cloned(&self) -> LazyIoResult<'n, PreparedFields<'d>> {
+        let mut fields = Vec::with_capacity(self.fields.len());
+
+        for field in &self.fields {
+            let data = match field.data {
+                Data::Text(ref text) => Data::Text(text.clone()),
+                Data::File(ref path) => Data::File(path.clone()),
+                Data::Stream(_) => return Err(LazyError::with_field(field.name.clone(), io::Error::new(
+                    io::ErrorKind::InvalidInput, "cannot prepare a stream field more than once"
+                ))),
+                Data::Relay(_) => return Err(LazyError::without_field(io::Error::new(
+                    io::ErrorKind::InvalidInput, "cannot prepare relayed fields more than once"
+                ))),
+            };
+
+            fields.push(Field { name: field.name.clone(), data: data });
+        }
+
+        PreparedFields::from_fields(&mut fields, self.gen_boundary(), self.part_content_len,
+                                    self.trailing_crlf, !self.basic_filenames,
+                                    self.default_file_mime.as_ref())
+    }
+
+    /// Serialize the fields in this request to a body which implements `Read + Seek`, so it can
+    /// be rewound and sent again, e.g. to retry a failed upload.
+    ///
+    /// If the serialized body is no larger than `threshold` bytes it is kept in memory;
+    /// otherwise it is written to a temporary file, which is deleted when the returned
+    /// `RewindableFields` is dropped.
+    pub fn prepare_rewindable_with_threshold(&mut self, threshold: usize)
+                                             -> LazyIoResult<'n, RewindableFields> {
+        let mut prepared = self.prepare()?;
+        let boundary = prepared.boundary().to_owned();
+
+        // read one byte past the threshold to tell if the body is larger
+        let mut buf = Vec::new();
+        try_lazy!((&mut prepared).take(threshold as u64 + 1).read_to_end(&mut buf));
+
+        if buf.len() <= threshold {
+            return Ok(RewindableFields {
+                content_len: buf.len() as u64,
+                body: RewindableBody::Memory(Cursor::new(buf)),
+                boundary: boundary,
+            });
+        }
+
+        let dir = try_lazy!(TempDir::new("multipart-rs"));
+
+        let mut file = try_lazy!(OpenOptions::new().read(true).write(true).create_new(true)
+            .open(dir.path().join("body")));
+
+        try_lazy!(file.write_all(&buf));
+        let content_len = buf.len() as u64 + try_lazy!(io::copy(&mut prepared, &mut file));
+        try_lazy!(file.seek(SeekFrom::Start(0)));
+
+        Ok(RewindableFields {
+            body: RewindableBody::File(file, dir),
+            boundary: boundary,
+            content_len: content_len,
+        })
+    }
+
+    /// Get an adaptor which implements `Read` over the fields in this request without
+    /// consuming them, so it can be created again to serialize the same fields multiple times.
+    ///
+    /// Unlike `prepare()`, fields are written in the order they were added, and files added by
+    /// path are not opened until the encoder reaches them.
+    ///
+    /// ## Returns Error
+    /// If any generic streams were added, as they can only be read once.
+    pub fn encoder(&self) -> LazyIoResult<'n, MultipartEncoder> {
+        MultipartEncoder::new(&self.fields, self.gen_boundary(), self.part_content_len,
+                              self.trailing_crlf, !self.basic_filenames,
+                              self.default_file_mime.clone())
+    }
+
+    /// Generate a new boundary which doesn't occur in any of the text fields, unless
+    /// `skip_collision_check` is set.
+    fn gen_boundary(&self) -> String {
+        loop {
+            let boundary = if self.webkit_boundary {
+                format!("----WebKitFormBoundary{}", ::random_alphanumeric(16))
+            } else {
+                super::gen_boundary()
+            };
+
+            if self.skip_collision_check {
+                return boundary;
+            }
+
+            let collides = self.fields.iter().any(|field| match field.data {
+                Data::Text(ref text) => text.contains(&*boundary),
+                _ => false,
+            });
+
+            if !collides {
+                return boundary;
+            }
+        }
     }
 }
 
@@ -204,6 +594,9 @@ enum Data<'n, 'd> {
     Text(Cow<'d, str>),
     File(Cow<'d, Path>),
     Stream(Stream<'n, 'd>),
+    /// Fields relayed from elsewhere; the field name is unused.
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    Relay(Box<Relay + 'd>),
 }
 
 impl<'n, 'd> fmt::Debug for Data<'n, 'd> {
@@ -212,14 +605,31 @@ impl<'n, 'd> fmt::Debug for Data<'n, 'd> {
             Data::Text(ref text) => write!(f, "Data::Text({:?})", text),
             Data::File(ref path) => write!(f, "Data::File({:?})", path),
             Data::Stream(_) => f.write_str("Data::Stream(Box<Read>)"),
+            Data::Relay(_) => f.write_str("Data::Relay(Box<Relay>)"),
         }
     }
 }
 
 struct Stream<'n, 'd> {
     filename: Option<Cow<'n, str>>,
-    content_type: Mime,
+    content_type: Option<Mime>,
+    transfer_encoding: Option<&'static str>,
     stream: Box<Read + 'd>,
+    /// Set by `add_stream_with_meta()`.
+    meta: Option<Box<Any>>,
+    /// The disposition type, `form-data` unless set by `add_stream_with_disposition()`.
+    disposition: Option<&'n str>,
+}
+
+/// A source of fields which only writes them out as the request is read, e.g. for
+/// `Multipart::extend_from_server()`.
+///
+/// Has no lifetime parameter of its own so that `Data` stays covariant over `'d`.
+trait Relay {
+    /// Get a reader over the fields, each starting with `boundary` (which includes the leading
+    /// CRLF) and their headers.
+    fn into_reader<'a>(self: Box<Self>, boundary: &str, extended_filenames: bool) -> Box<Read + 'a>
+        where Self: 'a;
 }
 
 /// The result of [`Multipart::prepare()`](struct.Multipart.html#method.prepare).
@@ -236,38 +646,87 @@ pub struct PreparedFields<'d> {
     streams: Vec<PreparedField<'d>>,
     end_boundary: Cursor<String>,
     content_len: Option<u64>,
+    /// The length of everything but the field data, unless there are relayed fields.
+    overhead_len: Option<u64>,
+    /// The names and metadata of fields added with `add_stream_with_meta()`, in order.
+    meta: Vec<(String, Box<Any>)>,
 }
 
 impl<'d> PreparedFields<'d> {
-    fn from_fields<'n>(fields: &mut Vec<Field<'n, 'd>>) -> Result<Self, LazyIoError<'n>> {
+    fn from_fields<'n>(fields: &mut Vec<Field<'n, 'd>>, boundary: String, part_content_len: bool,
+                       trailing_crlf: bool, extended_filenames: bool, default_mime: Option<&Mime>)
+                       -> Result<Self, LazyIoError<'n>> {
         debug!("Field count: {}", fields.len());
 
         // One of the two RFCs specifies that any bytes before the first boundary are to be
         // ignored anyway
-        let mut boundary = format!("\r\n--{}", super::gen_boundary());
+        let mut boundary = format!("\r\n--{}", boundary);
 
         let mut text_data = Vec::new();
         let mut streams = Vec::new();
         let mut content_len = 0u64;
         let mut use_len = true;
+        let mut overhead_len = 0u64;
+        let mut relayed = false;
+        let mut meta = Vec::new();
 
         for field in fields.drain(..) {
             match field.data {
-                Data::Text(text) => write!(text_data, "{}\r\nContent-Disposition: form-data; \
-                                                       name=\"{}\"\r\n\r\n{}",
-                                           boundary, field.name, text).unwrap(),
+                Data::Text(text) => {
+                    let start_len = text_data.len();
+
+                    write!(text_data, "{}\r\n", boundary).unwrap();
+                    super::write_content_disp(&mut text_data, &field.name, None, false).unwrap();
+
+                    if part_content_len {
+                        write!(text_data, "\r\nContent-Length: {}", text.len()).unwrap();
+                    }
+
+                    write!(text_data, "\r\n\r\n{}", text).unwrap();
+                    overhead_len += (text_data.len() - start_len - text.len()) as u64;
+                },
                 Data::File(file) => {
-                    let (stream, len) = PreparedField::from_path(field.name, &file, &boundary)?;
+                    let (stream, len) = PreparedField::from_path(field.name, &file, &boundary,
+                                                                 part_content_len,
+                                                                 extended_filenames, default_mime)?;
                     content_len += len;
                     streams.push(stream);
                 },
+                Data::Stream(_) if part_content_len => {
+                    return Err(LazyError::with_field(field.name, io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "cannot emit `Content-Length` for a stream of unknown length"
+                    )));
+                },
                 Data::Stream(stream) => {
                     use_len = false;
 
+                    if let Some(field_meta) = stream.meta {
+                        meta.push((field.name.to_string(), field_meta));
+                    }
+
                     streams.push(
-                        PreparedField::from_stream(&field.name, &boundary, stream.content_type,
+                        PreparedField::from_stream(stream.disposition, &field.name, &boundary,
+                                                   stream_mime(stream.content_type, default_mime),
                                                    stream.filename.as_ref().map(|f| &**f),
-                                                   stream.stream));
+                                                   extended_filenames, None,
+                                                   stream.transfer_encoding, stream.stream));
+                },
+                Data::Relay(_) if part_content_len => {
+                    return Err(LazyError::without_field(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "cannot emit `Content-Length` for relayed fields of unknown length"
+                    )));
+                },
+                Data::Relay(relay) => {
+                    use_len = false;
+                    relayed = true;
+
+                    // the relay writes the headers of each field itself
+                    streams.push(PreparedField {
+                        header: Cursor::new(Vec::new()),
+                        stream: relay.into_reader(&boundary, extended_filenames),
+                    });
                 },
             }
         }
@@ -276,19 +735,42 @@ impl<'d> PreparedFields<'d> {
         if text_data.is_empty() && streams.is_empty() {
             boundary = String::new();
         } else {
-            boundary.push_str("--");
+            boundary.push_str(if trailing_crlf { "--\r\n" } else { "--" });
         }
 
-        content_len += boundary.len() as u64;
+        content_len += (text_data.len() + boundary.len()) as u64;
+
+        overhead_len += boundary.len() as u64;
+        overhead_len += streams.iter().map(|stream| stream.header.get_ref().len() as u64).sum::<u64>();
 
         Ok(PreparedFields {
             text_data: Cursor::new(text_data),
             streams: streams,
             end_boundary: Cursor::new(boundary),
             content_len: if use_len { Some(content_len) } else { None } ,
+            overhead_len: if relayed { None } else { Some(overhead_len) },
+            meta: meta,
         })
     }
 
+    /// Get the number of bytes in the request which aren't field data: the boundaries, the
+    /// headers of each field and the line breaks around them.
+    ///
+    /// Adding the lengths of the fields' data gives `content_len()`, if that is known. Returns
+    /// `None` if fields were added with `Multipart::extend_from_server()`, whose headers aren't
+    /// known until they are read.
+    pub fn overhead_len(&self) -> Option<u64> {
+        self.overhead_len
+    }
+
+    /// Get the metadata attached with `Multipart::add_stream_with_meta()` to the first field
+    /// named `name`, or `None` if it has none or it isn't a `T`.
+    pub fn field_meta<T: Any>(&self, name: &str) -> Option<&T> {
+        self.meta.iter()
+            .find(|&&(ref field_name, _)| field_name == name)
+            .and_then(|&(_, ref meta)| (**meta).downcast_ref())
+    }
+
     /// Get the content-length value for this set of fields, if applicable (all fields are sized,
     /// i.e. not generic streams).
     pub fn content_len(&self) -> Option<u64> {
@@ -298,9 +780,18 @@ impl<'d> PreparedFields<'d> {
     /// Get the boundary that was used to serialize the request.
     pub fn boundary(&self) -> &str {
         let boundary = self.end_boundary.get_ref();
+        let suffix_len = if boundary.ends_with("\r\n") { 4 } else { 2 };
 
         // Get just the bare boundary string
-        &boundary[4 .. boundary.len() - 2]
+        &boundary[4 .. boundary.len() - suffix_len]
+    }
+
+    /// Get the full value of the `Content-Type` header for this request,
+    /// i.e. `multipart/form-data; boundary={boundary}`, quoting the boundary if necessary.
+    ///
+    /// Useful if you need to set the header yourself.
+    pub fn content_type_header(&self) -> String {
+        format!("multipart/form-data; boundary={}", super::quote_boundary(self.boundary()))
     }
 }
 
@@ -335,36 +826,118 @@ impl<'d> Read for PreparedFields<'d> {
     }
 }
 
+/// The result of [`Multipart::prepare_rewindable_with_threshold()`](struct.Multipart.html#method.prepare_rewindable_with_threshold).
+///
+/// Implements `Read + Seek` over the complete request body, which is held in memory or in a
+/// temporary file depending on its size.
+#[derive(Debug)]
+pub struct RewindableFields {
+    body: RewindableBody,
+    boundary: String,
+    content_len: u64,
+}
+
+#[derive(Debug)]
+enum RewindableBody {
+    Memory(Cursor<Vec<u8>>),
+    // the file is closed before its directory is deleted
+    File(File, TempDir),
+}
+
+impl RewindableFields {
+    /// Get the length of the serialized request body.
+    pub fn content_len(&self) -> u64 {
+        self.content_len
+    }
+
+    /// Get the boundary that was used to serialize the request.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Get the full value of the `Content-Type` header for this request,
+    /// i.e. `multipart/form-data; boundary={boundary}`, quoting the boundary if necessary.
+    pub fn content_type_header(&self) -> String {
+        format!("multipart/form-data; boundary={}", super::quote_boundary(&self.boundary))
+    }
+
+    /// Returns `true` if the body is held in memory instead of a temporary file.
+    pub fn is_in_memory(&self) -> bool {
+        match self.body {
+            RewindableBody::Memory(_) => true,
+            RewindableBody::File(..) => false,
+        }
+    }
+
+    /// Seek back to the start of the body.
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.seek(SeekFrom::Start(0)).map(|_| ())
+    }
+}
+
+impl Read for RewindableFields {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.body {
+            RewindableBody::Memory(ref mut cursor) => cursor.read(buf),
+            RewindableBody::File(ref mut file, _) => file.read(buf),
+        }
+    }
+}
+
+impl Seek for RewindableFields {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self.body {
+            RewindableBody::Memory(ref mut cursor) => cursor.seek(pos),
+            RewindableBody::File(ref mut file, _) => file.seek(pos),
+        }
+    }
+}
+
 struct PreparedField<'d> {
     header: Cursor<Vec<u8>>,
     stream: Box<Read + 'd>,
 }
 
 impl<'d> PreparedField<'d> {
-    fn from_path<'n>(name: Cow<'n, str>, path: &Path, boundary: &str) -> Result<(Self, u64), LazyIoError<'n>> {
-        let (content_type, filename) = super::mime_filename(&path);
+    fn from_path<'n>(name: Cow<'n, str>, path: &Path, boundary: &str, part_content_len: bool,
+                     extended_filenames: bool, default_mime: Option<&Mime>)
+                     -> Result<(Self, u64), LazyIoError<'n>> {
+        let (content_type, filename) = file_mime_filename(&path, default_mime);
 
         let file = try_lazy!(name, File::open(path));
         let content_len = try_lazy!(name, file.metadata()).len();
 
-        let stream = Self::from_stream(&name, boundary, content_type, filename, Box::new(file));
+        let part_len = if part_content_len { Some(content_len) } else { None };
+
+        let stream = Self::from_stream(Some("form-data"), &name, boundary, content_type,
+                                       filename, extended_filenames, part_len, None,
+                                       Box::new(file));
 
         let content_len = content_len + (stream.header.get_ref().len() as u64);
 
         Ok((stream, content_len))
     }
 
-    fn from_stream(name: &str, boundary: &str, content_type: Mime, filename: Option<&str>, stream: Box<Read + 'd>) -> Self {
+    fn from_stream(disposition: Option<&str>, name: &str, boundary: &str, content_type: Mime,
+                   filename: Option<&str>, extended_filename: bool, content_len: Option<u64>,
+                   transfer_encoding: Option<&str>, stream: Box<Read + 'd>) -> Self {
         let mut header = Vec::new();
 
-        write!(header, "{}\r\nContent-Disposition: form-data; name=\"{}\"",
-               boundary, name).unwrap();
+        write!(header, "{}\r\n", boundary).unwrap();
+        super::write_content_disp_type(&mut header, disposition, name, filename,
+                                       extended_filename).unwrap();
+
+        write!(header, "\r\nContent-Type: {}", content_type).unwrap();
 
-        if let Some(filename) = filename {
-            write!(header, "; filename=\"{}\"", filename).unwrap();
+        if let Some(content_len) = content_len {
+            write!(header, "\r\nContent-Length: {}", content_len).unwrap();
         }
 
-        write!(header, "\r\nContent-Type: {}\r\n\r\n", content_type).unwrap();
+        if let Some(transfer_encoding) = transfer_encoding {
+            write!(header, "\r\nContent-Transfer-Encoding: {}", transfer_encoding).unwrap();
+        }
+
+        header.extend_from_slice(b"\r\n\r\n");
 
         PreparedField {
             header: Cursor::new(header),
@@ -394,66 +967,437 @@ impl<'d> fmt::Debug for PreparedField<'d> {
     }
 }
 
-struct CowStrAsRef<'d>(Cow<'d, str>);
+/// Add `field`, read with the server-side API, to `multipart` as a stream field, preserving its
+/// name, filename and content type (or `text/plain` if it didn't have one, which is the default
+/// for `multipart/form-data`), so it can be relayed without buffering its data.
+///
+/// The field's data will be read from the incoming request when `multipart` is sent, so the
+/// next field of the incoming request can't be read until then. Any other fields to be relayed
+/// in the same request need to be read beforehand (e.g. by saving them).
+///
+/// #### Features: `client` and `server`
+#[cfg(feature = "server")]
+pub fn forward_field<'n, 'd, M>(field: MultipartField<M>, multipart: &mut Multipart<'n, 'd>)
+where M: ReadEntry + 'd {
+    let MultipartField { headers, data } = field;
+    let content_type = headers.content_type.unwrap_or(::mime::TEXT_PLAIN);
+
+    multipart.add_stream(headers.name.to_string(), data, headers.filename, Some(content_type));
+}
 
-impl<'d> AsRef<[u8]> for CowStrAsRef<'d> {
-    fn as_ref(&self) -> &[u8] {
-        self.0.as_bytes()
+/// Relays the fields of a server request, for `Multipart::extend_from_server()`.
+#[cfg(feature = "server")]
+struct ServerRelay<'d, R: 'd> {
+    server: &'d mut server::Multipart<R>,
+}
+
+#[cfg(feature = "server")]
+impl<'d, R: Read + 'd> Relay for ServerRelay<'d, R> {
+    fn into_reader<'a>(self: Box<Self>, boundary: &str, extended_filenames: bool) -> Box<Read + 'a>
+        where Self: 'a {
+        Box::new(RelayReader {
+            boundary: boundary.to_owned(),
+            extended_filenames: extended_filenames,
+            state: RelayState::Between(self.server),
+        })
     }
 }
 
-/// Conversion trait necessary for `Multipart::add_file()` to accept borrowed or owned strings
-/// and borrowed or owned paths
-pub trait IntoCowPath<'a> {
-    /// Self-explanatory, hopefully
-    fn into_cow_path(self) -> Cow<'a, Path>;
+/// Reads the fields of a server request one at a time as it is read.
+#[cfg(feature = "server")]
+struct RelayReader<'d, R: 'd> {
+    boundary: String,
+    extended_filenames: bool,
+    state: RelayState<'d, R>,
 }
 
-impl<'a> IntoCowPath<'a> for Cow<'a, Path> {
-    fn into_cow_path(self) -> Cow<'a, Path> {
-        self
-    }
+#[cfg(feature = "server")]
+enum RelayState<'d, R: 'd> {
+    /// Before the next field is read from the server.
+    Between(&'d mut server::Multipart<R>),
+    /// Writing the headers of a field, before its data.
+    Header(Cursor<Vec<u8>>, MultipartData<&'d mut server::Multipart<R>>),
+    Data(MultipartData<&'d mut server::Multipart<R>>),
+    /// After the last field, or an error.
+    Done,
 }
 
-impl IntoCowPath<'static> for PathBuf {
-    fn into_cow_path(self) -> Cow<'static, Path> {
-        self.into()
+#[cfg(feature = "server")]
+impl<'d, R: Read + 'd> RelayReader<'d, R> {
+    fn next_field(&self, server: &'d mut server::Multipart<R>) -> io::Result<RelayState<'d, R>> {
+        let MultipartField { headers, data } = match ReadEntry::read_entry(server) {
+            ReadEntryResult::Entry(field) => field,
+            ReadEntryResult::End(_) => return Ok(RelayState::Done),
+            ReadEntryResult::Error(_, err) => return Err(err),
+        };
+
+        let mut header = Vec::new();
+
+        write!(header, "{}\r\n", self.boundary)?;
+        super::write_content_disp(&mut header, &headers.name,
+                                  headers.filename.as_ref().map(|f| &**f),
+                                  self.extended_filenames)?;
+        write!(header, "\r\nContent-Type: {}\r\n\r\n",
+               headers.content_type.unwrap_or(::mime::TEXT_PLAIN))?;
+
+        Ok(RelayState::Header(Cursor::new(header), data))
     }
 }
 
-impl<'a> IntoCowPath<'a> for &'a Path {
-    fn into_cow_path(self) -> Cow<'a, Path> {
-        self.into()
+#[cfg(feature = "server")]
+impl<'d, R: Read + 'd> Read for RelayReader<'d, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let read = match self.state {
+                RelayState::Header(ref mut header, _) => header.read(buf)?,
+                RelayState::Data(ref mut data) => data.read(buf)?,
+                _ => 0,
+            };
+
+            if read != 0 {
+                return Ok(read);
+            }
+
+            self.state = match mem::replace(&mut self.state, RelayState::Done) {
+                RelayState::Between(server) => self.next_field(server)?,
+                RelayState::Header(_, data) => RelayState::Data(data),
+                RelayState::Data(data) => RelayState::Between(data.into_inner()),
+                RelayState::Done => return Ok(0),
+            };
+        }
     }
 }
 
-impl IntoCowPath<'static> for String {
-    fn into_cow_path(self) -> Cow<'static, Path> {
-        PathBuf::from(self).into()
+/// Guess the content type of a file from its extension, falling back to `default`.
+fn file_mime_filename<'p>(path: &'p Path, default: Option<&Mime>) -> (Mime, Option<&'p str>) {
+    let (content_type, filename) = super::mime_filename(path);
+
+    match default {
+        Some(default) if content_type == ::mime_guess::octet_stream() => (default.clone(), filename),
+        _ => (content_type, filename),
     }
 }
 
-impl<'a> IntoCowPath<'a> for &'a str {
-    fn into_cow_path(self) -> Cow<'a, Path> {
-        Path::new(self).into()
-    }
+fn stream_mime(content_type: Option<Mime>, default: Option<&Mime>) -> Mime {
+    content_type.or_else(|| default.cloned()).unwrap_or_else(::mime_guess::octet_stream)
 }
 
-fn cursor_at_end<T: AsRef<[u8]>>(cursor: &Cursor<T>) -> bool {
-    cursor.position() == (cursor.get_ref().as_ref().len() as u64)
+/// The result of [`Multipart::encoder()`](struct.Multipart.html#method.encoder).
+///
+/// Implements `Read`, producing the entire request body from fields borrowed from the
+/// `Multipart` (which cannot be modified while this is alive).
+///
+/// Each encoder generates a new boundary.
+pub struct MultipartEncoder<'a> {
+    fields: slice::Iter<'a, Field<'a, 'a>>,
+    boundary: String,
+    part_content_len: bool,
+    extended_filenames: bool,
+    part: Option<PreparedField<'a>>,
+    end_boundary: Cursor<String>,
+    default_file_mime: Option<Mime>,
 }
 
-#[cfg(feature = "hyper")]
-mod hyper {
-    use hyper::client::{Body, Client, IntoUrl, RequestBuilder, Response};
-    use hyper::Result as HyperResult;
+impl<'a> MultipartEncoder<'a> {
+    fn new<'n>(fields: &'a [Field<'n, 'a>], boundary: String, part_content_len: bool,
+               trailing_crlf: bool, extended_filenames: bool, default_file_mime: Option<Mime>)
+               -> Result<Self, LazyIoError<'n>> {
+        for field in fields {
+            match field.data {
+                Data::Stream(_) => return Err(LazyError::with_field(field.name.clone(), io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "cannot encode a stream field by reference"
+                ))),
+                Data::Relay(_) => return Err(LazyError::without_field(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "cannot encode relayed fields by reference"
+                ))),
+                _ => (),
+            }
+        }
 
-    impl<'n, 'd> super::Multipart<'n, 'd> {
-        /// #### Feature: `hyper`
-        /// Complete a POST request with the given `hyper::client::Client` and URL.
-        /// 
-        /// Supplies the fields in the body, optionally setting the content-length header if
-        /// applicable (all added fields were text or files, i.e. no streams).
+        let boundary = format!("\r\n--{}", boundary);
+
+        let end_boundary = if fields.is_empty() {
+            String::new()
+        } else if trailing_crlf {
+            format!("{}--\r\n", boundary)
+        } else {
+            format!("{}--", boundary)
+        };
+
+        Ok(MultipartEncoder {
+            fields: fields.iter(),
+            boundary: boundary,
+            part_content_len: part_content_len,
+            extended_filenames: extended_filenames,
+            part: None,
+            end_boundary: Cursor::new(end_boundary),
+            default_file_mime: default_file_mime,
+        })
+    }
+
+    /// Get the boundary that is used to serialize the request.
+    pub fn boundary(&self) -> &str {
+        &self.boundary[4..]
+    }
+
+    fn encode_field(&self, field: &'a Field<'a, 'a>) -> io::Result<PreparedField<'a>> {
+        match field.data {
+            Data::Text(ref text) => {
+                let mut header = Vec::new();
+
+                write!(header, "{}\r\n", self.boundary)?;
+                super::write_content_disp(&mut header, &field.name, None, false)?;
+
+                if self.part_content_len {
+                    write!(header, "\r\nContent-Length: {}", text.len())?;
+                }
+
+                header.extend_from_slice(b"\r\n\r\n");
+
+                Ok(PreparedField {
+                    header: Cursor::new(header),
+                    stream: Box::new(text.as_bytes()),
+                })
+            },
+            Data::File(ref path) => {
+                let (content_type, filename) = file_mime_filename(path,
+                                                                  self.default_file_mime.as_ref());
+                let file = File::open(path)?;
+
+                let content_len = if self.part_content_len {
+                    Some(file.metadata()?.len())
+                } else {
+                    None
+                };
+
+                Ok(PreparedField::from_stream(Some("form-data"), &field.name, &self.boundary,
+                                              content_type, filename, self.extended_filenames,
+                                              content_len, None, Box::new(file)))
+            },
+            // checked in `new()`
+            Data::Stream(_) | Data::Relay(_) => unreachable!(),
+        }
+    }
+}
+
+impl<'a> Read for MultipartEncoder<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            debug!("MultipartEncoder::read() was passed a zero-sized buffer.");
+            return Ok(0);
+        }
+
+        loop {
+            if let Some(ref mut part) = self.part {
+                match part.read(buf)? {
+                    0 => (),
+                    read => return Ok(read),
+                }
+            }
+
+            self.part = match self.fields.next() {
+                Some(field) => Some(self.encode_field(field)?),
+                None => return self.end_boundary.read(buf),
+            };
+        }
+    }
+}
+
+impl<'a> fmt::Debug for MultipartEncoder<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MultipartEncoder")
+            .field("boundary", &self.boundary())
+            .field("part", &self.part)
+            .finish()
+    }
+}
+
+/// Reads each of `sources` to the end in turn, for `Multipart::add_chained_stream()`.
+struct ChainedReaders<'d> {
+    /// In reverse order.
+    sources: Vec<Box<Read + 'd>>,
+}
+
+impl<'d> Read for ChainedReaders<'d> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        while !self.sources.is_empty() {
+            let read = self.sources.last_mut().unwrap().read(buf)?;
+
+            if read > 0 {
+                return Ok(read);
+            }
+
+            self.sources.pop();
+        }
+
+        Ok(0)
+    }
+}
+
+const BASE64_CHARS: &'static [u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The number of bytes encoded at once by `Base64Encoder`; a multiple of 3 so that only
+/// the last chunk needs padding.
+const BASE64_CHUNK_LEN: usize = 3 * 256;
+
+/// Base64-encodes `inner`, for `Multipart::add_stream_base64_wrapped()`.
+struct Base64Encoder<R> {
+    inner: R,
+    wrap: Option<usize>,
+    /// Encoded data not yet returned, starting at `pos`.
+    encoded: Vec<u8>,
+    pos: usize,
+    /// The number of characters on the current line.
+    line_len: usize,
+    done: bool,
+}
+
+impl<R: Read> Base64Encoder<R> {
+    fn new(inner: R, wrap: Option<usize>) -> Self {
+        Base64Encoder {
+            inner: inner,
+            wrap: if wrap == Some(0) { None } else { wrap },
+            encoded: Vec::new(),
+            pos: 0,
+            line_len: 0,
+            done: false,
+        }
+    }
+
+    /// Read and encode the next chunk of `inner`.
+    fn encode_chunk(&mut self) -> io::Result<()> {
+        let mut chunk = [0u8; BASE64_CHUNK_LEN];
+        let mut len = 0;
+
+        // fill the chunk, or read to the end
+        while len < chunk.len() {
+            match self.inner.read(&mut chunk[len..]) {
+                Ok(0) => { self.done = true; break; },
+                Ok(read) => len += read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.encoded.clear();
+        self.pos = 0;
+
+        for group in chunk[..len].chunks(3) {
+            let bits = group.iter().enumerate()
+                .fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+
+            for i in 0..4 {
+                let c = if i <= group.len() {
+                    BASE64_CHARS[(bits >> (18 - 6 * i) & 0x3F) as usize]
+                } else {
+                    b'='
+                };
+
+                self.push_char(c);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push_char(&mut self, c: u8) {
+        if self.wrap == Some(self.line_len) {
+            self.encoded.extend_from_slice(b"\r\n");
+            self.line_len = 0;
+        }
+
+        self.encoded.push(c);
+        self.line_len += 1;
+    }
+}
+
+impl<R: Read> Read for Base64Encoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.encoded.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+
+            self.encode_chunk()?;
+        }
+
+        let read = (&self.encoded[self.pos..]).read(buf)?;
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+struct CowStrAsRef<'d>(Cow<'d, str>);
+
+impl<'d> AsRef<[u8]> for CowStrAsRef<'d> {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+/// Conversion trait necessary for `Multipart::add_file()` to accept borrowed or owned strings
+/// and borrowed or owned paths
+pub trait IntoCowPath<'a> {
+    /// Self-explanatory, hopefully
+    fn into_cow_path(self) -> Cow<'a, Path>;
+}
+
+impl<'a> IntoCowPath<'a> for Cow<'a, Path> {
+    fn into_cow_path(self) -> Cow<'a, Path> {
+        self
+    }
+}
+
+impl IntoCowPath<'static> for PathBuf {
+    fn into_cow_path(self) -> Cow<'static, Path> {
+        self.into()
+    }
+}
+
+impl<'a> IntoCowPath<'a> for &'a Path {
+    fn into_cow_path(self) -> Cow<'a, Path> {
+        self.into()
+    }
+}
+
+impl IntoCowPath<'static> for String {
+    fn into_cow_path(self) -> Cow<'static, Path> {
+        PathBuf::from(self).into()
+    }
+}
+
+impl<'a> IntoCowPath<'a> for &'a str {
+    fn into_cow_path(self) -> Cow<'a, Path> {
+        Path::new(self).into()
+    }
+}
+
+fn cursor_at_end<T: AsRef<[u8]>>(cursor: &Cursor<T>) -> bool {
+    cursor.position() == (cursor.get_ref().as_ref().len() as u64)
+}
+
+#[cfg(feature = "hyper")]
+mod hyper {
+    use hyper::client::{Body, Client, IntoUrl, RequestBuilder, Response};
+    use hyper::Result as HyperResult;
+
+    impl<'n, 'd> super::Multipart<'n, 'd> {
+        /// #### Feature: `hyper`
+        /// Complete a POST request with the given `hyper::client::Client` and URL.
+        /// 
+        /// Supplies the fields in the body, optionally setting the content-length header if
+        /// applicable (all added fields were text or files, i.e. no streams).
         pub fn client_request<U: IntoUrl>(&mut self, client: &Client, url: U) -> HyperResult<Response> {
             self.client_request_mut(client, url, |r| r)
         }
@@ -495,3 +1439,853 @@ mod hyper {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Multipart;
+
+    use client::quote_boundary;
+
+    use mock::ClientRequest;
+
+    use std::io::{self, Write};
+
+    #[test]
+    fn test_content_type_header() {
+        let mut multipart = Multipart::new();
+        multipart.add_text("hello", "world");
+
+        let prepared = multipart.prepare().unwrap();
+        assert_eq!(prepared.content_type_header(),
+                   format!("multipart/form-data; boundary={}", prepared.boundary()));
+    }
+
+    #[test]
+    fn test_quote_boundary() {
+        assert_eq!(quote_boundary("simple-boundary_123"), "simple-boundary_123");
+        assert_eq!(quote_boundary("needs quoting?"), "\"needs quoting?\"");
+    }
+
+    #[test]
+    fn test_send_to() {
+        let mut multipart = Multipart::new();
+        multipart.add_text("hello", "world")
+            .add_stream("stream", &b"stream data"[..], Some("file.bin"), None);
+
+        let mut written = Vec::new();
+        let len = multipart.send_to(&mut written).unwrap();
+        assert_eq!(len, written.len() as u64);
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("hello", "world")
+            .add_stream("stream", &b"stream data"[..], Some("file.bin"), None);
+
+        let sent = multipart.send(ClientRequest::default()).unwrap();
+
+        // Each call generates its own boundary; normalize before comparing.
+        let written = String::from_utf8(written).unwrap();
+        let boundary = written[2..].split("\r\n").next().unwrap();
+        let written = written.replace(boundary, &sent.boundary);
+
+        assert_eq!(written.as_bytes(), &*sent.buf);
+    }
+
+    #[test]
+    fn test_send_tee() {
+        struct BrokenPipe;
+
+        impl Write for BrokenPipe {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("hello", "world")
+            .add_stream("stream", &b"stream data"[..], Some("file.bin"), None);
+
+        let (mut dest, mut log) = (Vec::new(), Vec::new());
+        let len = multipart.send_tee(&mut dest, &mut log).unwrap();
+
+        assert_eq!(len, dest.len() as u64);
+        assert_eq!(dest, log);
+
+        // errors from either sink are returned
+        let err = Multipart::new().add_text("hello", "world")
+            .send_tee(Vec::new(), BrokenPipe).unwrap_err();
+        assert_eq!(err.error.kind(), io::ErrorKind::BrokenPipe);
+
+        let err = Multipart::new().add_text("hello", "world")
+            .send_tee(BrokenPipe, Vec::new()).unwrap_err();
+        assert_eq!(err.error.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_chained_stream() {
+        use server;
+        use std::io::prelude::*;
+
+        let sources: Vec<Box<Read>> = vec![Box::new(&b"first "[..]), Box::new(io::empty()),
+                                           Box::new(io::Cursor::new("and third"))];
+
+        let mut multipart = Multipart::new();
+        multipart.add_chained_stream("chained", sources, Some("chained.txt"), None)
+            .add_text("text", "after");
+
+        let mut prepared = multipart.prepare().unwrap();
+        assert_eq!(prepared.content_len(), None);
+
+        let boundary = prepared.boundary().to_owned();
+        let mut body = Vec::new();
+        prepared.read_to_end(&mut body).unwrap();
+
+        let fields = server::parse_all(&body, &boundary).unwrap();
+        let chained = fields.iter().find(|&&(ref headers, _)| &*headers.name == "chained").unwrap();
+
+        assert_eq!(chained.0.filename.as_ref().unwrap(), "chained.txt");
+        assert_eq!(chained.1, b"first and third");
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_base64_stream() {
+        use server;
+        use server::FieldHeaders;
+        use std::io::prelude::*;
+
+        /// Returns the body and its boundary.
+        fn prepare_base64(data: &[u8], wrap: Option<usize>) -> (Vec<u8>, String) {
+            let mut multipart = Multipart::new();
+            multipart.add_stream_base64_wrapped("data", data, Some("data.bin"), None, wrap);
+
+            let mut prepared = multipart.prepare().unwrap();
+            assert_eq!(prepared.content_len(), None);
+
+            let boundary = prepared.boundary().to_owned();
+            let mut body = Vec::new();
+            prepared.read_to_end(&mut body).unwrap();
+            (body, boundary)
+        }
+
+        fn send_base64(data: &[u8], wrap: Option<usize>) -> (FieldHeaders, String) {
+            let (body, boundary) = prepare_base64(data, wrap);
+            let (headers, data) = server::parse_all(&body, &boundary).unwrap().pop().unwrap();
+            (headers, String::from_utf8(data).unwrap())
+        }
+
+        /// Decode the field on the server, as a client of such a server would expect.
+        fn round_trip(data: &[u8], wrap: Option<usize>) -> Vec<u8> {
+            let (body, boundary) = prepare_base64(data, wrap);
+            let mut multipart = server::Multipart::with_body(&body[..], boundary)
+                .with_transfer_decoding(true);
+
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert!(field.data.is_transfer_decoded());
+
+            let mut decoded = Vec::new();
+            field.data.read_to_end(&mut decoded).unwrap();
+            decoded
+        }
+
+        let data: Vec<u8> = (0..100).collect();
+        let (headers, encoded) = send_base64(&data, None);
+
+        assert_eq!(headers.transfer_encoding.as_ref().unwrap(), "base64");
+        assert_eq!(encoded, "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4\
+                             OTo7PD0+P0BBQkNERUZHSElKS0xNTk9QUVJTVFVWV1hZWltcXV5fYGFiYw==");
+        assert_eq!(send_base64(&data, Some(0)).1, encoded);
+        assert_eq!(send_base64(b"Many hands make light work.", None).1, "TWFueSBoYW5kcyBtYWtlIGxpZ2h0IHdvcmsu");
+        assert_eq!(round_trip(&data, None), data);
+
+        // more than one chunk of the encoder
+        let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let unwrapped = send_base64(&data, None).1;
+        let wrapped = send_base64(&data, Some(64)).1;
+
+        assert_eq!(unwrapped.len(), 1336);
+        assert_eq!(wrapped.replace("\r\n", ""), unwrapped);
+
+        let lines: Vec<&str> = wrapped.split("\r\n").collect();
+        assert_eq!(lines.len(), 21);
+        assert!(lines[..20].iter().all(|line| line.len() == 64));
+        assert_eq!(lines[20].len(), 56);
+
+        assert_eq!(round_trip(&data, None), data);
+        assert_eq!(round_trip(&data, Some(64)), data);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_webkit_style_boundary() {
+        use server;
+        use std::io::prelude::*;
+
+        const PREFIX: &'static str = "----WebKitFormBoundary";
+
+        let mut multipart = Multipart::new();
+        multipart.use_webkit_style_boundary().add_text("hello", "world");
+
+        assert!(multipart.encoder().unwrap().boundary().starts_with(PREFIX));
+
+        let mut prepared = multipart.prepare().unwrap();
+        let boundary = prepared.boundary().to_owned();
+
+        assert!(boundary.starts_with(PREFIX), "{}", boundary);
+
+        let suffix = &boundary[PREFIX.len()..];
+        assert_eq!(suffix.len(), 16);
+        assert!(suffix.bytes().all(|b| match b {
+            b'0' ... b'9' | b'a' ... b'z' | b'A' ... b'Z' => true,
+            _ => false,
+        }), "{}", suffix);
+
+        // doesn't need quoting
+        assert_eq!(quote_boundary(&boundary), &*boundary);
+
+        let mut body = Vec::new();
+        prepared.read_to_end(&mut body).unwrap();
+
+        let fields = server::parse_all(&body, &boundary).unwrap();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].1, b"world");
+    }
+
+    #[test]
+    fn test_trailing_crlf() {
+        use std::io::prelude::*;
+
+        fn serialize(multipart: &mut Multipart) -> (String, String) {
+            let mut prepared = multipart.prepare().unwrap();
+            let content_len = prepared.content_len();
+            let boundary = prepared.boundary().to_owned();
+
+            let mut body = String::new();
+            prepared.read_to_string(&mut body).unwrap();
+            assert_eq!(content_len, Some(body.len() as u64));
+
+            (body, boundary)
+        }
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("hello", "world");
+
+        let (body, boundary) = serialize(&mut multipart);
+        assert!(body.ends_with(&format!("world\r\n--{}--", boundary)), "{}", body);
+
+        multipart.trailing_crlf(true).add_text("hello", "world");
+
+        let (body, boundary) = serialize(&mut multipart);
+        assert!(body.ends_with(&format!("world\r\n--{}--\r\n", boundary)), "{}", body);
+
+        multipart.add_text("hello", "world");
+
+        let mut encoder = multipart.encoder().unwrap();
+        let boundary = encoder.boundary().to_owned();
+        let mut body = String::new();
+        encoder.read_to_string(&mut body).unwrap();
+        assert!(body.ends_with(&format!("world\r\n--{}--\r\n", boundary)), "{}", body);
+    }
+
+    #[test]
+    fn test_field_meta() {
+        use std::io::prelude::*;
+
+        #[derive(Debug, PartialEq)]
+        struct Upload {
+            id: u32,
+        }
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("text", "text")
+            .add_stream("plain", &b"plain"[..], None::<&str>, None)
+            .add_stream_with_meta("first", &b"first"[..], Some("first.txt"), None, Upload { id: 1 })
+            .add_stream_with_meta("second", &b"second"[..], None::<&str>, None, "second meta");
+
+        let mut prepared = multipart.prepare().unwrap();
+
+        assert_eq!(prepared.field_meta("first"), Some(&Upload { id: 1 }));
+        assert_eq!(prepared.field_meta("second"), Some(&"second meta"));
+        // wrong type
+        assert_eq!(prepared.field_meta::<Upload>("second"), None);
+        assert_eq!(prepared.field_meta::<()>("plain"), None);
+        assert_eq!(prepared.field_meta::<()>("text"), None);
+
+        // the metadata isn't sent
+        let mut body = String::new();
+        prepared.read_to_string(&mut body).unwrap();
+        assert!(!body.contains("meta"), "{}", body);
+    }
+
+    #[test]
+    fn test_overhead_len() {
+        use std::fs::File;
+        use std::io::prelude::*;
+        use tempdir::TempDir;
+
+        let dir = TempDir::new("multipart-overhead").unwrap();
+        let path = dir.path().join("file.txt");
+        File::create(&path).unwrap().write_all(b"file data").unwrap();
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("text", "some text")
+            .add_text("empty", "")
+            .add_file("file", &path);
+
+        let mut prepared = multipart.prepare().unwrap();
+        let overhead_len = prepared.overhead_len().unwrap();
+        let content_len = prepared.content_len().unwrap();
+
+        assert!(overhead_len > 0);
+        assert_eq!(overhead_len + "some text".len() as u64 + "file data".len() as u64, content_len);
+
+        let mut body = Vec::new();
+        prepared.read_to_end(&mut body).unwrap();
+        assert_eq!(body.len() as u64, content_len);
+
+        // streams have no known length, but their overhead does
+        let mut multipart = Multipart::new();
+        multipart.add_stream("stream", &b"stream data"[..], Some("stream.bin"), None);
+
+        let mut prepared = multipart.prepare().unwrap();
+        assert_eq!(prepared.content_len(), None);
+        let overhead_len = prepared.overhead_len().unwrap();
+
+        let mut body = Vec::new();
+        prepared.read_to_end(&mut body).unwrap();
+        assert_eq!(overhead_len + "stream data".len() as u64, body.len() as u64);
+    }
+
+    #[test]
+    fn test_part_content_length() {
+        use std::fs::File;
+        use std::io::prelude::*;
+        use tempdir::TempDir;
+
+        let tempdir = TempDir::new("multipart_lazy_test").unwrap();
+        let path = tempdir.path().join("file.txt");
+        File::create(&path).unwrap().write_all(b"file contents").unwrap();
+
+        let mut multipart = Multipart::new();
+        multipart.emit_part_content_length(true)
+            .add_text("hello", "world")
+            .add_file("file", &*path);
+
+        let mut prepared = multipart.prepare().unwrap();
+        let content_len = prepared.content_len();
+
+        let mut body = String::new();
+        prepared.read_to_string(&mut body).unwrap();
+
+        assert!(body.contains("name=\"hello\"\r\nContent-Length: 5\r\n\r\nworld"), "{}", body);
+        assert!(body.contains("Content-Length: 13\r\n\r\nfile contents"), "{}", body);
+        assert_eq!(content_len, Some(body.len() as u64));
+    }
+
+    #[test]
+    fn test_part_content_length_stream() {
+        let mut multipart = Multipart::new();
+        multipart.emit_part_content_length(true)
+            .add_stream("stream", &b"stream data"[..], None::<&str>, None);
+
+        let err = match multipart.prepare() {
+            Ok(_) => panic!("expected an error for a stream of unknown length"),
+            Err(err) => err,
+        };
+
+        assert_eq!(err.field_name.as_ref().map(|n| &**n), Some("stream"));
+    }
+
+    #[test]
+    fn test_encoder() {
+        use std::io::prelude::*;
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("hello", "world").add_text("goodbye", "world");
+
+        let encode = |multipart: &Multipart| {
+            let mut encoder = multipart.encoder().unwrap();
+            let boundary = encoder.boundary().to_owned();
+
+            let mut body = String::new();
+            encoder.read_to_string(&mut body).unwrap();
+            body.replace(&boundary, "boundary")
+        };
+
+        let first = encode(&multipart);
+        assert_eq!(first, "\r\n--boundary\r\n\
+                           Content-Disposition: form-data; name=\"hello\"\r\n\r\n\
+                           world\r\n\
+                           --boundary\r\n\
+                           Content-Disposition: form-data; name=\"goodbye\"\r\n\r\n\
+                           world\r\n\
+                           --boundary--");
+
+        assert_eq!(encode(&multipart), first);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_forward_field() {
+        use super::forward_field;
+        use server;
+        use std::io::prelude::*;
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.bin\"\r\n\
+                            Content-Type: application/octet-stream\r\n\r\n\
+                            \x00\x01\x02\r\n\
+                            --boundary--";
+
+        let mut incoming = server::Multipart::with_body(body, "boundary");
+        let field = incoming.read_entry().unwrap().unwrap();
+
+        let mut multipart = Multipart::new();
+        forward_field(field, &mut multipart);
+
+        let mut prepared = multipart.prepare().unwrap();
+        let boundary = prepared.boundary().to_owned();
+
+        let mut relayed = Vec::new();
+        prepared.read_to_end(&mut relayed).unwrap();
+
+        let fields = server::parse_all(&relayed, &boundary).unwrap();
+        assert_eq!(fields.len(), 1);
+
+        let (ref headers, ref data) = fields[0];
+        assert_eq!(&*headers.name, "file");
+        assert_eq!(headers.filename.as_ref().unwrap(), "file.bin");
+        assert_eq!(headers.content_type, Some(::mime::APPLICATION_OCTET_STREAM));
+        assert_eq!(data, b"\x00\x01\x02");
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_empty_fields() {
+        use server;
+        use std::io::prelude::*;
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("empty_text", "")
+            .add_text("text", "text")
+            .add_stream("empty_file", &b""[..], Some("empty.bin"), None)
+            .add_text("last", "");
+
+        let mut prepared = multipart.prepare().unwrap();
+        let boundary = prepared.boundary().to_owned();
+
+        let mut body = Vec::new();
+        prepared.read_to_end(&mut body).unwrap();
+
+        let fields = server::parse_all(&body, &boundary).unwrap();
+        let fields: Vec<_> = fields.iter().map(|&(ref headers, ref data)| (&headers.name[..], &data[..]))
+            .collect();
+
+        let expected: [(&str, &[u8]); 4] = [("empty_text", b""), ("text", b"text"), ("empty_file", b""),
+                                            ("last", b"")];
+        assert_eq!(fields, expected);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_escaped_content_disp() {
+        use server;
+        use std::io::prelude::*;
+
+        let names = [r#"quoted "name" \ backslash"#, "na\u{EF}ve"];
+        let filenames = [r#"a "quoted"; file\name.txt"#, "\u{6587}\u{4EF6} r\u{E9}sum\u{E9}.pdf"];
+
+        let mut multipart = Multipart::new();
+
+        for (name, filename) in names.iter().zip(filenames.iter()) {
+            multipart.add_stream(*name, &b"data"[..], Some(*filename), None);
+        }
+
+        let mut prepared = multipart.prepare().unwrap();
+        let boundary = prepared.boundary().to_owned();
+
+        let mut body = Vec::new();
+        prepared.read_to_end(&mut body).unwrap();
+
+        let body_str = String::from_utf8_lossy(&body);
+        assert!(body_str.contains(r#"name="quoted \"name\" \\ backslash""#), "{}", body_str);
+        assert!(body_str.contains("filename*=UTF-8''%E6%96%87%E4%BB%B6%20r%C3%A9sum%C3%A9.pdf"),
+                "{}", body_str);
+
+        let fields = server::parse_all(&body, &boundary).unwrap();
+        assert_eq!(fields.len(), 2);
+
+        for (&(ref headers, ref data), (name, filename)) in fields.iter().zip(names.iter().zip(filenames.iter())) {
+            assert_eq!(&*headers.name, *name);
+            assert_eq!(headers.filename.as_ref().unwrap(), filename);
+            assert_eq!(data, b"data");
+        }
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_extended_filenames() {
+        use server;
+        use std::io::prelude::*;
+
+        let filename = "party \u{1F389}.txt";
+
+        for &extended in &[true, false] {
+            let mut multipart = Multipart::new();
+            multipart.extended_filenames(extended)
+                .add_stream("file", &b"data"[..], Some(filename), None);
+
+            let mut prepared = multipart.prepare().unwrap();
+            let boundary = prepared.boundary().to_owned();
+
+            let mut body = Vec::new();
+            prepared.read_to_end(&mut body).unwrap();
+
+            let body_str = String::from_utf8_lossy(&body);
+            assert_eq!(body_str.contains("filename*=UTF-8''party%20%F0%9F%8E%89.txt"), extended,
+                       "{}", body_str);
+
+            let fields = server::parse_all(&body, &boundary).unwrap();
+            assert_eq!(fields[0].0.filename.as_ref().unwrap(), filename);
+        }
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_extend_from_server() {
+        use server;
+        use std::io::prelude::*;
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"relayed_text\"\r\n\r\n\
+                            relayed text\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"relayed_file\"; filename=\"file.bin\"\r\n\
+                            Content-Type: application/octet-stream\r\n\r\n\
+                            relayed file\r\n\
+                            --boundary--";
+
+        let mut incoming = server::Multipart::with_body(body, "boundary");
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("local_text", "local text");
+        multipart.extend_from_server(&mut incoming);
+        multipart.add_stream("local_file", &b"local file"[..], Some("local.txt"), None);
+
+        let mut prepared = multipart.prepare().unwrap();
+        let boundary = prepared.boundary().to_owned();
+        assert_eq!(prepared.content_len(), None);
+        assert_eq!(prepared.overhead_len(), None);
+
+        let mut out = Vec::new();
+        prepared.read_to_end(&mut out).unwrap();
+
+        let mut fields: Vec<_> = server::parse_all(&out, &boundary).unwrap().into_iter()
+            .map(|(headers, data)| (headers.name.to_string(), headers.filename,
+                                    String::from_utf8(data).unwrap()))
+            .collect();
+
+        fields.sort();
+
+        assert_eq!(fields, vec![
+            ("local_file".to_string(), Some("local.txt".to_string()), "local file".to_string()),
+            ("local_text".to_string(), None, "local text".to_string()),
+            ("relayed_file".to_string(), Some("file.bin".to_string()), "relayed file".to_string()),
+            ("relayed_text".to_string(), None, "relayed text".to_string()),
+        ]);
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_stream_with_disposition() {
+        use server::{self, parse_content_disposition};
+        use std::io::prelude::*;
+
+        fn encode(disposition: Option<&str>, filename: Option<&str>) -> (Vec<u8>, String) {
+            let mut multipart = Multipart::new();
+            multipart.add_stream_with_disposition(disposition, "file", &b"attached"[..], filename,
+                                                  None);
+
+            let mut prepared = multipart.prepare().unwrap();
+            let mut body = Vec::new();
+            prepared.read_to_end(&mut body).unwrap();
+            (body, prepared.boundary().to_owned())
+        }
+
+        let (body, _) = encode(None, None);
+        let body_str = String::from_utf8_lossy(&body);
+        assert!(body_str.contains("\r\nContent-Disposition: name=\"file\"\r\n"), "{}", body_str);
+
+        let (body, boundary) = encode(Some("attachment"), Some("file.txt"));
+        let mut multipart = server::Multipart::with_body(&body[..], boundary).with_nested_mixed();
+        let mut field = multipart.read_entry().unwrap().unwrap();
+
+        let disp = {
+            let &(_, ref value) = field.headers.raw_headers().iter()
+                .find(|&&(ref name, _)| name == "Content-Disposition")
+                .unwrap();
+            parse_content_disposition(value).unwrap()
+        };
+
+        assert_eq!(disp.disposition_type, "attachment");
+        assert_eq!(disp.name.as_ref().unwrap(), "file");
+        assert_eq!(field.headers.filename.as_ref().unwrap(), "file.txt");
+
+        let mut data = String::new();
+        field.data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "attached");
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_file_group() {
+        use server::{self, boundary_from_content_type};
+        use std::io::prelude::*;
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("text", "text")
+            .add_file_group("files", vec![(&b"first file"[..], "first.txt", None),
+                                          (&b"second file"[..], "second.bin",
+                                           Some(::mime::APPLICATION_OCTET_STREAM))]);
+
+        let mut prepared = multipart.prepare().unwrap();
+        assert_eq!(prepared.content_len(), None);
+
+        let boundary = prepared.boundary().to_owned();
+
+        let mut body = Vec::new();
+        prepared.read_to_end(&mut body).unwrap();
+
+        let mut multipart = server::Multipart::with_body(&body[..], boundary);
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(&*field.headers.name, "text");
+            assert_eq!(field.read_text().unwrap(), "text");
+        }
+
+        {
+            let field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(&*field.headers.name, "files");
+
+            let content_type = field.headers.content_type.as_ref().unwrap().to_string();
+            assert!(content_type.starts_with("multipart/mixed"), "{}", content_type);
+
+            let nested_boundary = boundary_from_content_type(&content_type).unwrap();
+            let mut nested = server::Multipart::with_body(field.data, nested_boundary)
+                .with_nested_mixed();
+
+            let expected = [("first.txt", ::mime::TEXT_PLAIN, "first file"),
+                            ("second.bin", ::mime::APPLICATION_OCTET_STREAM, "second file")];
+
+            for &(filename, ref content_type, data) in &expected {
+                let mut file = nested.read_entry().unwrap().unwrap();
+                assert_eq!(&*file.headers.name, "");
+                assert_eq!(file.headers.filename.as_ref().unwrap(), filename);
+                assert_eq!(file.headers.content_type.as_ref(), Some(content_type));
+                assert_eq!(file.read_text().unwrap(), data);
+            }
+
+            assert!(nested.read_entry().unwrap().is_none());
+        }
+
+        assert!(multipart.read_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_prepare_cloned() {
+        use super::PreparedFields;
+        use std::fs::File;
+        use std::io::prelude::*;
+        use tempdir::TempDir;
+
+        fn read_normalized(mut prepared: PreparedFields) -> String {
+            let boundary = prepared.boundary().to_owned();
+
+            let mut body = String::new();
+            prepared.read_to_string(&mut body).unwrap();
+            body.replace(&boundary, "boundary")
+        }
+
+        let tempdir = TempDir::new("multipart_lazy_test").unwrap();
+        let path = tempdir.path().join("file.txt");
+        File::create(&path).unwrap().write_all(b"file data").unwrap();
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("hello", "world")
+            .add_file("file", &*path);
+
+        let first = read_normalized(multipart.prepare_cloned().unwrap());
+        let second = read_normalized(multipart.prepare_cloned().unwrap());
+
+        assert!(first.contains("file data"), "{}", first);
+        assert_eq!(first, second);
+        assert_eq!(first, read_normalized(multipart.prepare().unwrap()));
+
+        multipart.add_text("hello", "world")
+            .add_stream("stream", &b"stream data"[..], Some("file.bin"), None);
+
+        match multipart.prepare_cloned() {
+            Err(err) => {
+                assert_eq!(err.field_name.as_ref().unwrap(), "stream");
+                assert_eq!(err.error.kind(), io::ErrorKind::InvalidInput);
+            },
+            Ok(_) => panic!("expected an error for a stream field"),
+        }
+    }
+
+    #[test]
+    fn test_prepare_fixed_size() {
+        use std::io::Read;
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("hello", "world")
+            .add_text("goodbye", "world");
+
+        let mut prepared = multipart.prepare_fixed_size().unwrap();
+        let content_len = prepared.content_len().unwrap();
+
+        let mut body = Vec::new();
+        prepared.read_to_end(&mut body).unwrap();
+        assert_eq!(body.len() as u64, content_len);
+
+        multipart.add_text("hello", "world")
+            .add_stream("stream", &b"stream data"[..], None as Option<&str>, None);
+
+        match multipart.prepare_fixed_size() {
+            Err(err) => {
+                assert_eq!(err.field_name.as_ref().unwrap(), "stream");
+                assert_eq!(err.error.kind(), io::ErrorKind::InvalidInput);
+            },
+            Ok(_) => panic!("expected an error for a stream field"),
+        }
+    }
+
+    #[test]
+    fn test_prepare_rewindable() {
+        use super::{RewindableBody, RewindableFields};
+        use std::io::prelude::*;
+
+        fn read_twice(rewindable: &mut RewindableFields) -> Vec<u8> {
+            let mut first = Vec::new();
+            rewindable.read_to_end(&mut first).unwrap();
+
+            rewindable.rewind().unwrap();
+
+            let mut second = Vec::new();
+            rewindable.read_to_end(&mut second).unwrap();
+            assert_eq!(first, second);
+
+            first
+        }
+
+        let mut multipart = Multipart::new();
+        multipart.add_text("hello", "world");
+
+        let mut rewindable = multipart.prepare_rewindable_with_threshold(1024).unwrap();
+        assert!(rewindable.is_in_memory());
+
+        let body = read_twice(&mut rewindable);
+        assert_eq!(body.len() as u64, rewindable.content_len());
+        assert!(String::from_utf8(body).unwrap().contains(rewindable.boundary()));
+
+        let data = vec![b'a'; 4096];
+
+        let mut multipart = Multipart::new();
+        multipart.add_stream("stream", &data[..], Some("data.txt"), None);
+
+        let mut rewindable = multipart.prepare_rewindable_with_threshold(1024).unwrap();
+        assert!(!rewindable.is_in_memory());
+
+        let body = read_twice(&mut rewindable);
+        assert_eq!(body.len() as u64, rewindable.content_len());
+
+        // the temporary file is deleted with the body
+        let dir = match rewindable.body {
+            RewindableBody::File(_, ref dir) => dir.path().to_owned(),
+            RewindableBody::Memory(_) => unreachable!(),
+        };
+
+        assert!(dir.exists());
+        drop(rewindable);
+        assert!(!dir.exists());
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn test_default_file_mime() {
+        use server;
+        use std::fs::File;
+        use std::io::prelude::*;
+        use tempdir::TempDir;
+
+        let tempdir = TempDir::new("multipart_lazy_test").unwrap();
+        let unknown = tempdir.path().join("file.unknownext");
+        let known = tempdir.path().join("file.txt");
+        File::create(&unknown).unwrap().write_all(b"unknown").unwrap();
+        File::create(&known).unwrap().write_all(b"known").unwrap();
+
+        let mut multipart = Multipart::new();
+        multipart.add_stream("default", &b"default"[..], Some("default.bin"), None)
+            .add_stream("explicit", &b"explicit"[..], Some("explicit.bin"),
+                        Some(::mime::IMAGE_PNG))
+            .add_file("unknown", &*unknown)
+            .add_file("known", &*known)
+            .set_default_file_mime(::mime::APPLICATION_JSON);
+
+        let mut prepared = multipart.prepare().unwrap();
+        let boundary = prepared.boundary().to_owned();
+
+        let mut body = Vec::new();
+        prepared.read_to_end(&mut body).unwrap();
+
+        let fields = server::parse_all(&body, &boundary).unwrap();
+        assert_eq!(fields.len(), 4);
+
+        let content_type = |name: &str| fields.iter().find(|&&(ref headers, _)| &*headers.name == name)
+            .and_then(|&(ref headers, _)| headers.content_type.clone()).unwrap();
+
+        assert_eq!(content_type("default"), ::mime::APPLICATION_JSON);
+        assert_eq!(content_type("explicit"), ::mime::IMAGE_PNG);
+        assert_eq!(content_type("unknown"), ::mime::APPLICATION_JSON);
+        assert_eq!(content_type("known"), ::mime::TEXT_PLAIN);
+    }
+}
+
+#[cfg(all(test, feature = "bench"))]
+mod bench {
+    extern crate test;
+    use self::test::Bencher;
+
+    use super::Multipart;
+
+    const FIELD_COUNT: usize = 64;
+    const FIELD_LEN: usize = 64 * 1024;
+
+    fn large_form(skip_check: bool) -> Multipart<'static, 'static> {
+        let mut multipart = Multipart::new();
+        multipart.skip_boundary_collision_check(skip_check);
+
+        for i in 0..FIELD_COUNT {
+            multipart.add_text(format!("field{}", i), "a".repeat(FIELD_LEN));
+        }
+
+        multipart
+    }
+
+    #[bench]
+    fn bench_boundary_collision_check(b: &mut Bencher) {
+        let multipart = large_form(false);
+        b.iter(|| multipart.gen_boundary());
+    }
+
+    #[bench]
+    fn bench_boundary_collision_check_skipped(b: &mut Bencher) {
+        let multipart = large_form(true);
+        b.iter(|| multipart.gen_boundary());
+    }
+}
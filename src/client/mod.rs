@@ -10,6 +10,7 @@
 use mime::Mime;
 
 use std::borrow::Cow;
+use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
@@ -19,6 +20,9 @@ use std::path::Path;
 #[cfg(feature = "hyper")]
 pub mod hyper;
 
+#[cfg(feature = "hyper1")]
+pub mod hyper1;
+
 pub mod lazy;
 
 mod sized;
@@ -39,7 +43,8 @@ macro_rules! map_self {
 /// The entry point of the client-side multipart API.
 ///
 /// Though they perform I/O, the `.write_*()` methods do not return `io::Result<_>` in order to
-/// facilitate method chaining. Upon the first error, all subsequent API calls will be no-ops until
+/// facilitate method chaining (except for `.write_stream()`, which returns the number of bytes
+/// it wrote). Upon the first error, all subsequent API calls will be no-ops until
 /// `.send()` is called, at which point the error will be reported.
 pub struct Multipart<S> {
     writer: MultipartWriter<'static, S>,
@@ -57,6 +62,32 @@ impl Multipart<()> {
             writer: MultipartWriter::new(stream, boundary),
         })
     }
+
+    /// Create a new `Multipart` which writes the request body directly into `dest`, e.g. a
+    /// socket, as each field is written, so the body is never held in memory.
+    ///
+    /// No headers are written; use `boundary()` to set the `Content-Type` of the request.
+    /// `send()` returns the number of bytes written.
+    pub fn from_writer<W: Write>(dest: W) -> Multipart<WriterStream<W>> {
+        let stream = WriterStream {
+            inner: dest,
+            written: 0,
+        };
+
+        Multipart {
+            writer: MultipartWriter::new(stream, gen_boundary()),
+        }
+    }
+
+    /// Like `from_writer()`, but also copy everything written to `dest` to `log`, e.g. to
+    /// record outgoing requests for debugging.
+    ///
+    /// The first error from either `dest` or `log` is returned by the `write_*()` call which
+    /// hit it, or by `send()`.
+    pub fn from_writer_tee<W: Write, L: Write>(dest: W, log: L)
+                                               -> Multipart<WriterStream<Tee<W, L>>> {
+        Multipart::from_writer(Tee::new(dest, log))
+    }
 }
 
 impl<S: HttpStream> Multipart<S> { 
@@ -104,21 +135,29 @@ impl<S: HttpStream> Multipart<S> {
     /// Use `Read::take()` if you wish to send data from a `Read` 
     /// that will never return EOF otherwise.
     ///
+    /// Returns the number of bytes of field data read from `stream` and written, not counting
+    /// the field headers, for tracking progress.
+    ///
     /// ## Errors
     /// If the reader returned an error, or if something went wrong with the HTTP stream.
     // RFC: How to format this declaration?
     pub fn write_stream<N: AsRef<str>, St: Read>(
         &mut self, name: N, stream: &mut St, filename: Option<&str>, content_type: Option<Mime>
-    ) -> Result<&mut Self, S::Error> {
+    ) -> Result<u64, S::Error> {
         let name = name.as_ref();
 
-        map_self!(self, self.writer.write_stream(stream, name, filename, content_type))
+        self.writer.write_stream(stream, name, filename, content_type).map_err(Into::into)
     } 
 
     /// Finalize the request and return the response from the server, or the last error if set.
     pub fn send(self) -> Result<S::Response, S::Error> {
         self.writer.finish().map_err(io::Error::into).and_then(|body| body.finish())
     }    
+
+    /// Get the boundary used to separate the fields of this request.
+    pub fn boundary(&self) -> &str {
+        &self.writer.boundary
+    }
 }
 
 impl<R: HttpRequest> Multipart<SizedRequest<R>>
@@ -163,6 +202,75 @@ pub trait HttpStream: Write {
     fn finish(self) -> Result<Self::Response, Self::Error>;
 }
 
+/// An `HttpStream` which writes the request body into any writer, returned by
+/// `Multipart::from_writer()`.
+///
+/// `finish()` flushes the writer and returns the number of bytes written to it.
+#[derive(Debug)]
+pub struct WriterStream<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for WriterStream<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> HttpStream for WriterStream<W> {
+    type Request = ();
+    type Response = u64;
+    type Error = io::Error;
+
+    fn finish(mut self) -> io::Result<u64> {
+        self.inner.flush()?;
+        Ok(self.written)
+    }
+}
+
+/// A writer which writes everything written to `dest` to `log` as well, returning the first
+/// error from either.
+#[derive(Debug)]
+pub struct Tee<W, L> {
+    dest: W,
+    log: L,
+}
+
+impl<W: Write, L: Write> Tee<W, L> {
+    /// Write to both `dest` and `log`.
+    pub fn new(dest: W, log: L) -> Self {
+        Tee {
+            dest: dest,
+            log: log,
+        }
+    }
+
+    /// Unwrap the destination and the log.
+    pub fn into_inner(self) -> (W, L) {
+        (self.dest, self.log)
+    }
+}
+
+impl<W: Write, L: Write> Write for Tee<W, L> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.dest.write(buf)?;
+        self.log.write_all(&buf[..written])?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.dest.flush()?;
+        self.log.flush()
+    }
+}
+
 impl HttpRequest for () {
     type Stream = io::Sink;
     type Error = io::Error;
@@ -183,6 +291,23 @@ fn gen_boundary() -> String {
     ::random_alphanumeric(BOUNDARY_LEN)
 }
 
+/// Quote `boundary` for use as the value of the `boundary` parameter in a `Content-Type` header,
+/// if necessary.
+///
+/// Boundaries generated by this crate never need quoting, but [IETF RFC 2046, Section 5.1.1][rfc]
+/// allows some characters in a boundary which are not valid in an unquoted parameter value.
+///
+/// [rfc]: https://tools.ietf.org/html/rfc2046#section-5.1.1
+pub fn quote_boundary(boundary: &str) -> Cow<str> {
+    const TSPECIALS: &'static str = "()<>@,;:\\\"/[]?= ";
+
+    if boundary.is_empty() || boundary.chars().any(|c| TSPECIALS.contains(c)) {
+        format!("\"{}\"", boundary).into()
+    } else {
+        boundary.into()
+    }
+}
+
 fn open_stream<R: HttpRequest>(mut req: R, content_len: Option<u64>) -> Result<(String, R::Stream), R::Error> {
     let boundary = gen_boundary();
     req.apply_headers(&boundary, content_len);
@@ -222,17 +347,16 @@ impl<'a, W: Write> MultipartWriter<'a, W> {
     fn write_file(&mut self, name: &str, path: &Path) -> io::Result<()> {
         let (content_type, filename) = mime_filename(path);
         let mut file = File::open(path)?;
-        self.write_stream(&mut file, name, filename, Some(content_type))
+        self.write_stream(&mut file, name, filename, Some(content_type)).map(|_| ())
     }
 
-    fn write_stream<S: Read>(&mut self, stream: &mut S, name: &str, filename: Option<&str>, content_type: Option<Mime>) -> io::Result<()> {
+    fn write_stream<S: Read>(&mut self, stream: &mut S, name: &str, filename: Option<&str>, content_type: Option<Mime>) -> io::Result<u64> {
         // This is necessary to make sure it is interpreted as a file on the server end.
         let content_type = Some(content_type.unwrap_or_else(::mime_guess::octet_stream));
 
         chain_result! {
             self.write_field_headers(name, filename, content_type),
-            io::copy(stream, &mut self.inner),
-            Ok(()) 
+            io::copy(stream, &mut self.inner)
         }
     }
 
@@ -242,9 +366,7 @@ impl<'a, W: Write> MultipartWriter<'a, W> {
             // Write the first boundary, or the boundary for the previous field.
             self.write_boundary(),
             { self.data_written = true; Ok(()) },
-            write!(self.inner, "Content-Disposition: form-data; name=\"{}\"", name),
-            filename.map(|filename| write!(self.inner, "; filename=\"{}\"", filename))
-                .unwrap_or(Ok(())),
+            write_content_disp(&mut self.inner, name, filename, true),
             content_type.map(|content_type| write!(self.inner, "\r\nContent-Type: {}", content_type))
                 .unwrap_or(Ok(())),
             self.inner.write_all(b"\r\n\r\n")
@@ -265,6 +387,80 @@ impl<'a, W: Write> MultipartWriter<'a, W> {
     }
 }
 
+/// Write the `Content-Disposition` header of a field, without the trailing CRLF.
+///
+/// `name` and `filename` are quoted with any `"` or `\` escaped by a backslash. If `extended`
+/// is set, a non-ASCII `filename` is additionally written as an [RFC 5987][rfc5987]
+/// `filename*` parameter.
+///
+/// [rfc5987]: https://tools.ietf.org/html/rfc5987
+fn write_content_disp<W: Write>(out: W, name: &str, filename: Option<&str>, extended: bool)
+                                -> io::Result<()> {
+    write_content_disp_type(out, Some("form-data"), name, filename, extended)
+}
+
+/// Write a `Content-Disposition` header with the given disposition type, or only its parameters
+/// if it is `None`.
+fn write_content_disp_type<W: Write>(mut out: W, disposition: Option<&str>, name: &str,
+                                     filename: Option<&str>, extended: bool) -> io::Result<()> {
+    match disposition {
+        Some(disposition) => write!(out, "Content-Disposition: {}; name=\"{}\"", disposition,
+                                    Quoted(name))?,
+        None => write!(out, "Content-Disposition: name=\"{}\"", Quoted(name))?,
+    }
+
+    if let Some(filename) = filename {
+        write_filename(out, filename, extended)?;
+    }
+
+    Ok(())
+}
+
+/// Write the `filename` parameter, and `filename*` if `extended` is set and it is needed.
+fn write_filename<W: Write>(mut out: W, filename: &str, extended: bool) -> io::Result<()> {
+    write!(out, "; filename=\"{}\"", Quoted(filename))?;
+
+    if extended && filename.bytes().any(|b| b >= 0x80) {
+        write!(out, "; filename*=UTF-8''{}", PercentEncoded(filename))?;
+    }
+
+    Ok(())
+}
+
+/// Escapes `"` and `\` for a quoted header parameter; line breaks are replaced with spaces.
+struct Quoted<'a>(&'a str);
+
+impl<'a> fmt::Display for Quoted<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '"' | '\\' => write!(f, "\\{}", c)?,
+                '\r' | '\n' => f.write_str(" ")?,
+                _ => write!(f, "{}", c)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Percent-encodes all bytes which are not `attr-char` in RFC 5987.
+struct PercentEncoded<'a>(&'a str);
+
+impl<'a> fmt::Display for PercentEncoded<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &b in self.0.as_bytes() {
+            match b {
+                b'a' ... b'z' | b'A' ... b'Z' | b'0' ... b'9' | b'!' | b'#' | b'$' | b'&' | b'+'
+                | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => write!(f, "{}", b as char)?,
+                _ => write!(f, "%{:02X}", b)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
 fn mime_filename(path: &Path) -> (Mime, Option<&str>) {
     let content_type = ::mime_guess::guess_mime_type(path);
     let filename = opt_filename(path);
@@ -274,3 +470,74 @@ fn mime_filename(path: &Path) -> (Mime, Option<&str>) {
 fn opt_filename(path: &Path) -> Option<&str> {
     path.file_name().and_then(|filename| filename.to_str())
 }
+
+#[cfg(test)]
+mod test {
+    use super::Multipart;
+
+    use mock::ClientRequest;
+
+    use std::io::{self, Write};
+
+    #[test]
+    fn test_from_writer() {
+        let mut written = Vec::new();
+
+        let (len, boundary) = {
+            let mut multipart = Multipart::from_writer(&mut written);
+            multipart.write_text("hello", "world").unwrap()
+                .write_stream("stream", &mut &b"stream data"[..], Some("file.bin"), None).unwrap();
+
+            let boundary = multipart.boundary().to_owned();
+            (multipart.send().unwrap(), boundary)
+        };
+
+        assert_eq!(len, written.len() as u64);
+
+        let mut multipart = Multipart::from_request(ClientRequest::default()).unwrap();
+        multipart.write_text("hello", "world").unwrap()
+            .write_stream("stream", &mut &b"stream data"[..], Some("file.bin"), None).unwrap();
+
+        let sent = multipart.send().unwrap();
+
+        // each request generates its own boundary
+        let written = String::from_utf8(written).unwrap().replace(&boundary, &sent.boundary);
+        assert_eq!(written.as_bytes(), &*sent.buf);
+    }
+
+    #[test]
+    fn test_from_writer_tee() {
+        struct BrokenPipe;
+
+        impl Write for BrokenPipe {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let (mut dest, mut log) = (Vec::new(), Vec::new());
+
+        let len = {
+            let mut multipart = Multipart::from_writer_tee(&mut dest, &mut log);
+            multipart.write_text("hello", "world").unwrap()
+                .write_stream("stream", &mut &b"stream data"[..], Some("file.bin"), None).unwrap();
+            multipart.send().unwrap()
+        };
+
+        assert_eq!(len, dest.len() as u64);
+        assert_eq!(dest, log);
+
+        // errors from either sink are returned
+        let err = Multipart::from_writer_tee(Vec::new(), BrokenPipe).write_text("hello", "world")
+            .err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+        let err = Multipart::from_writer_tee(BrokenPipe, Vec::new()).write_text("hello", "world")
+            .err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -52,6 +52,34 @@ extern crate mime_guess;
 extern crate rand;
 extern crate tempdir;
 
+#[cfg(feature = "digest")]
+extern crate digest;
+
+#[cfg(feature = "bytes")]
+extern crate bytes;
+
+#[cfg(feature = "async-bridge")]
+extern crate futures;
+
+#[cfg(all(test, feature = "digest"))]
+extern crate sha2;
+
+#[cfg(all(test, feature = "server"))]
+extern crate flate2;
+
+#[cfg(feature = "memmap2")]
+extern crate memmap2;
+
+#[cfg(feature = "serde")]
+extern crate serde;
+
+#[cfg(feature = "json")]
+extern crate serde_json;
+
+#[cfg(all(test, feature = "json"))]
+#[macro_use]
+extern crate serde_derive;
+
 #[cfg(feature = "quick-error")]
 #[macro_use]
 extern crate quick_error;
@@ -62,6 +90,12 @@ extern crate safemem;
 #[cfg(feature = "hyper")]
 extern crate hyper;
 
+#[cfg(feature = "hyper1")]
+extern crate hyper_1;
+
+#[cfg(feature = "hyper1")]
+extern crate bytes_1;
+
 #[cfg(feature = "iron")]
 extern crate iron;
 
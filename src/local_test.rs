@@ -338,8 +338,9 @@ fn test_client(test_fields: &TestFields) -> HttpBuffer {
     // Intersperse file fields amongst text fields
     for (name, text) in test_texts {
         if let Some((file_name, file)) = test_files.next() {
-            multipart.write_stream(file_name, &mut &*file.data.0, file.filename(),
-                                   Some(file.content_type.clone())).unwrap();
+            let written = multipart.write_stream(file_name, &mut &*file.data.0, file.filename(),
+                                                 Some(file.content_type.clone())).unwrap();
+            assert_eq!(written, file.data.0.len() as u64);
         }
 
         multipart.write_text(name, text).unwrap();    
@@ -347,8 +348,9 @@ fn test_client(test_fields: &TestFields) -> HttpBuffer {
 
     // Write remaining files
     for (file_name, file) in test_files {
-        multipart.write_stream(file_name, &mut &*file.data.0, file.filename(),
-                               Some(file.content_type.clone())).unwrap();
+        let written = multipart.write_stream(file_name, &mut &*file.data.0, file.filename(),
+                                             Some(file.content_type.clone())).unwrap();
+        assert_eq!(written, file.data.0.len() as u64);
     }
 
     multipart.send().unwrap()
@@ -6,9 +6,11 @@
 // copied, modified, or distributed except according to those terms.
 //! Mocked types for client-side and server-side APIs.
 use std::cell::{Cell, RefCell};
-use std::io::{self, Read, Write};
-use std::sync::{Once, ONCE_INIT};
-use std::{fmt, ptr, thread};
+use std::io::{self, Cursor, Read, Write};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+use std::time::{Duration, Instant};
+use std::{cmp, fmt, ptr, thread};
 
 use log::{Metadata, Record};
 
@@ -80,6 +82,7 @@ impl HttpBuffer {
             data: &self.buf,
             boundary: &self.boundary,
             content_len: self.content_len,
+            has_content_type: true,
             rng: rand::thread_rng(),
         }
     }
@@ -135,6 +138,7 @@ pub struct ServerRequest<'a> {
     pub boundary: &'a str,
     /// The value of the content-length header, if set.
     pub content_len: Option<u64>,
+    has_content_type: bool,
     rng: ThreadRng,
 }
 
@@ -147,9 +151,15 @@ impl<'a> ServerRequest<'a> {
             data: data,
             boundary: boundary,
             content_len: None,
+            has_content_type: true,
             rng: rand::thread_rng(),
         }
     }
+
+    /// Simulate a request which is missing the `Content-Type` header, so `boundary` is ignored.
+    pub fn without_content_type(self) -> Self {
+        ServerRequest { has_content_type: false, .. self }
+    }
 }
 
 impl<'a> Read for ServerRequest<'a> {
@@ -171,13 +181,160 @@ impl<'a> Read for ServerRequest<'a> {
 impl<'a> ::server::HttpRequest for ServerRequest<'a> {
     type Body = Self;
 
-    fn multipart_boundary(&self) -> Option<&str> { Some(self.boundary) }
+    fn multipart_boundary(&self) -> Option<&str> {
+        if self.has_content_type { Some(self.boundary) } else { None }
+    }
+
+    fn has_content_type(&self) -> bool {
+        self.has_content_type
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.content_len
+    }
 
     fn body(self) -> Self::Body {
         self
     }
 }
 
+/// A `Read` adapter which pulls chunks of bytes from a channel, for bridging a producer thread
+/// into the blocking server API, e.g. with `server::Multipart::with_body()`.
+///
+/// Blocks until more data is sent; returns EOF once the channel is closed (all senders dropped)
+/// and the last chunk has been read.
+pub struct ChannelReader {
+    rx: Receiver<Vec<u8>>,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl ChannelReader {
+    /// Wrap the given receiver.
+    pub fn new(rx: Receiver<Vec<u8>>) -> Self {
+        ChannelReader {
+            rx: rx,
+            chunk: Cursor::new(Vec::new()),
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() {
+            debug!("ChannelReader::read() was passed a zero-sized buffer.");
+            return Ok(0);
+        }
+
+        loop {
+            match self.chunk.read(out)? {
+                0 => (),
+                read => return Ok(read),
+            }
+
+            // skips empty chunks
+            match self.rx.recv() {
+                Ok(chunk) => self.chunk = Cursor::new(chunk),
+                Err(_) => return Ok(0),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ChannelReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("multipart::mock::ChannelReader")
+            .field("chunk", &self.chunk)
+            .finish()
+    }
+}
+
+/// A mock clock which only moves forward when `advance()` is called, so time-based limits
+/// can be tested deterministically.
+///
+/// Clones share the same time. `server::timeout::Clock` impl requires the `server` feature.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl MockClock {
+    /// Create a new clock starting at the current time.
+    pub fn new() -> Self {
+        MockClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Move the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(feature = "server")]
+impl ::server::timeout::Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// A `Read` adapter which yields at most `bytes_per_interval` bytes of the inner reader per
+/// `interval` of a `MockClock`, to simulate a slow upload deterministically.
+///
+/// Instead of sleeping, a read which would exceed the budget of the current interval advances
+/// the clock to the next one first, so wrapping this in e.g. a `server::timeout::TimeoutReader`
+/// with the same clock sees each such read take `interval`.
+#[derive(Debug)]
+pub struct RateLimitedReader<R> {
+    inner: R,
+    clock: MockClock,
+    bytes_per_interval: usize,
+    interval: Duration,
+    /// The bytes left to read in the current interval.
+    remaining: usize,
+}
+
+impl<R: Read> RateLimitedReader<R> {
+    /// Wrap `inner`, reading at most `bytes_per_interval` bytes per `interval` of `clock`.
+    ///
+    /// ## Panics
+    /// If `bytes_per_interval` is zero.
+    pub fn new(inner: R, bytes_per_interval: usize, interval: Duration, clock: MockClock) -> Self {
+        assert!(bytes_per_interval > 0, "`bytes_per_interval` must be nonzero");
+
+        RateLimitedReader {
+            inner: inner,
+            clock: clock,
+            bytes_per_interval: bytes_per_interval,
+            interval: interval,
+            remaining: bytes_per_interval,
+        }
+    }
+
+    /// Unwrap the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for RateLimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            self.clock.advance(self.interval);
+            self.remaining = self.bytes_per_interval;
+        }
+
+        let len = cmp::min(buf.len(), self.remaining);
+        let read = self.inner.read(&mut buf[..len])?;
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
 /// A `Write` adapter that duplicates all data written to the inner writer as well as stdout.
 pub struct StdoutTee<'s, W> {
     inner: W,
@@ -12,9 +12,13 @@ use ::safemem;
 use super::buf_redux;
 use super::buf_redux::strategy::{LessThan, AtEndLessThan};
 use super::twoway;
+use super::timeout::{Clock, DeadlineExceeded, SystemClock, TimeoutReader};
+use super::{BoundaryNotFound, FieldTooLarge, LimitExceeded};
 
 use std::cmp;
 use std::borrow::Borrow;
+use std::fmt;
+use std::time::{Duration, Instant};
 
 use std::io;
 use std::io::prelude::*;
@@ -35,10 +39,106 @@ enum State {
 /// A struct implementing `Read` and `BufRead` that will yield bytes until it sees a given sequence.
 #[derive(Debug)]
 pub struct BoundaryReader<R> {
-    source: BufReader<R>,
+    source: BufReader<TimeoutReader<R, ReadClock>>,
     boundary: Vec<u8>,
     search_idx: usize,
     state: State,
+    /// The last two bytes consumed from `source`, so the CRLF preceding a boundary can be
+    /// verified even if it was already consumed. Starts as CRLF since the first boundary
+    /// doesn't need one.
+    last_consumed: [u8; 2],
+    /// The total number of bytes consumed from `source`.
+    consumed: u64,
+    lenient_final: bool,
+    /// Also accept a bare LF instead of CRLF before and after a boundary.
+    lenient_line_endings: bool,
+    /// Treat the end of the source as the end of the body, even without a closing boundary.
+    allow_unterminated: bool,
+    /// Also accept the boundary without its leading `--` as a delimiter.
+    flexible_delimiter: bool,
+    /// The length of the delimiter found at `search_idx`, which is shorter than `boundary`
+    /// for a delimiter without the leading `--`.
+    delim_len: usize,
+    /// The number of bytes within which the first boundary must start, if set.
+    preamble_limit: Option<u64>,
+    /// Whether a boundary has been consumed yet.
+    boundary_seen: bool,
+    /// The maximum number of bytes of the current field's data which may be read, and the error
+    /// to return beyond that.
+    data_limit: Option<(u64, DataLimit)>,
+    /// The number of bytes of field data read while `data_limit` was set.
+    data_read: u64,
+    deadline: Option<Deadline>,
+}
+
+/// The limit which applies to the data of the current field, to report when it is exceeded.
+#[derive(Clone, Debug)]
+pub enum DataLimit {
+    /// One of the `Limits` on all fields.
+    Total(LimitExceeded),
+    /// The limit for the field's content type.
+    ContentType(FieldTooLarge),
+}
+
+impl DataLimit {
+    fn to_error(&self) -> io::Error {
+        match *self {
+            DataLimit::Total(exceeded) => io::Error::new(io::ErrorKind::InvalidData, exceeded),
+            DataLimit::ContentType(ref exceeded) =>
+                io::Error::new(io::ErrorKind::InvalidData, exceeded.clone()),
+        }
+    }
+}
+
+/// The clock of the timeout for single reads of the source.
+struct ReadClock(Box<Clock + Send + Sync>);
+
+impl Clock for ReadClock {
+    fn now(&self) -> Instant {
+        self.0.now()
+    }
+}
+
+impl fmt::Debug for ReadClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ReadClock(Box<Clock>)")
+    }
+}
+
+/// A time limit for reading the whole source, measured with `clock` from `started`.
+pub struct Deadline {
+    clock: Box<Clock + Send + Sync>,
+    started: Instant,
+    timeout: Duration,
+}
+
+impl Deadline {
+    /// Start a deadline of `timeout` from now.
+    pub fn new<C: Clock + Send + Sync + 'static>(timeout: Duration, clock: C) -> Self {
+        Deadline {
+            started: clock.now(),
+            clock: Box::new(clock),
+            timeout: timeout,
+        }
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if self.clock.now().duration_since(self.started) > self.timeout {
+            return Err(io::Error::new(io::ErrorKind::TimedOut,
+                                      DeadlineExceeded { timeout: self.timeout }));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Deadline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Deadline")
+            .field("started", &self.started)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
 }
 
 impl<R> BoundaryReader<R> where R: Read {
@@ -47,43 +147,104 @@ impl<R> BoundaryReader<R> where R: Read {
         let mut boundary = boundary.into();
         safemem::prepend(b"--", &mut boundary);
 
+        let delim_len = boundary.len();
+
         BoundaryReader {
             source: BufReader::with_strategies(
-                reader,
+                TimeoutReader::with_clock(reader, None, ReadClock(Box::new(SystemClock))),
                 LessThan(MIN_BUF_SIZE),
                 AtEndLessThan(MIN_BUF_SIZE),
             ),
             boundary,
             search_idx: 0,
             state: Searching,
+            last_consumed: *b"\r\n",
+            consumed: 0,
+            lenient_final: false,
+            lenient_line_endings: false,
+            allow_unterminated: false,
+            flexible_delimiter: false,
+            delim_len: delim_len,
+            preamble_limit: None,
+            boundary_seen: false,
+            data_limit: None,
+            data_read: 0,
+            deadline: None,
         }
     }
 
-    fn read_to_boundary(&mut self) -> io::Result<&[u8]> {
-        let buf = fill_buf_min(&mut self.source, self.search_idx + self.boundary.len())?;
+    /// Accept the closing boundary even if it isn't preceded by a CRLF.
+    pub fn set_lenient_final(&mut self, lenient_final: bool) {
+        self.lenient_final = lenient_final;
+    }
 
-        trace!("Buf: {:?}", String::from_utf8_lossy(buf));
+    /// Accept a boundary line preceded or terminated by a bare LF instead of CRLF.
+    pub fn set_lenient_line_endings(&mut self, lenient: bool) {
+        self.lenient_line_endings = lenient;
+    }
 
-        debug!("Before-loop Buf len: {} Search idx: {} State: {:?}",
-               buf.len(), self.search_idx, self.state);
+    /// Return an error if the first boundary doesn't start within `limit` bytes of the source.
+    pub fn set_preamble_limit(&mut self, limit: Option<u64>) {
+        self.preamble_limit = limit;
+    }
 
-        if self.state == Searching && self.search_idx < buf.len() {
-            let lookahead = &buf[self.search_idx..];
+    /// Accept `boundary` without the leading `--` as a delimiter as well.
+    pub fn set_flexible_delimiter(&mut self, flexible: bool) {
+        self.flexible_delimiter = flexible;
+    }
 
-            debug!("Find boundary loop! Lookahead len: {}", lookahead.len());
+    /// Accept the end of the source in place of the closing boundary.
+    pub fn set_allow_unterminated(&mut self, allow: bool) {
+        self.allow_unterminated = allow;
+    }
+
+    /// Limit the number of bytes of the current field's data which may be read, returning
+    /// an error of kind `InvalidData` wrapping `exceeded` beyond that.
+    ///
+    /// The limit is cleared at the next boundary. Resets `data_read()`.
+    pub fn set_data_limit(&mut self, limit: Option<(u64, DataLimit)>) {
+        self.data_limit = limit;
+        self.data_read = 0;
+    }
+
+    /// The number of bytes of field data read since `set_data_limit()`, while the limit was set.
+    pub fn data_read(&self) -> u64 {
+        self.data_read
+    }
+
+    /// Return an error if a single read of the source takes longer than `timeout`, measured
+    /// with `clock`.
+    pub fn set_read_timeout<C>(&mut self, timeout: Option<Duration>, clock: C)
+    where C: Clock + Send + Sync + 'static {
+        let source = self.source.get_mut();
+        source.set_timeout(timeout);
+        source.set_clock(ReadClock(Box::new(clock)));
+    }
+
+    /// Return an error of kind `TimedOut` from any read or boundary once `deadline` has passed.
+    pub fn set_deadline(&mut self, deadline: Option<Deadline>) {
+        self.deadline = deadline;
+    }
+
+    fn check_deadline(&self) -> io::Result<()> {
+        match self.deadline {
+            Some(ref deadline) => deadline.check(),
+            None => Ok(()),
+        }
+    }
+
+    fn read_to_boundary(&mut self) -> io::Result<&[u8]> {
+        self.check_deadline()?;
+
+        // we need the two bytes after the boundary as well to verify it
+        let mut min_len = self.search_idx + self.boundary.len() + 2;
+
+        while let Some(more) = self.find_boundary(min_len)? {
+            min_len = more;
+        }
+
+        let buf = self.source.get_buf();
 
-            // Look for the boundary, or if it isn't found, stop near the end.
-            match twoway::find_bytes(lookahead, &self.boundary) {
-                Some(found_idx) => {
-                    self.search_idx += found_idx;
-                    self.state = BoundaryRead;
-                },
-                None => {
-                    self.search_idx += lookahead.len().saturating_sub(self.boundary.len() + 2);
-                }
-            }
-        }        
-        
         debug!("After-loop Buf len: {} Search idx: {} State: {:?}",
                buf.len(), self.search_idx, self.state);
 
@@ -103,6 +264,19 @@ impl<R> BoundaryReader<R> where R: Read {
             }
         }
 
+        // or a bare LF, if lenient
+        if self.state != Searching && self.lenient_line_endings && buf_len == self.search_idx
+            && buf[..buf_len].ends_with(b"\n") {
+            buf_len -= 1;
+        }
+
+        // the field has at least this much data left, so if it doesn't fit it can't be read fully
+        if let Some((limit, ref exceeded)) = self.data_limit {
+            if self.data_read.saturating_add(buf_len as u64) > limit {
+                return Err(exceeded.to_error());
+            }
+        }
+
         let ret_buf = &buf[..buf_len];
 
         trace!("Returning buf: {:?}", String::from_utf8_lossy(ret_buf));
@@ -110,6 +284,176 @@ impl<R> BoundaryReader<R> where R: Read {
         Ok(ret_buf)
     }
 
+    /// Search the buffer for the boundary starting at `search_idx`, updating it and `state`.
+    ///
+    /// Returns `Some(min_len)` if more data is needed before anything can be returned.
+    fn find_boundary(&mut self, min_len: usize) -> io::Result<Option<usize>> {
+        let buf = fill_buf_min(&mut self.source, min_len)?;
+
+        trace!("Buf: {:?}", String::from_utf8_lossy(buf));
+
+        debug!("Before-loop Buf len: {} Search idx: {} State: {:?}",
+               buf.len(), self.search_idx, self.state);
+
+        // `fill_buf_min()` only returns less than `min_len` if the source is exhausted
+        let at_eof = buf.len() < min_len;
+
+        // a flexible delimiter may omit the leading `--`
+        let delim = if self.flexible_delimiter { &self.boundary[2..] } else { &self.boundary[..] };
+
+        while self.state == Searching && self.search_idx < buf.len() {
+            let lookahead = &buf[self.search_idx..];
+
+            debug!("Find boundary loop! Lookahead len: {}", lookahead.len());
+
+            // Look for the boundary, or if it isn't found, stop near the end.
+            let match_idx = match twoway::find_bytes(lookahead, delim) {
+                Some(found_idx) => self.search_idx + found_idx,
+                // no more data is coming so the rest of the buffer can't contain a boundary
+                None if at_eof => {
+                    self.search_idx = buf.len();
+                    break;
+                },
+                None => {
+                    self.search_idx += lookahead.len().saturating_sub(self.boundary.len() + 2);
+                    break;
+                }
+            };
+
+            // include the leading `--` if a flexible delimiter still has it
+            let (found_idx, delim_len) = if self.flexible_delimiter && match_idx >= 2
+                && &buf[match_idx - 2 .. match_idx] == b"--" {
+                (match_idx - 2, self.boundary.len())
+            } else {
+                (match_idx, delim.len())
+            };
+
+            let after_idx = found_idx + delim_len;
+
+            if buf.len() < after_idx + 2 {
+                if at_eof {
+                    // `consume_boundary()` will report the unexpected EOF
+                    self.search_idx = found_idx;
+                    self.delim_len = delim_len;
+                    self.state = BoundaryRead;
+                } else {
+                    // stop before the possible boundary and its preceding CRLF
+                    // and wait for more data
+                    self.search_idx = cmp::max(self.search_idx, found_idx.saturating_sub(2));
+                }
+
+                break;
+            }
+
+            let bytes_before = preceding_bytes(self.last_consumed, buf, found_idx);
+            let crlf_before = bytes_before == *b"\r\n"
+                || (self.lenient_line_endings && bytes_before[1] == b'\n');
+
+            let bytes_after = &buf[after_idx .. after_idx + 2];
+
+            let is_boundary = match bytes_after {
+                b"\r\n" => crlf_before,
+                b"--" => crlf_before || self.lenient_final,
+                _ if self.lenient_line_endings && bytes_after[0] == b'\n' => crlf_before,
+                _ => false,
+            };
+
+            if is_boundary {
+                self.search_idx = found_idx;
+                self.delim_len = delim_len;
+                self.state = BoundaryRead;
+            } else {
+                // a line like `--boundaryX` is field data, not a boundary; skip past it
+                debug!("Near-miss boundary at {}", found_idx);
+                self.search_idx = match_idx + 1;
+            }
+        }
+
+        // don't return an empty buffer unless we're at a boundary or EOF
+        if self.state == Searching && self.search_idx == 0 && !at_eof {
+            return Ok(Some(buf.len() + 1));
+        }
+
+        Ok(None)
+    }
+
+    /// Consume `amt` bytes from `source`, remembering the last two.
+    fn consume_source(&mut self, amt: usize) {
+        let amt = cmp::min(amt, self.source.get_buf().len());
+
+        {
+            let buf = self.source.get_buf();
+            self.last_consumed = preceding_bytes(self.last_consumed, buf, amt);
+        }
+
+        self.source.consume(amt);
+        self.consumed += amt as u64;
+    }
+
+    /// The number of bytes read from the source but not yet consumed.
+    pub fn buffered_len(&self) -> usize {
+        self.source.get_buf().len()
+    }
+
+    /// The total number of bytes consumed from the source.
+    pub fn consumed(&self) -> u64 {
+        self.consumed
+    }
+
+    /// Consume up to `limit` bytes, or to the end, of the source regardless of boundaries,
+    /// returning the number of bytes consumed.
+    pub fn consume_to_end(&mut self, limit: u64) -> io::Result<u64> {
+        let mut total = 0;
+
+        while total < limit {
+            let len = {
+                let buf = self.source.fill_buf()?;
+                cmp::min(buf.len() as u64, limit - total) as usize
+            };
+
+            if len == 0 {
+                break;
+            }
+
+            self.consume_source(len);
+            total += len as u64;
+        }
+
+        Ok(total)
+    }
+
+    /// Returns `true` if nothing has been consumed from the source and it is at EOF.
+    pub fn is_empty(&mut self) -> io::Result<bool> {
+        if self.consumed > 0 {
+            return Ok(false);
+        }
+
+        Ok(self.source.fill_buf()?.is_empty())
+    }
+
+    /// Consume the rest of the current field, leaving the next boundary unread.
+    pub fn drain_to_boundary(&mut self) -> io::Result<()> {
+        // discarded data doesn't count towards the limit
+        self.data_limit = None;
+
+        loop {
+            let buf_len = self.read_to_boundary()?.len();
+
+            if buf_len == 0 {
+                return Ok(());
+            }
+
+            self.consume(buf_len);
+        }
+    }
+
+    /// Unwrap the source, returning any bytes buffered but not yet consumed followed by the rest
+    /// of the source.
+    pub fn into_remainder(self) -> io::Chain<io::Cursor<Vec<u8>>, R> {
+        let buffered = self.source.get_buf().to_vec();
+        io::Cursor::new(buffered).chain(self.source.into_inner().into_inner())
+    }
+
     pub fn set_min_buf_size(&mut self, min_buf_size: usize) {
         // ensure the minimum buf size is at least enough to find a boundary with some extra
         let min_buf_size = cmp::max(self.boundary.len() * 2, min_buf_size);
@@ -124,22 +468,61 @@ impl<R> BoundaryReader<R> where R: Read {
             return Ok(true);
         }
 
+        self.check_deadline()?;
+
+        // discarded data doesn't count towards the limit
+        self.data_limit = None;
+
         while self.state == Searching {
             debug!("Boundary not found yet");
 
             let buf_len = self.read_to_boundary()?.len();
 
+            if buf_len == 0 && self.state == Searching {
+                // a completely empty body has no fields, which is how the client encodes it
+                if self.consumed == 0 {
+                    self.state = AtEnd;
+                    return Ok(true);
+                }
+
+                if let (false, Some(limit)) = (self.boundary_seen, self.preamble_limit) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                              BoundaryNotFound { limit: limit }));
+                }
+
+                if self.allow_unterminated {
+                    self.state = AtEnd;
+                    return Ok(true);
+                }
+
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                          "unexpected end of request body"));
+            }
+
+            if let (false, Some(limit)) = (self.boundary_seen, self.preamble_limit) {
+                if self.consumed + buf_len as u64 > limit {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                              BoundaryNotFound { limit: limit }));
+                }
+            }
+
             debug!("Discarding {} bytes", buf_len);
 
             self.consume(buf_len);
         }
 
         let consume_amt = {
-            let min_len = self.boundary.len() + 4;
+            let min_len = self.search_idx + self.delim_len + 2;
 
             let buf = fill_buf_min(&mut self.source, min_len)?;
 
             if buf.len() < min_len {
+                // e.g. the closing boundary without its trailing hyphens
+                if self.allow_unterminated {
+                    self.state = AtEnd;
+                    return Ok(true);
+                }
+
                 return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
                                           "not enough bytes to verify boundary"));
             }
@@ -147,13 +530,14 @@ impl<R> BoundaryReader<R> where R: Read {
             // we have enough bytes to verify
             self.state = Searching;
 
-            let mut consume_amt = self.search_idx + self.boundary.len();
+            let mut consume_amt = self.search_idx + self.delim_len;
 
             let last_two = &buf[consume_amt .. consume_amt + 2];
 
             match last_two {
                 b"\r\n" => consume_amt += 2,
                 b"--" => { consume_amt += 2; self.state = AtEnd },
+                _ if self.lenient_line_endings && last_two[0] == b'\n' => consume_amt += 1,
                 _ => debug!("Unexpected bytes following boundary: {:?}",
                             String::from_utf8_lossy(&last_two)),
             }
@@ -165,8 +549,9 @@ impl<R> BoundaryReader<R> where R: Read {
                consume_amt,
                String::from_utf8_lossy(self.source.get_buf()));
 
-        self.source.consume(consume_amt);
+        self.consume_source(consume_amt);
         self.search_idx = 0;
+        self.boundary_seen = true;
 
         trace!("Consumed boundary (state: {:?}), remaining buf: {:?}", self.state,
                String::from_utf8_lossy(self.source.get_buf()));
@@ -186,12 +571,14 @@ impl<'a> BoundaryReader<io::Cursor<&'a [u8]>> {
         self.source.seek(io::SeekFrom::Start(0));
         self.state = Searching;
         self.search_idx = 0;
+        self.last_consumed = *b"\r\n";
+        self.consumed = 0;
     }
 }
 
 impl<R> Borrow<R> for BoundaryReader<R> {
     fn borrow(&self) -> &R {
-        self.source.get_ref()
+        self.source.get_ref().get_ref()
     }
 }
 
@@ -218,8 +605,21 @@ impl<R> BufRead for BoundaryReader<R> where R: Read {
 
         debug!("Consume! amt: {} true amt: {}", amt, true_amt);
 
-        self.source.consume(true_amt);
+        self.consume_source(true_amt);
         self.search_idx -= true_amt;
+
+        if self.data_limit.is_some() {
+            self.data_read += true_amt as u64;
+        }
+    }
+}
+
+/// Get the two bytes preceding `idx` in `buf`, taking them from `last_consumed` as necessary.
+fn preceding_bytes(last_consumed: [u8; 2], buf: &[u8], idx: usize) -> [u8; 2] {
+    match idx {
+        0 => last_consumed,
+        1 => [last_consumed[1], buf[0]],
+        _ => [buf[idx - 2], buf[idx - 1]],
     }
 }
 
@@ -349,6 +749,34 @@ mod test {
         assert_eq!(buf, "");
     }
 
+    #[test]
+    fn test_first_boundary() {
+        // the first boundary needs no preceding CRLF, but one after a preamble does
+        let at_start = "--boundary\r\nvalue\r\n--boundary--";
+        let preamble = "junk--boundary\r\nmore junk\r\n--boundary\r\nvalue\r\n--boundary--";
+
+        let mut buf = String::new();
+
+        for &(body, expected_preamble) in &[(at_start, ""), (preamble, "junk--boundary\r\nmore junk")] {
+            for split_at in 0 .. body.len() {
+                let src = SplitReader::split(body.as_bytes(), split_at);
+                let mut reader = BoundaryReader::from_reader(src, BOUNDARY);
+
+                buf.clear();
+                reader.read_to_string(&mut buf).unwrap();
+                assert_eq!(buf, expected_preamble, "split at {}", split_at);
+
+                assert!(!reader.consume_boundary().unwrap());
+
+                buf.clear();
+                reader.read_to_string(&mut buf).unwrap();
+                assert_eq!(buf, "value", "split at {}", split_at);
+
+                assert!(reader.consume_boundary().unwrap());
+            }
+        }
+    }
+
     #[test]
     fn test_leading_crlf() {
         let mut body: &[u8] = b"\r\n\r\n--boundary\r\n\
@@ -457,6 +885,58 @@ mod test {
         assert_eq!(buf, "");
     }
 
+    const NEAR_MISS_VAL: &'static str = "--boundary\r\n\
+                                         field-1\r\n\
+                                         --boundaryX\r\n\
+                                         --boundary-\r\n\
+                                         x--boundary_\r\n\
+                                         --boundary\r\n\
+                                         field-2\r\n\
+                                         --boundary--";
+
+    fn test_near_miss_reader<R: Read>(reader: &mut BoundaryReader<R>, buf: &mut String) {
+        buf.clear();
+
+        debug!("Consume 1");
+        assert!(!reader.consume_boundary().unwrap());
+
+        debug!("Read 1");
+        let _ = reader.read_to_string(buf).unwrap();
+        assert_eq!(buf, "field-1\r\n--boundaryX\r\n--boundary-\r\nx--boundary_");
+        buf.clear();
+
+        debug!("Consume 2");
+        assert!(!reader.consume_boundary().unwrap());
+
+        debug!("Read 2");
+        let _ = reader.read_to_string(buf).unwrap();
+        assert_eq!(buf, "field-2");
+        buf.clear();
+
+        debug!("Consume 3");
+        assert!(reader.consume_boundary().unwrap());
+    }
+
+    #[test]
+    fn test_near_miss_boundary() {
+        let logger = ::mock::log_on_panic();
+
+        let mut buf = String::new();
+
+        let mut reader = BoundaryReader::from_reader(NEAR_MISS_VAL.as_bytes(), BOUNDARY);
+        test_near_miss_reader(&mut reader, &mut buf);
+
+        for split_at in 0 .. NEAR_MISS_VAL.len() {
+            debug!("Testing split at: {}", split_at);
+
+            let src = SplitReader::split(NEAR_MISS_VAL.as_bytes(), split_at);
+            let mut reader = BoundaryReader::from_reader(src, BOUNDARY);
+            test_near_miss_reader(&mut reader, &mut buf);
+        }
+
+        logger.clear();
+    }
+
     #[cfg(feature = "bench")]
     mod bench {
         extern crate test;
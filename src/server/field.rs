@@ -10,21 +10,47 @@ use mime::{self, Mime, FromStrError as MimeParseErr};
 
 use quick_error::ResultExt;
 
+use std::collections::HashSet;
 use std::error::Error;
-use std::io::{self, Read, BufRead, Write};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, BufRead, Seek, SeekFrom, Write};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
-use std::{str, fmt, error};
+use std::sync::Arc;
+
+#[cfg(feature = "timing")]
+use std::time::{Duration, Instant};
+
+use std::{cmp, str, fmt, error};
 
 use std::ascii::AsciiExt;
 
 use super::httparse::{self, EMPTY_HEADER, Header, Status, Error as HttparseError};
 
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+
+#[cfg(feature = "async-bridge")]
+use futures::{Async, Poll, Stream};
+
+#[cfg(feature = "memmap2")]
+use memmap2::{Mmap, MmapMut};
+
+#[cfg(feature = "json")]
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "json")]
+use serde_json;
+
 use self::ReadEntryResult::*;
 
 use super::save::{SaveBuilder, SavedField};
 
-use super::ArcStr;
+use super::transfer::{is_supported_encoding, TransferDecoder};
+
+use super::{ArcStr, Limits, Multipart, NestingTooDeep};
+
+use tempdir::TempDir;
 
 const EMPTY_STR_HEADER: StrHeader<'static> = StrHeader {
     name: "",
@@ -60,7 +86,10 @@ impl <'s, 'a: 's> fmt::Display for  DisplayHeaders<'s, 'a> {
 
 const MAX_ATTEMPTS: usize = 30;
 
-fn with_headers<R, F, Ret>(r: &mut R, closure: F) -> Result<Ret, ParseHeaderError>
+/// Parse the header block at the start of `r` and pass the headers to `closure`.
+///
+/// Unless `lenient_line_endings` is set, the block must end in CRLF-CRLF.
+fn with_headers<R, F, Ret>(r: &mut R, lenient_line_endings: bool, closure: F) -> Result<Ret, ParseHeaderError>
 where R: BufRead, F: FnOnce(&[StrHeader]) -> Ret {
     const HEADER_LEN: usize = 4;
 
@@ -84,8 +113,12 @@ where R: BufRead, F: FnOnce(&[StrHeader]) -> Ret {
             // read more and try again
             Status::Partial => last_len = buf.len(),
             Status::Complete((consume_, raw_headers)) => {
+                if !lenient_line_endings && !ends_with_crlf_crlf(&buf[..consume_]) {
+                    return Err(ParseHeaderError::BareLineFeed);
+                }
+
                 let mut headers = [EMPTY_STR_HEADER; HEADER_LEN];
-                let headers = copy_headers(raw_headers, &mut headers)?;
+                let headers = copy_headers(buf, raw_headers, &mut headers)?;
                 debug!("Parsed headers: {:?}", headers);
                 consume = consume_;
                 ret = closure(headers);
@@ -98,15 +131,155 @@ where R: BufRead, F: FnOnce(&[StrHeader]) -> Ret {
     Ok(ret)
 }
 
-fn copy_headers<'h, 'b: 'h>(raw: &[Header<'b>], headers: &'h mut [StrHeader<'b>]) -> io::Result<&'h [StrHeader<'b>]> {
+/// Check that a complete header block, as consumed by `httparse`, ends in CRLF-CRLF
+/// (or is a single CRLF, if there are no headers).
+fn ends_with_crlf_crlf(block: &[u8]) -> bool {
+    block == b"\r\n" || block.ends_with(b"\r\n\r\n")
+}
+
+/// Copy the headers parsed by `httparse` from `buf`, restoring any trailing whitespace of their
+/// values which it trimmed, so `FieldHeaders::raw_headers()` returns them exactly as sent.
+fn copy_headers<'h, 'b: 'h>(buf: &'b [u8], raw: &[Header<'b>], headers: &'h mut [StrHeader<'b>])
+                            -> io::Result<&'h [StrHeader<'b>]> {
     for (raw, header) in raw.iter().zip(&mut *headers) {
         header.name = raw.name;
-        header.val = io_str_utf8(raw.value)?;
+        header.val = io_str_utf8(untrimmed_value(buf, raw.value))?;
     }
 
     Ok(&headers[..raw.len()])
 }
 
+/// Extend `value`, a slice of `buf`, over the spaces and tabs following it.
+fn untrimmed_value<'b>(buf: &'b [u8], value: &'b [u8]) -> &'b [u8] {
+    let buf_start = buf.as_ptr() as usize;
+    let value_start = value.as_ptr() as usize;
+
+    // an empty value might not point into `buf`
+    if value_start < buf_start || value_start + value.len() > buf_start + buf.len() {
+        return value;
+    }
+
+    let start = value_start - buf_start;
+    let end = start + value.len();
+    let trailing = buf[end..].iter().take_while(|&&b| b == b' ' || b == b'\t').count();
+
+    &buf[start..end + trailing]
+}
+
+/// What to do with a field which has a `filename` but an empty or whitespace-only `name`.
+///
+/// Set with `Multipart::on_empty_name()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EmptyNamePolicy {
+    /// Return an error from `read_entry()`.
+    Error,
+    /// Use the field's `filename` as its name.
+    UseFilename,
+    /// Pass through the empty name (the default).
+    AllowEmpty,
+}
+
+impl Default for EmptyNamePolicy {
+    fn default() -> Self {
+        EmptyNamePolicy::AllowEmpty
+    }
+}
+
+/// What to do with a field whose `Content-Transfer-Encoding` isn't supported, with
+/// `Multipart::with_transfer_decoding()`.
+///
+/// Set with `Multipart::on_unknown_encoding()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnknownEncoding {
+    /// Return an error from `read_entry()` (the default).
+    Error,
+    /// Pass through the field data undecoded; check `FieldHeaders::transfer_encoding`.
+    PassThrough,
+}
+
+impl Default for UnknownEncoding {
+    fn default() -> Self {
+        UnknownEncoding::Error
+    }
+}
+
+/// The line ending to convert text fields to in `MultipartField::read_text()`.
+///
+/// Set with `Multipart::with_text_newline_normalization()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Normalize {
+    /// Convert all line endings to `\r\n`.
+    CrLf,
+    /// Convert all line endings to `\n`.
+    Lf,
+    /// Leave line endings as they are (the default).
+    None,
+}
+
+impl Default for Normalize {
+    fn default() -> Self {
+        Normalize::None
+    }
+}
+
+/// A decoder for a text charset, registered with `Multipart::with_charset_decoder()`.
+pub type CharsetDecoder = Fn(&[u8]) -> Result<String, DecodeError> + Send + Sync;
+
+/// An error returned by a `CharsetDecoder` for data which isn't valid in its charset.
+///
+/// `MultipartField::read_text()` returns it wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    message: String,
+}
+
+impl DecodeError {
+    /// Create a new error with the given message.
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        DecodeError { message: message.into() }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.description(), self.message)
+    }
+}
+
+impl Error for DecodeError {
+    fn description(&self) -> &str {
+        "error decoding text field"
+    }
+}
+
+/// Options for parsing and validating field headers, set via the builder methods on `Multipart`.
+///
+/// Not exposed.
+#[derive(Clone, Debug, Default)]
+pub struct HeaderOpts {
+    /// The maximum length of a field's `filename`, in bytes. `None` means unlimited.
+    pub filename_limit: Option<usize>,
+    /// What to do with fields with a filename but an empty name.
+    pub empty_name: EmptyNamePolicy,
+    /// Parsing a `multipart/related` body, in which `Content-Disposition` is optional.
+    pub related: bool,
+    /// Parsing a nested `multipart/mixed` body, in which `Content-Disposition` is optional and
+    /// may have any type and no name.
+    pub mixed: bool,
+    /// Don't trim leading and trailing ASCII whitespace from header values before parsing them.
+    pub keep_header_whitespace: bool,
+    /// Return an error for field names and filenames containing ASCII control characters.
+    pub reject_control_chars: bool,
+    /// Trim leading and trailing ASCII whitespace from field names.
+    pub trim_names: bool,
+    /// Check the `Content-Transfer-Encoding` of fields.
+    pub transfer_decoding: bool,
+    /// What to do with unsupported transfer encodings when `transfer_decoding` is set.
+    pub unknown_encoding: UnknownEncoding,
+    /// Accept `\n\n` and `\r\n\n` as well as `\r\n\r\n` at the end of the header block.
+    pub lenient_line_endings: bool,
+}
+
 /// The headers that (may) appear before a `multipart/form-data` field.
 ///
 /// ### Warning: Values are Client-Provided
@@ -128,25 +301,284 @@ pub struct FieldHeaders {
     /// should not be implicitly trusted. This crate makes no attempt to identify or validate
     /// the content-type of the actual field data.
     pub content_type: Option<Mime>,
+
+    /// The `Content-ID` value of this field, if supplied, including any enclosing angle brackets.
+    ///
+    /// Mostly used to refer to parts of a `multipart/related` body.
+    pub content_id: Option<String>,
+
+    /// The `Content-Transfer-Encoding` value of this field, if supplied.
+    ///
+    /// Deprecated by [IETF RFC 7578, section 4.7](https://tools.ietf.org/html/rfc7578#section-4.7)
+    /// but still sent by some clients.
+    pub transfer_encoding: Option<String>,
+
+    /// The `Content-Length` value of this field, if supplied and valid.
+    ///
+    /// Most clients don't send this; see `Multipart::with_strict_part_length()` to check it.
+    pub content_length: Option<u64>,
+
+    content_type_params: Vec<(String, String)>,
+
+    raw_headers: Vec<(String, String)>,
 }
 
 impl FieldHeaders {
     /// Parse the field headers from the passed `BufRead`, consuming the relevant bytes.
-    fn read_from<R: BufRead>(r: &mut R) -> Result<Self, ParseHeaderError> {
-        with_headers(r, Self::parse)?
+    fn read_from<R: BufRead>(r: &mut R, opts: &HeaderOpts) -> Result<Self, ParseHeaderError> {
+        with_headers(r, opts.lenient_line_endings, |headers| Self::parse(headers, opts))?
     }
 
-    fn parse(headers: &[StrHeader]) -> Result<FieldHeaders, ParseHeaderError> {
-        let cont_disp = ContentDisp::parse_required(headers)?;
+    fn parse(headers: &[StrHeader], opts: &HeaderOpts) -> Result<FieldHeaders, ParseHeaderError> {
+        let raw_headers = headers.iter().map(|h| (h.name.to_owned(), h.val.to_owned())).collect();
+
+        let trimmed: Vec<StrHeader>;
+
+        let headers = if opts.keep_header_whitespace {
+            headers
+        } else {
+            trimmed = headers.iter()
+                .map(|h| StrHeader { name: h.name, val: trim_ascii_whitespace(h.val) })
+                .collect();
+            &trimmed
+        };
+
+        let mut cont_disp = if (opts.related || opts.mixed)
+            && find_header(headers, "Content-Disposition").is_none() {
+            ContentDisp { field_name: String::new(), filename: None }
+        } else if opts.mixed {
+            ContentDisp::parse_mixed(headers)?
+        } else {
+            ContentDisp::parse_required(headers)?
+        };
+
+        if let (Some(limit), Some(filename)) = (opts.filename_limit, cont_disp.filename.as_ref()) {
+            if filename.len() > limit {
+                return Err(ParseHeaderError::FilenameTooLong(filename.len(), limit));
+            }
+        }
+
+        if opts.reject_control_chars {
+            if has_control_char(&cont_disp.field_name) {
+                return Err(ParseHeaderError::IllegalControlChar("name", cont_disp.field_name));
+            }
+
+            if let Some(ref filename) = cont_disp.filename {
+                if has_control_char(filename) {
+                    return Err(ParseHeaderError::IllegalControlChar("filename", filename.clone()));
+                }
+            }
+        }
+
+        if opts.trim_names {
+            cont_disp.field_name = trim_ascii_whitespace(&cont_disp.field_name).to_owned();
+        }
+
+        if cont_disp.field_name.trim().is_empty() {
+            if let Some(ref filename) = cont_disp.filename {
+                match opts.empty_name {
+                    EmptyNamePolicy::Error =>
+                        return Err(ParseHeaderError::EmptyName(filename.clone())),
+                    EmptyNamePolicy::UseFilename => cont_disp.field_name = filename.clone(),
+                    EmptyNamePolicy::AllowEmpty => (),
+                }
+            }
+        }
+
+        let content_type = parse_content_type(headers)?;
+
+        let content_type_params = content_type.as_ref().map_or_else(Vec::new, |ct| {
+            ct.params()
+                .map(|(name, val)| (name.as_str().to_owned(), trim_quotes(val.as_str()).to_owned()))
+                .collect()
+        });
+
+        let transfer_encoding = find_header(headers, "Content-Transfer-Encoding")
+            .map(|h| h.val.to_owned());
+
+        if let (true, Some(encoding)) = (opts.transfer_decoding, transfer_encoding.as_ref()) {
+            if !is_supported_encoding(encoding) && opts.unknown_encoding == UnknownEncoding::Error {
+                return Err(ParseHeaderError::UnknownEncoding(encoding.clone()));
+            }
+        }
 
         Ok(FieldHeaders {
             name: cont_disp.field_name.into(),
             filename: cont_disp.filename,
-            content_type: parse_content_type(headers)?,
+            content_type: content_type,
+            content_id: find_header(headers, "Content-ID").map(|h| h.val.to_owned()),
+            transfer_encoding: transfer_encoding,
+            content_length: find_header(headers, "Content-Length")
+                .and_then(|h| h.val.parse().ok()),
+            content_type_params: content_type_params,
+            raw_headers: raw_headers,
+        })
+    }
+
+    /// Get all parameters of the field's `Content-Type`, as `(name, value)` pairs in the order
+    /// they appeared, e.g. `header=present` in `Content-Type: text/csv; header=present`.
+    ///
+    /// Empty if there was no `Content-Type`.
+    pub fn content_type_params(&self) -> &[(String, String)] {
+        &self.content_type_params
+    }
+
+    /// Get all headers of the field as `(name, value)` pairs, exactly in the order they appeared
+    /// and including duplicates, e.g. to verify a signature over them.
+    ///
+    /// Names keep their case as sent, and values aren't unquoted, trimmed or otherwise processed,
+    /// regardless of `Multipart::with_trim_header_values()`; only the whitespace between the
+    /// colon and the value is skipped.
+    pub fn raw_headers(&self) -> &[(String, String)] {
+        &self.raw_headers
+    }
+
+    /// If the field's `Content-Type` is `message/external-body` ([IETF RFC 2046, section
+    /// 5.2.3][rfc2046-5.2.3]), get the reference to where its actual data lives.
+    ///
+    /// Nothing is fetched; it is up to the caller to resolve the reference, if at all. The
+    /// field's data holds the headers of the external body, which can be parsed with
+    /// `FieldHeaders` again if needed.
+    ///
+    /// Returns `None` if the field has a different `Content-Type` or no `access-type` parameter.
+    ///
+    /// [rfc2046-5.2.3]: https://tools.ietf.org/html/rfc2046#section-5.2.3
+    pub fn external_body(&self) -> Option<ExternalBodyRef> {
+        let content_type = try_opt!(self.content_type.as_ref());
+
+        if content_type.type_() != mime::MESSAGE || content_type.subtype() != "external-body" {
+            return None;
+        }
+
+        let mut params = self.content_type_params.clone();
+        let access_type = try_opt!(take_param(&mut params, "access-type"));
+
+        Some(ExternalBodyRef {
+            access_type: access_type,
+            params: params,
+        })
+    }
+}
+
+/// The reference to the data of a `message/external-body` field; see
+/// `FieldHeaders::external_body()`.
+///
+/// ### Warning: Values are Client-Provided
+/// As with `FieldHeaders`, these values should be considered **untrustworthy**; in particular,
+/// fetching an arbitrary reference sent by a client may expose internal resources.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExternalBodyRef {
+    /// The `access-type` parameter as it appeared, e.g. `URL`, `anon-ftp` or `local-file`.
+    pub access_type: String,
+    /// All other parameters of the `Content-Type`, as `(name, value)` pairs in the order they
+    /// appeared, e.g. `URL`, `name`, `site`, `directory`, `expiration` or `size`.
+    pub params: Vec<(String, String)>,
+}
+
+impl ExternalBodyRef {
+    /// Get the value of the parameter `name`, compared case-insensitively.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter()
+            .find(|&&(ref param, _)| param.eq_ignore_ascii_case(name))
+            .map(|&(_, ref val)| &**val)
+    }
+
+    /// Get the `URL` parameter, if the access type is `URL` ([IETF RFC 2017][rfc2017]).
+    ///
+    /// [rfc2017]: https://tools.ietf.org/html/rfc2017
+    pub fn url(&self) -> Option<&str> {
+        if self.access_type.eq_ignore_ascii_case("URL") {
+            self.param("URL")
+        } else {
+            None
+        }
+    }
+}
+
+/// A parsed `Content-Disposition` header value; see `parse_content_disposition()`.
+///
+/// ### Warning: Values are Client-Provided
+/// As with `FieldHeaders`, these values should be considered **untrustworthy**.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Disposition {
+    /// The disposition type, e.g. `form-data`, `attachment` or `inline`, as it appeared.
+    pub disposition_type: String,
+    /// The `name` parameter, if present.
+    pub name: Option<String>,
+    /// The `filename` parameter, if present.
+    ///
+    /// If an extended `filename*` parameter ([IETF RFC 2231][rfc2231]) could be decoded,
+    /// it is used instead.
+    ///
+    /// [rfc2231]: https://tools.ietf.org/html/rfc2231
+    pub filename: Option<String>,
+    /// All other parameters, as `(name, value)` pairs in the order they appeared.
+    ///
+    /// Extended parameters are decoded and appear under their plain name.
+    pub params: Vec<(String, String)>,
+}
+
+impl Disposition {
+    fn parse(val: &str) -> Result<Disposition, ParseHeaderError> {
+        let (disp_type, params) = split_once(val, ';').unwrap_or((val, ""));
+        let disp_type = trim_ascii_whitespace(disp_type);
+
+        if disp_type.is_empty() {
+            invalid_cont_disp!("expected disposition type, got", val);
+        }
+
+        let mut params = collapse_ext_params(parse_params(params));
+
+        let name = take_param(&mut params, "name");
+        let filename = take_param(&mut params, "filename");
+
+        Ok(Disposition {
+            disposition_type: disp_type.to_owned(),
+            name: name,
+            filename: filename,
+            params: params,
         })
     }
 }
 
+/// Parse the value of a `Content-Disposition` header, e.g.
+/// `form-data; name="field"; filename="file.txt"`.
+///
+/// This is the same parser used for the headers of fields in `Multipart`. Parameter values may
+/// be quoted strings with `"` or `\` escaped by a backslash, and extended parameters
+/// (`filename*=UTF-8''...`) as described in [IETF RFC 2231][rfc2231] are decoded, including
+/// continuations; only the `UTF-8` and `US-ASCII` charsets are supported.
+///
+/// [rfc2231]: https://tools.ietf.org/html/rfc2231
+///
+/// ## Returns Error
+/// Of kind `InvalidData` if the disposition type is missing.
+pub fn parse_content_disposition(val: &str) -> io::Result<Disposition> {
+    Disposition::parse(val).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Get the `boundary` parameter from the value of a `Content-Type` header, e.g.
+/// `multipart/form-data; boundary="some boundary"`, for use with `Multipart::with_body()`.
+///
+/// Parameters are parsed as in `parse_content_disposition()`, so the boundary may be a quoted
+/// string and may be followed by other parameters. Returns `None` if the type isn't
+/// `multipart/*` or the boundary is missing or empty.
+pub fn boundary_from_content_type(val: &str) -> Option<String> {
+    let (media_type, params) = split_once(val, ';').unwrap_or((val, ""));
+
+    let is_multipart = split_once(trim_ascii_whitespace(media_type), '/')
+        .map_or(false, |(type_, _)| type_.eq_ignore_ascii_case("multipart"));
+
+    if !is_multipart {
+        return None;
+    }
+
+    parse_params(params).into_iter()
+        .find(|&(key, _)| key.eq_ignore_ascii_case("boundary"))
+        .map(|(_, boundary)| boundary)
+        .and_then(|boundary| if boundary.is_empty() { None } else { Some(boundary) })
+}
+
 /// The `Content-Disposition` header.
 struct ContentDisp {
     /// The name of the `multipart/form-data` field.
@@ -165,34 +597,32 @@ impl ContentDisp {
             ));
         };
 
-        // Content-Disposition: ?
-        let after_disp_type = match split_once(header.val, ';') {
-            Some((disp_type, after_disp_type)) => {
-                // assert Content-Disposition: form-data
-                // but needs to be parsed out to trim the spaces (allowed by spec IIRC)
-                if disp_type.trim() != "form-data" {
-                    invalid_cont_disp!("unexpected Content-Disposition value", disp_type);
-                }
-                after_disp_type
-            },
-            None => invalid_cont_disp!("expected additional data after Content-Disposition type",
-                                       header.val),
-        };
+        let disp = Disposition::parse(header.val)?;
+
+        // assert Content-Disposition: form-data
+        if !disp.disposition_type.eq_ignore_ascii_case("form-data") {
+            invalid_cont_disp!("unexpected Content-Disposition value", disp.disposition_type);
+        }
 
         // Content-Disposition: form-data; name=?
-        let (field_name, filename) = match get_str_after("name=", ';', after_disp_type) {
-            None => invalid_cont_disp!("expected field name and maybe filename, got",
-                                       after_disp_type),
-            // Content-Disposition: form-data; name={field_name}; filename=?
-            Some((field_name, after_field_name)) => {
-                let field_name = trim_quotes(field_name);
-                let filename = get_str_after("filename=", ';', after_field_name)
-                    .map(|(filename, _)| trim_quotes(filename).to_owned());
-                (field_name, filename)
-            },
-        };
+        match disp.name {
+            Some(field_name) => Ok(ContentDisp { field_name, filename: disp.filename }),
+            None => invalid_cont_disp!("expected field name and maybe filename, got", header.val),
+        }
+    }
+
+    /// Parse a `Content-Disposition` header which must be present but may have any type,
+    /// e.g. `file` in a nested `multipart/mixed` body, and no name.
+    fn parse_mixed(headers: &[StrHeader]) -> Result<ContentDisp, ParseHeaderError> {
+        let header = find_header(headers, "Content-Disposition")
+            .expect("checked by the caller");
+
+        let disp = Disposition::parse(header.val)?;
 
-        Ok(ContentDisp { field_name: field_name.to_owned(), filename })
+        Ok(ContentDisp {
+            field_name: disp.name.unwrap_or_default(),
+            filename: disp.filename,
+        })
     }
 }
 
@@ -234,116 +664,1147 @@ impl<M: ReadEntry> MultipartField<M> {
         self.headers.content_type.as_ref().map_or(true, |ct| ct.type_() == mime::TEXT)
     }
 
-    /// Read the next entry in the request.
-    pub fn next_entry(self) -> ReadEntryResult<M> {
-        self.data.into_inner().read_entry()
-    }
+    /// Read the field data to a string, returning an error if it isn't valid UTF-8.
+    ///
+    /// If the field's `Content-Type` has a `charset` parameter for which a decoder was registered
+    /// with `Multipart::with_charset_decoder()`, that decoder is used instead.
+    ///
+    /// If this isn't a file field (it has no `filename`), a leading UTF-8 BOM is removed if
+    /// `Multipart::with_strip_bom()` is set and it was read as UTF-8, it is percent-decoded if
+    /// `Multipart::with_urlencoded_text_values()` is set, and its line endings are converted as
+    /// set by `Multipart::with_text_newline_normalization()`. File fields are returned unchanged.
+    pub fn read_text(&mut self) -> io::Result<String> {
+        let newline = self.data.inner_mut().text_newlines();
+        let urlencoded = self.data.inner_mut().urlencoded_text();
+        let strip_bom = self.data.inner_mut().strip_bom();
+
+        let decoder = match self.headers.content_type_params.iter()
+            .find(|&&(ref name, _)| name.eq_ignore_ascii_case("charset")) {
+            Some(&(_, ref charset)) => self.data.inner_mut().charset_decoder(charset),
+            None => None,
+        };
+
+        let is_utf8 = decoder.is_none();
+
+        let text = match decoder {
+            Some(decoder) => {
+                let mut bytes = Vec::new();
+                self.data.read_to_end(&mut bytes)?;
+                (*decoder)(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            },
+            None => {
+                let mut text = String::new();
+                self.data.read_to_string(&mut text)?;
+                text
+            },
+        };
+
+        if self.headers.filename.is_some() {
+            return Ok(text);
+        }
+
+        let text = if strip_bom && is_utf8 && text.starts_with('\u{FEFF}') {
+            text['\u{FEFF}'.len_utf8()..].to_owned()
+        } else {
+            text
+        };
+
+        let text = if urlencoded { url_decode(&text)? } else { text };
+        Ok(normalize_newlines(text, newline))
+    }
+
+    /// Guess the real type of the field data from its first few bytes ("magic numbers"),
+    /// rather than trusting the client-provided `headers.content_type`.
+    ///
+    /// The bytes are only peeked at, so they are still returned by subsequent reads of `data`.
+    /// Recognizes PNG, JPEG, GIF, PDF and ZIP (which includes formats based on it, such as
+    /// `.docx` or `.jar`); returns `None` for anything else, or if not enough data is left.
+    pub fn sniff_content_type(&mut self) -> io::Result<Option<Mime>> {
+        let mut last_len = 0;
+
+        loop {
+            let buf = self.data.fill_buf()?;
+
+            // stop once no more data is coming before the boundary
+            if buf.len() >= MAX_SIGNATURE_LEN || buf.len() == last_len {
+                return Ok(sniff_signature(buf));
+            }
+
+            last_len = buf.len();
+        }
+    }
+
+    /// Wrap the field data in a decoder constructed by `f`, e.g. to inflate a part which the
+    /// client compressed with gzip, and return it.
+    ///
+    /// The decoder reads from `data`, so it still reaches EOF at the end of this field.
+    /// Which decoder to use is up to the caller, based on the headers agreed with the client;
+    /// this crate doesn't interpret them.
+    pub fn decode_with<'a, D: Read, F>(&'a mut self, f: F) -> D
+    where F: FnOnce(&'a mut MultipartData<M>) -> D {
+        f(&mut self.data)
+    }
+
+    /// Get a reader over the field's data exactly as sent, without decoding its
+    /// `Content-Transfer-Encoding`, e.g. to store a `base64` part as-is.
+    ///
+    /// If the field isn't decoded (see `MultipartData::is_transfer_decoded()`), this reads the
+    /// same bytes as `data`.
+    ///
+    /// ### Note: Don't Mix Raw and Decoded Reads
+    /// For a decoded field, once some of the data has been read from `data`, reading from the
+    /// raw body returns an error of kind `InvalidInput`, and vice versa; pick one per field.
+    pub fn raw_body(&mut self) -> RawBody<M> {
+        RawBody { data: &mut self.data }
+    }
+
+    /// Read the field's data as a nested multipart body, e.g. a `multipart/mixed` field holding
+    /// several files, using the boundary from the field's `Content-Type`.
+    ///
+    /// The nested `Multipart` reads parts as with `Multipart::with_nested_mixed()`, and has the
+    /// same `Limits` as the body this field is in, counted separately. It is one level deeper
+    /// than that body (the request body is depth 0); if that exceeds
+    /// `Limits::max_nesting_depth`, this returns an error of kind `InvalidData` wrapping a
+    /// `NestingTooDeep` before any of the data is read.
+    ///
+    /// Returns an error of kind `InvalidData` if the field isn't `multipart/*` with a boundary.
+    pub fn read_nested(&mut self) -> io::Result<Multipart<&mut MultipartData<M>>> {
+        let boundary = self.headers.content_type.as_ref()
+            .and_then(|content_type| boundary_from_content_type(&content_type.to_string()))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+                                          "expected a multipart field with a boundary"))?;
+
+        let (depth, limits) = {
+            let inner = self.data.inner_ref();
+            (inner.nesting_depth() + 1, inner.nested_limits())
+        };
+
+        if let Some(limit) = limits.max_nesting_depth {
+            if depth > limit {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          NestingTooDeep { limit: limit }));
+            }
+        }
+
+        let mut nested = Multipart::with_body(&mut self.data, boundary)
+            .with_nested_mixed()
+            .with_limits(limits);
+
+        nested.nesting_depth = depth;
+        Ok(nested)
+    }
+
+    /// Read the rest of the field data into a buffer which implements `Read + Seek`, so it can
+    /// be rewound, e.g. to sniff its first bytes and then read it from the start.
+    ///
+    /// If the data is no larger than `threshold` bytes it is kept in memory; otherwise it is
+    /// written to a temporary file, which is deleted when the returned `SeekableData` is dropped.
+    /// This does not limit the size of the data; see `Multipart::with_limits()`.
+    pub fn buffered_seekable(&mut self, threshold: usize) -> io::Result<SeekableData> {
+        // read one byte past the threshold to tell if the data is larger
+        let mut buf = Vec::new();
+        (&mut self.data).take(threshold as u64 + 1).read_to_end(&mut buf)?;
+
+        if buf.len() <= threshold {
+            return Ok(SeekableData {
+                len: buf.len() as u64,
+                body: SeekableBody::Memory(Cursor::new(buf)),
+            });
+        }
+
+        let dir = TempDir::new("multipart-rs")?;
+
+        let mut file = OpenOptions::new().read(true).write(true).create_new(true)
+            .open(dir.path().join("field"))?;
+
+        file.write_all(&buf)?;
+        let len = buf.len() as u64 + io::copy(&mut self.data, &mut file)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        Ok(SeekableData {
+            body: SeekableBody::File(file, dir),
+            len: len,
+        })
+    }
+
+    /// Create or truncate the file at `path` and copy the rest of the field data into it,
+    /// returning the number of bytes written.
+    ///
+    /// This does not limit the size of the file; use `data.save().size_limit()` with
+    /// `write_to()` or `with_path()` if you need to.
+    pub fn save_to_path<P: AsRef<Path>>(&mut self, path: P) -> io::Result<u64> {
+        let mut file = File::create(path)?;
+        io::copy(&mut self.data, &mut file)
+    }
+
+    /// Create or truncate the file at `path`, save the rest of the field data to it and return
+    /// the path with a read-only memory map of the file, e.g. to process a large upload in place.
+    ///
+    /// If the field has a `Content-Length` (`headers.content_length`) and isn't transfer-decoded,
+    /// the file is sized up front and written through the map; an error of kind `InvalidData` is
+    /// returned if the data doesn't match the declared length. Otherwise the data is copied to
+    /// the file, which is then mapped. Requires the `memmap2` feature.
+    ///
+    /// At most `max_len` bytes are saved: an error of kind `InvalidData` is returned if the
+    /// declared length or the data is longer, before the file is sized. The file is removed if
+    /// an error is returned after creating it.
+    ///
+    /// ### Warning: Shared Files
+    /// The map is undefined behavior to use if the file is modified by anything else while it
+    /// exists, including other processes; save to a path only this process uses.
+    ///
+    /// Some platforms can't map empty files, so saving an empty field may return an error.
+    #[cfg(feature = "memmap2")]
+    pub fn save_mmap<P: AsRef<Path>>(&mut self, path: P, max_len: u64)
+                                     -> io::Result<(PathBuf, Mmap)> {
+        let path = path.as_ref().to_owned();
+
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(true)
+            .open(&path)?;
+
+        match self.write_mmap(&mut file, max_len) {
+            Ok(map) => Ok((path, map)),
+            Err(e) => {
+                drop(file);
+                // the error from saving is more useful than one from cleaning up
+                let _ = fs::remove_file(&path);
+                Err(e)
+            },
+        }
+    }
+
+    #[cfg(feature = "memmap2")]
+    fn write_mmap(&mut self, file: &mut File, max_len: u64) -> io::Result<Mmap> {
+        fn too_long() -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, "field data is longer than `max_len`")
+        }
+
+        let declared = match self.headers.content_length {
+            Some(declared) if declared > 0 && !self.data.is_transfer_decoded() => declared,
+            _ => {
+                let copied = io::copy(&mut (&mut self.data).take(max_len.saturating_add(1)), file)?;
+
+                if copied > max_len {
+                    return Err(too_long());
+                }
+
+                return unsafe { Mmap::map(&*file) };
+            }
+        };
+
+        if declared > max_len {
+            return Err(too_long());
+        }
+
+        file.set_len(declared)?;
+        let mut map = unsafe { MmapMut::map_mut(&*file)? };
+
+        let mut filled = 0;
+
+        while filled < map.len() {
+            match self.data.read(&mut map[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        if filled < map.len() || self.data.read(&mut [0u8])? != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "field data doesn't match its `Content-Length`"));
+        }
+
+        map.flush()?;
+        map.make_read_only()
+    }
+
+    /// Read the next entry in the request.
+    pub fn next_entry(self) -> ReadEntryResult<M> {
+        self.data.into_inner().read_entry()
+    }
+
+    /// Update `self` as the next entry.
+    ///
+    /// Returns `Ok(Some(self))` if another entry was read, `Ok(None)` if the end of the body was
+    /// reached, and `Err(e)` for any errors that occur.
+    pub fn next_entry_inplace(&mut self) -> io::Result<Option<&mut Self>> where for<'a> &'a mut M: ReadEntry {
+        let multipart = self.data.take_inner();
+
+        match multipart.read_entry() {
+            Entry(entry) => {
+                *self = entry;
+                Ok(Some(self))
+            },
+            End(multipart) => {
+                self.data.give_inner(multipart);
+                Ok(None)
+            },
+            Error(multipart, err) => {
+                self.data.give_inner(multipart);
+                Err(err)
+            }
+        }
+    }
+}
+
+/// File signatures recognized by `MultipartField::sniff_content_type()`.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1A\n", "image/png"),
+    (b"\xFF\xD8\xFF", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    // empty archive
+    (b"PK\x05\x06", "application/zip"),
+    // spanned archive
+    (b"PK\x07\x08", "application/zip"),
+];
+
+const MAX_SIGNATURE_LEN: usize = 8;
+
+fn sniff_signature(data: &[u8]) -> Option<Mime> {
+    SIGNATURES.iter()
+        .find(|&&(signature, _)| data.starts_with(signature))
+        .map(|&(_, mime)| mime.parse().expect("invalid MIME type in `SIGNATURES`"))
+}
+
+/// The data of a field in a `multipart/form-data` request.
+///
+/// You can read it to EOF, or use the `save()` adaptor to save it to disk/memory.
+#[derive(Debug)]
+pub struct MultipartData<M> {
+    inner: Option<M>,
+    transfer_encoding: Option<String>,
+    /// Decodes the `transfer_encoding`, with `Multipart::with_transfer_decoding()`.
+    decoder: Option<TransferDecoder>,
+    /// Whether any of the data was read undecoded with `MultipartField::raw_body()`.
+    raw_read: bool,
+    timer: ReadTimer,
+}
+
+/// Measures how long reading a field's data takes, with the `timing` feature; otherwise
+/// does nothing.
+#[derive(Debug, Default)]
+struct ReadTimer {
+    #[cfg(feature = "timing")]
+    started: Option<Instant>,
+    #[cfg(feature = "timing")]
+    elapsed: Option<Duration>,
+}
+
+impl ReadTimer {
+    /// Start timing, if this is the first read.
+    #[cfg(feature = "timing")]
+    fn start(&mut self) {
+        if self.started.is_none() {
+            self.started = Some(Instant::now());
+        }
+    }
+
+    /// Stop timing if the read reached EOF.
+    #[cfg(feature = "timing")]
+    fn finish(&mut self, eof: bool) {
+        if let (true, None, Some(started)) = (eof, self.elapsed, self.started) {
+            self.elapsed = Some(started.elapsed());
+        }
+    }
+
+    #[cfg(not(feature = "timing"))]
+    #[inline(always)]
+    fn start(&mut self) {}
+
+    #[cfg(not(feature = "timing"))]
+    #[inline(always)]
+    fn finish(&mut self, _eof: bool) {}
+}
+
+const DATA_INNER_ERR: &'static str = "MultipartFile::inner taken and not replaced; this is likely \
+                                      caused by a logic error in `multipart` or by resuming after \
+                                      a previously caught panic.\nPlease open an issue with the \
+                                      relevant backtrace and debug logs at \
+                                      https://github.com/abonander/multipart";
+
+impl<M> MultipartData<M> where M: ReadEntry {
+    /// Get a builder type which can save the field with or without a size limit.
+    pub fn save(&mut self) -> SaveBuilder<&mut Self> {
+        SaveBuilder::new(self)
+    }
+
+    /// Convert this into a `futures::Stream` of chunks of the field data.
+    ///
+    /// See `DataStream` for more info, in particular about blocking.
+    #[cfg(feature = "async-bridge")]
+    pub fn into_stream(self) -> DataStream<M> {
+        DataStream { data: self }
+    }
+
+    /// Read the rest of the field data into `buf` like `Read::read_to_end()`, first reserving
+    /// capacity for `hint` more bytes to avoid reallocating while reading a large field.
+    ///
+    /// The hint only affects performance; any amount of data is read correctly. Don't pass
+    /// a size declared by the client without capping it, since the memory is reserved up front.
+    pub fn read_to_end_with_hint(&mut self, buf: &mut Vec<u8>, hint: usize) -> io::Result<usize> {
+        buf.reserve(hint);
+        self.read_to_end(buf)
+    }
+
+    /// Get an iterator over the lines of the rest of the field data, like `BufRead::lines()`,
+    /// but which returns an error of kind `InvalidData` for a line longer than `max_line` bytes
+    /// (not counting the line ending) instead of buffering it.
+    ///
+    /// See `LinesCapped` for more info.
+    pub fn lines_capped(&mut self, max_line: usize) -> LinesCapped<M> {
+        LinesCapped {
+            data: self,
+            max_line: max_line,
+            done: false,
+        }
+    }
+
+    /// Get an iterator over the characters of the rest of the field data, validating it as
+    /// UTF-8 as it is read, so a large text field doesn't have to be buffered to be validated.
+    ///
+    /// See `Utf8Chars` for more info.
+    pub fn utf8_chars(&mut self) -> Utf8Chars<M> {
+        Utf8Chars {
+            data: self,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Read the rest of the field data and deserialize it from JSON, e.g. for a metadata part
+    /// sent alongside file uploads.
+    ///
+    /// The data is read like any other, so it counts towards the `Limits` set on the
+    /// `Multipart`, and exceeding them returns the same error. Invalid JSON, or JSON which
+    /// doesn't match `T`, returns an error of kind `InvalidData`. Requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn read_json<T: DeserializeOwned>(&mut self) -> io::Result<T> {
+        serde_json::from_reader(self).map_err(io::Error::from)
+    }
+
+    /// Read the rest of the field data into a `Bytes`, which can be cheaply cloned and shared,
+    /// e.g. to pass to other tasks.
+    ///
+    /// The data is read like any other, so it counts towards the `Limits` set on the
+    /// `Multipart`, and exceeding them returns the same error. Requires the `bytes` feature.
+    #[cfg(feature = "bytes")]
+    pub fn read_to_bytes(&mut self) -> io::Result<Bytes> {
+        let mut data = Vec::new();
+        self.read_to_end(&mut data)?;
+        Ok(Bytes::from(data))
+    }
+
+    /// How long it took to read the field data, from the first read to the end of the field,
+    /// or `None` if it hasn't been read to the end (yet).
+    ///
+    /// Data skipped by reading the next field isn't timed. Requires the `timing` feature.
+    #[cfg(feature = "timing")]
+    pub fn read_duration(&self) -> Option<Duration> {
+        self.timer.elapsed
+    }
+
+    /// The `Content-Transfer-Encoding` of the field, if supplied; the same as
+    /// `FieldHeaders::transfer_encoding`.
+    pub fn transfer_encoding(&self) -> Option<&str> {
+        self.transfer_encoding.as_ref().map(|encoding| &**encoding)
+    }
+
+    /// Returns `true` if the data read is decoded from the field's `Content-Transfer-Encoding`,
+    /// or `false` if it is passed through as sent.
+    ///
+    /// This is `true` for `base64` and `quoted-printable` fields with
+    /// `Multipart::with_transfer_decoding()`. It is `false` for fields with no encoding, with an
+    /// identity encoding (`7bit`, `8bit` or `binary`), or with one passed through by
+    /// `UnknownEncoding::PassThrough`, and for all fields when decoding is off.
+    pub fn is_transfer_decoded(&self) -> bool {
+        self.decoder.is_some()
+    }
+
+    /// Take the inner `Multipart` or `&mut Multipart`
+    pub fn into_inner(self) -> M {
+        self.inner.expect(DATA_INNER_ERR)
+    }
+
+    /// Set the minimum buffer size that `BufRead::fill_buf(self)` will return
+    /// until the end of the stream is reached. Set this as small as you can tolerate
+    /// to minimize `read()` calls (`read()` won't be called again until the buffer
+    /// is smaller than this).
+    ///
+    /// This value is reset between fields.
+    pub fn set_min_buf_size(&mut self, min_buf_size: usize) {
+        self.inner_mut().set_min_buf_size(min_buf_size)
+    }
+
+    fn inner_ref(&self) -> &M {
+        self.inner.as_ref().expect(DATA_INNER_ERR)
+    }
+
+    fn inner_mut(&mut self) -> &mut M {
+        self.inner.as_mut().expect(DATA_INNER_ERR)
+    }
+
+    fn take_inner(&mut self) -> M {
+        self.inner.take().expect(DATA_INNER_ERR)
+    }
+
+    fn give_inner(&mut self, inner: M) {
+        self.inner = Some(inner);
+    }
+
+    fn check_not_raw_read(&self) -> io::Result<()> {
+        if self.raw_read && self.decoder.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "field data was already read undecoded with `raw_body()`"));
+        }
+
+        Ok(())
+    }
+}
+
+impl<M: ReadEntry> Read for MultipartData<M> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>{
+        self.check_not_raw_read()?;
+        self.timer.start();
+
+        let read = {
+            let source = self.inner.as_mut().expect(DATA_INNER_ERR).source_mut();
+
+            match self.decoder {
+                Some(ref mut decoder) => decoder.read(source, buf)?,
+                None => source.read(buf)?,
+            }
+        };
+
+        self.timer.finish(read == 0 && !buf.is_empty());
+        Ok(read)
+    }
+}
+
+/// In this implementation, `fill_buf()` can return more data with each call.
+///
+/// Use `set_min_buf_size()` if you require a minimum buffer length.
+impl<M: ReadEntry> BufRead for MultipartData<M> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check_not_raw_read()?;
+        self.timer.start();
+
+        let source = self.inner.as_mut().expect(DATA_INNER_ERR).source_mut();
+
+        let buf = match self.decoder {
+            Some(ref mut decoder) => decoder.fill_buf(source)?,
+            None => source.fill_buf()?,
+        };
+
+        self.timer.finish(buf.is_empty());
+        Ok(buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self.decoder {
+            Some(ref mut decoder) => decoder.consume(amt),
+            None => self.inner.as_mut().expect(DATA_INNER_ERR).source_mut().consume(amt),
+        }
+    }
+}
+
+/// The data of a field exactly as sent, returned by `MultipartField::raw_body()`.
+///
+/// For a field decoded with `Multipart::with_transfer_decoding()`, reading returns an error of
+/// kind `InvalidInput` if some of the decoded data was already read, and reading the decoded
+/// data returns one once this has been read from.
+#[derive(Debug)]
+pub struct RawBody<'a, M: 'a> {
+    data: &'a mut MultipartData<M>,
+}
+
+impl<'a, M: ReadEntry + 'a> RawBody<'a, M> {
+    fn source_mut(&mut self) -> io::Result<&mut M::Source> {
+        if self.data.decoder.as_ref().map_or(false, |decoder| decoder.is_started()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "field data was already read decoded"));
+        }
+
+        self.data.raw_read = true;
+        Ok(self.data.inner_mut().source_mut())
+    }
+}
+
+impl<'a, M: ReadEntry + 'a> Read for RawBody<'a, M> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.source_mut()?.read(buf)
+    }
+}
+
+impl<'a, M: ReadEntry + 'a> BufRead for RawBody<'a, M> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.source_mut()?.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.data.inner_mut().source_mut().consume(amt)
+    }
+}
+
+/// An iterator over the lines of a field's data, returned by `MultipartData::lines_capped()`.
+///
+/// Lines are split on `\n` or `\r\n`, which is removed, and must be valid UTF-8. The last line
+/// ends with the field's data, which is never read past. After an error, the iterator returns
+/// `None`, since the rest of an over-long line would otherwise be read as the next line.
+#[derive(Debug)]
+pub struct LinesCapped<'a, M: 'a> {
+    data: &'a mut MultipartData<M>,
+    max_line: usize,
+    done: bool,
+}
+
+impl<'a, M: ReadEntry + 'a> LinesCapped<'a, M> {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = Vec::new();
+
+        // allow for a `\r\n`; anything longer is over the limit
+        let limit = (self.max_line as u64).saturating_add(2);
+
+        if (&mut *self.data).take(limit).read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+
+        if line.ends_with(b"\n") {
+            line.pop();
+
+            if line.ends_with(b"\r") {
+                line.pop();
+            }
+        }
+
+        if line.len() > self.max_line {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("line longer than {} bytes", self.max_line)));
+        }
+
+        String::from_utf8(line).map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl<'a, M: ReadEntry + 'a> Iterator for LinesCapped<'a, M> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_line() {
+            Ok(Some(line)) => Some(Ok(line)),
+            Ok(None) => { self.done = true; None },
+            Err(e) => { self.done = true; Some(Err(e)) },
+        }
+    }
+}
+
+/// An iterator over the characters of a field's data, returned by `MultipartData::utf8_chars()`.
+///
+/// The first invalid or truncated UTF-8 sequence returns an error of kind `InvalidData` wrapping
+/// an `InvalidUtf8`, after which the iterator returns `None`.
+#[derive(Debug)]
+pub struct Utf8Chars<'a, M: 'a> {
+    data: &'a mut MultipartData<M>,
+    /// The number of bytes decoded so far.
+    offset: u64,
+    done: bool,
+}
+
+impl<'a, M: ReadEntry + 'a> Utf8Chars<'a, M> {
+    /// The number of bytes of the field data decoded so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    fn read_char(&mut self) -> io::Result<Option<char>> {
+        let offset = self.offset;
+        let mut bytes = [0u8; 4];
+        let mut len = 0;
+        let mut width = 1;
+
+        // a sequence may be split between reads of the source
+        while len < width {
+            let amt = {
+                let buf = self.data.fill_buf()?;
+
+                if buf.is_empty() {
+                    if len == 0 {
+                        return Ok(None);
+                    }
+
+                    return Err(invalid_utf8(offset));
+                }
+
+                if len == 0 {
+                    width = utf8_width(buf[0]);
+
+                    if width == 0 {
+                        return Err(invalid_utf8(offset));
+                    }
+                }
+
+                let amt = cmp::min(width - len, buf.len());
+                bytes[len .. len + amt].copy_from_slice(&buf[..amt]);
+                amt
+            };
+
+            self.data.consume(amt);
+            len += amt;
+        }
+
+        match str::from_utf8(&bytes[..len]) {
+            Ok(s) => {
+                self.offset += len as u64;
+                Ok(s.chars().next())
+            },
+            Err(_) => Err(invalid_utf8(offset)),
+        }
+    }
+}
+
+impl<'a, M: ReadEntry + 'a> Iterator for Utf8Chars<'a, M> {
+    type Item = io::Result<char>;
+
+    fn next(&mut self) -> Option<io::Result<char>> {
+        if self.done {
+            return None;
+        }
+
+        match self.read_char() {
+            Ok(Some(c)) => Some(Ok(c)),
+            Ok(None) => { self.done = true; None },
+            Err(e) => { self.done = true; Some(Err(e)) },
+        }
+    }
+}
+
+/// The field data read by `Utf8Chars` wasn't valid UTF-8.
+///
+/// Returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidUtf8 {
+    /// The offset in the field data of the first byte of the invalid sequence.
+    pub offset: u64,
+}
+
+impl fmt::Display for InvalidUtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.description(), self.offset)
+    }
+}
+
+impl Error for InvalidUtf8 {
+    fn description(&self) -> &str {
+        "invalid UTF-8 sequence in field data"
+    }
+}
+
+fn invalid_utf8(offset: u64) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, InvalidUtf8 { offset: offset })
+}
+
+/// The length of the UTF-8 sequence starting with `first`, or 0 if it can't start one.
+fn utf8_width(first: u8) -> usize {
+    if first < 0x80 {
+        1
+    } else if first < 0xC2 {
+        // a continuation byte, or the start of an overlong encoding
+        0
+    } else if first < 0xE0 {
+        2
+    } else if first < 0xF0 {
+        3
+    } else if first < 0xF5 {
+        4
+    } else {
+        0
+    }
+}
+
+/// The data of a field buffered by `MultipartField::buffered_seekable()`.
+///
+/// Implements `Read + Seek` over the field data, which is held in memory or in a temporary
+/// file depending on its size.
+#[derive(Debug)]
+pub struct SeekableData {
+    body: SeekableBody,
+    len: u64,
+}
+
+#[derive(Debug)]
+enum SeekableBody {
+    Memory(Cursor<Vec<u8>>),
+    // the file is closed before its directory is deleted
+    File(File, TempDir),
+}
+
+impl SeekableData {
+    /// Get the length of the field data.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the field data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if the data is held in memory instead of a temporary file.
+    pub fn is_in_memory(&self) -> bool {
+        match self.body {
+            SeekableBody::Memory(_) => true,
+            SeekableBody::File(..) => false,
+        }
+    }
+
+    /// Seek back to the start of the data.
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.seek(SeekFrom::Start(0)).map(|_| ())
+    }
+}
+
+impl Read for SeekableData {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.body {
+            SeekableBody::Memory(ref mut cursor) => cursor.read(buf),
+            SeekableBody::File(ref mut file, _) => file.read(buf),
+        }
+    }
+}
+
+impl Seek for SeekableData {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self.body {
+            SeekableBody::Memory(ref mut cursor) => cursor.seek(pos),
+            SeekableBody::File(ref mut file, _) => file.seek(pos),
+        }
+    }
+}
+
+/// A `futures::Stream` of the chunks of a field's data, backed by the synchronous reader;
+/// created with `MultipartData::into_stream()`. Enabled with the `async-bridge` feature.
+///
+/// ### Note: Blocking
+/// Each call to `poll()` reads from the request body directly and blocks until data is
+/// available, so it is never `NotReady`. Polling it on an event loop will stall the loop
+/// while waiting on the client; use a thread or pool dedicated to blocking work (e.g. with
+/// `futures-cpupool`) if the request body may be slow.
+///
+/// An error of kind `WouldBlock` from the body is returned as an error as well, since the
+/// stream has no way to be woken up when the body is ready.
+#[cfg(feature = "async-bridge")]
+#[derive(Debug)]
+pub struct DataStream<M> {
+    data: MultipartData<M>,
+}
+
+#[cfg(feature = "async-bridge")]
+impl<M: ReadEntry> DataStream<M> {
+    /// Get back the field data, e.g. to read the next entry.
+    pub fn into_inner(self) -> MultipartData<M> {
+        self.data
+    }
+}
+
+#[cfg(feature = "async-bridge")]
+impl<M: ReadEntry> Stream for DataStream<M> {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, io::Error> {
+        let chunk;
+
+        loop {
+            match self.data.fill_buf() {
+                Ok(buf) => {
+                    chunk = Bytes::from(buf);
+                    break;
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        if chunk.is_empty() {
+            return Ok(Async::Ready(None));
+        }
+
+        self.data.consume(chunk.len());
+        Ok(Async::Ready(Some(chunk)))
+    }
+}
+
+fn normalize_newlines(text: String, newline: Normalize) -> String {
+    let newline = match newline {
+        Normalize::CrLf => "\r\n",
+        Normalize::Lf => "\n",
+        Normalize::None => return text,
+    };
+
+    let mut normalized = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+
+                normalized.push_str(newline);
+            },
+            '\n' => normalized.push_str(newline),
+            c => normalized.push(c),
+        }
+    }
+
+    normalized
+}
+
+fn split_once(s: &str, delim: char) -> Option<(&str, &str)> {
+    s.find(delim).map(|idx| s.split_at(idx))
+}
+
+fn trim_quotes(s: &str) -> &str {
+    s.trim_matches('"')
+}
+
+fn trim_ascii_whitespace(s: &str) -> &str {
+    s.trim_matches(|c: char| match c {
+        ' ' | '\t' | '\n' | '\r' | '\x0C' => true,
+        _ => false,
+    })
+}
+
+/// Returns `true` if `s` contains an ASCII control character (`0x00 - 0x1F` or `0x7F`).
+fn has_control_char(s: &str) -> bool {
+    s.bytes().any(|b| b < 0x20 || b == 0x7F)
+}
+
+fn is_param_space(c: char) -> bool {
+    c == ';' || c == ' ' || c == '\t'
+}
+
+/// Parse the `; key=value` parameters of a header value, where `value` may be a quoted string.
+fn parse_params(mut s: &str) -> Vec<(&str, String)> {
+    let mut params = Vec::new();
+
+    loop {
+        s = s.trim_left_matches(is_param_space);
 
-    /// Update `self` as the next entry.
-    ///
-    /// Returns `Ok(Some(self))` if another entry was read, `Ok(None)` if the end of the body was
-    /// reached, and `Err(e)` for any errors that occur.
-    pub fn next_entry_inplace(&mut self) -> io::Result<Option<&mut Self>> where for<'a> &'a mut M: ReadEntry {
-        let multipart = self.data.take_inner();
+        if s.is_empty() {
+            return params;
+        }
 
-        match multipart.read_entry() {
-            Entry(entry) => {
-                *self = entry;
-                Ok(Some(self))
-            },
-            End(multipart) => {
-                self.data.give_inner(multipart);
-                Ok(None)
-            },
-            Error(multipart, err) => {
-                self.data.give_inner(multipart);
-                Err(err)
-            }
+        let key_end = s.find(|c: char| c == '=' || c == ';').unwrap_or(s.len());
+        let key = trim_ascii_whitespace(&s[..key_end]);
+        s = &s[key_end..];
+
+        // skip parameters without a value
+        if !s.starts_with('=') {
+            continue;
         }
+
+        s = s[1..].trim_left_matches(|c: char| c == ' ' || c == '\t');
+
+        let val = if s.starts_with('"') {
+            let (val, rest) = parse_quoted(&s[1..]);
+            s = rest;
+            val
+        } else {
+            let (val, rest) = split_once(s, ';').unwrap_or((s, ""));
+            s = rest;
+            trim_ascii_whitespace(val).to_owned()
+        };
+
+        params.push((key, val));
     }
 }
 
-/// The data of a field in a `multipart/form-data` request.
+/// Parse a quoted string up to its closing quote (the opening quote already being stripped),
+/// returning the unescaped string and the rest of `s`.
 ///
-/// You can read it to EOF, or use the `save()` adaptor to save it to disk/memory.
-#[derive(Debug)]
-pub struct MultipartData<M> {
-    inner: Option<M>,
+/// A backslash only escapes `"` or `\`, and is otherwise kept; some clients send filenames
+/// which are Windows paths without escaping them.
+fn parse_quoted(s: &str) -> (String, &str) {
+    let mut val = String::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return (val, &s[idx + 1..]),
+            '\\' => match chars.peek().map(|&(_, next)| next) {
+                Some(next) if next == '"' || next == '\\' => {
+                    val.push(next);
+                    chars.next();
+                },
+                _ => val.push(c),
+            },
+            _ => val.push(c),
+        }
+    }
+
+    // unterminated quoted string
+    (val, "")
 }
 
-const DATA_INNER_ERR: &'static str = "MultipartFile::inner taken and not replaced; this is likely \
-                                      caused by a logic error in `multipart` or by resuming after \
-                                      a previously caught panic.\nPlease open an issue with the \
-                                      relevant backtrace and debug logs at \
-                                      https://github.com/abonander/multipart";
+/// Decode extended parameters (`key*=charset'language'value`) and join continuations
+/// (`key*0=...; key*1*=...`) as per [IETF RFC 2231][rfc2231], returning plain `(key, value)`
+/// pairs.
+///
+/// An extended parameter replaces a plain parameter of the same name, unless it can't be
+/// decoded, in which case it is dropped.
+///
+/// [rfc2231]: https://tools.ietf.org/html/rfc2231
+fn collapse_ext_params(raw: Vec<(&str, String)>) -> Vec<(String, String)> {
+    let mut params: Vec<(String, String)> = Vec::new();
+    let mut extended = Vec::new();
+    // (name, section, is encoded, value)
+    let mut sections: Vec<(&str, u32, bool, String)> = Vec::new();
+
+    for (key, val) in raw {
+        let (base, encoded) = if key.ends_with('*') {
+            (&key[..key.len() - 1], true)
+        } else {
+            (key, false)
+        };
 
-impl<M> MultipartData<M> where M: ReadEntry {
-    /// Get a builder type which can save the field with or without a size limit.
-    pub fn save(&mut self) -> SaveBuilder<&mut Self> {
-        SaveBuilder::new(self)
-    }
+        if let Some((name, section)) = split_once(base, '*') {
+            if let Ok(section) = section[1..].parse() {
+                sections.push((name, section, encoded, val));
+                continue;
+            }
+        }
 
-    /// Take the inner `Multipart` or `&mut Multipart`
-    pub fn into_inner(self) -> M {
-        self.inner.expect(DATA_INNER_ERR)
+        if encoded {
+            if let Some(decoded) = decode_ext_value(&val) {
+                extended.push((base.to_owned(), decoded));
+            }
+        } else {
+            params.push((key.to_owned(), val));
+        }
     }
 
-    /// Set the minimum buffer size that `BufRead::fill_buf(self)` will return
-    /// until the end of the stream is reached. Set this as small as you can tolerate
-    /// to minimize `read()` calls (`read()` won't be called again until the buffer
-    /// is smaller than this).
-    ///
-    /// This value is reset between fields.
-    pub fn set_min_buf_size(&mut self, min_buf_size: usize) {
-        self.inner_mut().set_min_buf_size(min_buf_size)
-    }
+    sections.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
 
-    fn inner_mut(&mut self) -> &mut M {
-        self.inner.as_mut().expect(DATA_INNER_ERR)
-    }
+    let mut sections = sections.into_iter().peekable();
 
-    fn take_inner(&mut self) -> M {
-        self.inner.take().expect(DATA_INNER_ERR)
+    while let Some((name, _, encoded, val)) = sections.next() {
+        let mut bytes = Vec::new();
+
+        // only the first section declares the charset
+        let mut valid = if encoded {
+            ext_value_data(&val).map_or(false, |data| pct_decode(data, &mut bytes).is_some())
+        } else {
+            bytes.extend_from_slice(val.as_bytes());
+            true
+        };
+
+        while sections.peek().map_or(false, |next| next.0 == name) {
+            let (_, _, encoded, val) = sections.next().unwrap();
+
+            if encoded {
+                valid &= pct_decode(&val, &mut bytes).is_some();
+            } else {
+                bytes.extend_from_slice(val.as_bytes());
+            }
+        }
+
+        if let (true, Ok(joined)) = (valid, String::from_utf8(bytes)) {
+            extended.push((name.to_owned(), joined));
+        }
     }
 
-    fn give_inner(&mut self, inner: M) {
-        self.inner = Some(inner);
+    for (name, val) in extended {
+        match params.iter().position(|&(ref key, _)| key.eq_ignore_ascii_case(&name)) {
+            Some(idx) => params[idx].1 = val,
+            None => params.push((name, val)),
+        }
     }
+
+    params
 }
 
-impl<M: ReadEntry> Read for MultipartData<M> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>{
-        self.inner_mut().source_mut().read(buf)
-    }
+fn take_param(params: &mut Vec<(String, String)>, name: &str) -> Option<String> {
+    params.iter().position(|&(ref key, _)| key.eq_ignore_ascii_case(name))
+        .map(|idx| params.remove(idx).1)
 }
 
-/// In this implementation, `fill_buf()` can return more data with each call.
-///
-/// Use `set_min_buf_size()` if you require a minimum buffer length.
-impl<M: ReadEntry> BufRead for MultipartData<M> {
-    fn fill_buf(&mut self) -> io::Result<&[u8]> {
-        self.inner_mut().source_mut().fill_buf()
-    }
+/// Decode an extended parameter value (`charset'language'value`), returning `None` if it is
+/// malformed or uses an unsupported charset.
+fn decode_ext_value(ext_val: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    try_opt!(pct_decode(try_opt!(ext_value_data(ext_val)), &mut bytes));
+    String::from_utf8(bytes).ok()
+}
 
-    fn consume(&mut self, amt: usize) {
-        self.inner_mut().source_mut().consume(amt)
+/// Get the encoded data of an extended parameter value if the charset is supported.
+fn ext_value_data(ext_val: &str) -> Option<&str> {
+    let mut parts = ext_val.splitn(3, '\'');
+    let charset = try_opt!(parts.next());
+    let _language = try_opt!(parts.next());
+    let data = try_opt!(parts.next());
+
+    if charset.eq_ignore_ascii_case("UTF-8") || charset.eq_ignore_ascii_case("US-ASCII") {
+        Some(data)
+    } else {
+        None
     }
 }
 
-fn split_once(s: &str, delim: char) -> Option<(&str, &str)> {
-    s.find(delim).map(|idx| s.split_at(idx))
+fn pct_decode(encoded: &str, out: &mut Vec<u8>) -> Option<()> {
+    let mut iter = encoded.chars();
+
+    while let Some(c) = iter.next() {
+        if c == '%' {
+            let high = try_opt!(iter.next().and_then(|c| c.to_digit(16)));
+            let low = try_opt!(iter.next().and_then(|c| c.to_digit(16)));
+            out.push((high << 4 | low) as u8);
+        } else {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+    }
+
+    Some(())
 }
 
-fn trim_quotes(s: &str) -> &str {
-    s.trim_matches('"')
+/// Decode an `application/x-www-form-urlencoded` value: `+` is a space and `%xx` an escaped byte.
+///
+/// A `%` which isn't followed by two hex digits is kept as-is.
+fn url_decode(encoded: &str) -> io::Result<String> {
+    let bytes = encoded.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let (high, low) = (hex_digit(bytes.get(i + 1)), hex_digit(bytes.get(i + 2)));
+
+        match (bytes[i], high, low) {
+            (b'+', _, _) => out.push(b' '),
+            (b'%', Some(high), Some(low)) => {
+                out.push((high << 4 | low) as u8);
+                i += 2;
+            },
+            (b, _, _) => out.push(b),
+        }
+
+        i += 1;
+    }
+
+    String::from_utf8(out).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
 
-/// Get the string after `needle` in `haystack`, stopping before `end_val_delim`
-fn get_str_after<'a>(needle: &str, end_val_delim: char, haystack: &'a str) -> Option<(&'a str, &'a str)> {
-    let val_start_idx = try_opt!(haystack.find(needle)) + needle.len();
-    let val_end_idx = haystack[val_start_idx..].find(end_val_delim)
-        .map_or(haystack.len(), |end_idx| end_idx + val_start_idx);
-    Some((&haystack[val_start_idx..val_end_idx], &haystack[val_end_idx..]))
+fn hex_digit(byte: Option<&u8>) -> Option<u32> {
+    byte.and_then(|&b| (b as char).to_digit(16))
 }
 
 fn io_str_utf8(buf: &[u8]) -> io::Result<&str> {
@@ -380,11 +1841,23 @@ pub trait ReadEntry: PrivReadEntry + Sized {
                    field_headers);
         });
 
+        let transfer_encoding = field_headers.transfer_encoding.clone();
+
+        let decoder = if self.transfer_decoding() {
+            transfer_encoding.as_ref().and_then(|encoding| TransferDecoder::for_encoding(encoding))
+        } else {
+            None
+        };
+
         Entry(
             MultipartField {
                 headers: field_headers,
                 data: MultipartData {
                     inner: Some(self),
+                    transfer_encoding: transfer_encoding,
+                    decoder: decoder,
+                    raw_read: false,
+                    timer: ReadTimer::default(),
                 },
             }
         )
@@ -410,9 +1883,41 @@ pub trait PrivReadEntry {
     /// Returns `true` if the last boundary was read, `false` otherwise.
     fn consume_boundary(&mut self) -> io::Result<bool>;
 
-    fn read_headers(&mut self) -> Result<FieldHeaders, io::Error> {
-        FieldHeaders::read_from(self.source_mut())
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    fn read_headers(&mut self) -> Result<FieldHeaders, io::Error>;
+
+    /// The line ending to convert text fields to in `MultipartField::read_text()`.
+    fn text_newlines(&self) -> Normalize {
+        Normalize::None
+    }
+
+    /// The decoder registered for `charset`, used by `MultipartField::read_text()`.
+    fn charset_decoder(&self, _charset: &str) -> Option<Arc<CharsetDecoder>> {
+        None
+    }
+
+    /// Whether `MultipartField::read_text()` percent-decodes text fields.
+    fn urlencoded_text(&self) -> bool {
+        false
+    }
+
+    /// Whether `MultipartField::read_text()` strips a leading UTF-8 BOM from text fields.
+    fn strip_bom(&self) -> bool {
+        false
+    }
+
+    /// How deeply this body is nested in the request, for `MultipartField::read_nested()`.
+    fn nesting_depth(&self) -> usize {
+        0
+    }
+
+    /// The `Limits` of bodies nested in this one with `MultipartField::read_nested()`.
+    fn nested_limits(&self) -> Limits {
+        Limits::default()
+    }
+
+    /// Whether field data is decoded from its `Content-Transfer-Encoding`.
+    fn transfer_decoding(&self) -> bool {
+        false
     }
 
     fn read_to_string(&mut self) -> io::Result<String> {
@@ -439,6 +1944,56 @@ impl<'a, M: ReadEntry> PrivReadEntry for &'a mut M {
     fn consume_boundary(&mut self) -> io::Result<bool> {
         (**self).consume_boundary()
     }
+
+    fn read_headers(&mut self) -> Result<FieldHeaders, io::Error> {
+        (**self).read_headers()
+    }
+
+    fn text_newlines(&self) -> Normalize {
+        (**self).text_newlines()
+    }
+
+    fn charset_decoder(&self, charset: &str) -> Option<Arc<CharsetDecoder>> {
+        (**self).charset_decoder(charset)
+    }
+
+    fn urlencoded_text(&self) -> bool {
+        (**self).urlencoded_text()
+    }
+
+    fn strip_bom(&self) -> bool {
+        (**self).strip_bom()
+    }
+
+    fn nesting_depth(&self) -> usize {
+        (**self).nesting_depth()
+    }
+
+    fn nested_limits(&self) -> Limits {
+        (**self).nested_limits()
+    }
+
+    fn transfer_decoding(&self) -> bool {
+        (**self).transfer_decoding()
+    }
+}
+
+/// Parse the field headers from `source` according to `opts`, for `PrivReadEntry` implementors.
+pub fn read_field_headers<R: BufRead>(source: &mut R, opts: &HeaderOpts) -> io::Result<FieldHeaders> {
+    FieldHeaders::read_from(source, opts)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Record the name of the field in `seen`, returning an error if it was already seen.
+///
+/// Not exposed.
+pub fn check_unique_name(seen: &mut HashSet<ArcStr>, headers: &FieldHeaders) -> io::Result<()> {
+    if seen.insert(headers.name.clone()) {
+        return Ok(());
+    }
+
+    Err(io::Error::new(io::ErrorKind::InvalidData,
+                       ParseHeaderError::DuplicateField(headers.name.to_string())))
 }
 
 /// Ternary result type returned by `ReadEntry::next_entry()`,
@@ -543,6 +2098,37 @@ quick_error! {
         TooLarge {
             description("field headers section ridiculously long or missing trailing CRLF-CRLF")
         }
+        /// The header block ended in a bare LF, without `Multipart::with_lenient_line_endings()`
+        BareLineFeed {
+            description("field headers section not terminated by CRLF-CRLF")
+        }
+        /// The `filename` parameter exceeded `Multipart::with_filename_limit()`
+        FilenameTooLong(len: usize, limit: usize) {
+            display(x) -> ("{}: {} bytes (limit: {})", x.description(), len, limit)
+            description("field filename too long")
+        }
+        /// The field had a filename but an empty name, with `EmptyNamePolicy::Error`
+        EmptyName(filename: String) {
+            display(x) -> ("{}: {:?}", x.description(), filename)
+            description("field has a filename but an empty name")
+        }
+        /// The field's `Content-Transfer-Encoding` isn't supported, with
+        /// `UnknownEncoding::Error`
+        UnknownEncoding(encoding: String) {
+            display(x) -> ("{}: {:?}", x.description(), encoding)
+            description("unsupported `Content-Transfer-Encoding`")
+        }
+        /// The field's name or filename contained an ASCII control character, with
+        /// `Multipart::with_reject_control_chars()`
+        IllegalControlChar(param: &'static str, value: String) {
+            display(x) -> ("{}: {} {:?}", x.description(), param, value)
+            description("control character in field name or filename")
+        }
+        /// A field with the same name was already read, with `Multipart::with_unique_field_names()`
+        DuplicateField(name: String) {
+            display(x) -> ("{}: {:?}", x.description(), name)
+            description("duplicate field name")
+        }
         /// IO error
         Io(err: io::Error) {
             description("an io error occurred while parsing the headers")
@@ -565,3 +2151,485 @@ fn test_find_header() {
     assert_eq!(find_header(&headers, "Content-Disposition").unwrap().val, "form-data");
     assert_eq!(find_header(&headers, "Content-Transfer-Encoding").unwrap().val, "binary");
 }
+
+#[test]
+fn test_parse_content_disp() {
+    fn parse(val: &str) -> ContentDisp {
+        ContentDisp::parse_required(&[StrHeader { name: "Content-Disposition", val }]).unwrap()
+    }
+
+    let disp = parse(r#"form-data; filename="a; b=\"c\".txt"; name=field"#);
+    assert_eq!(disp.field_name, "field");
+    assert_eq!(disp.filename.unwrap(), r#"a; b="c".txt"#);
+
+    // unknown escapes are kept as-is
+    let disp = parse(r#"form-data; name="path"; filename="C:\Users\\file.txt""#);
+    assert_eq!(disp.filename.unwrap(), r#"C:\Users\file.txt"#);
+
+    let disp = parse("form-data; name=\"file\"; filename=\"fallback.txt\"; \
+                      filename*=UTF-8''%E6%96%87%E4%BB%B6.txt");
+    assert_eq!(disp.filename.unwrap(), "\u{6587}\u{4EF6}.txt");
+
+    // an undecodable extended value falls back to `filename`
+    let disp = parse("form-data; name=\"file\"; filename=\"fallback.txt\"; filename*=latin1''%E9");
+    assert_eq!(disp.filename.unwrap(), "fallback.txt");
+}
+
+#[test]
+fn test_parse_content_disposition() {
+    let disp = parse_content_disposition("attachment").unwrap();
+    assert_eq!(disp.disposition_type, "attachment");
+    assert_eq!(disp.name, None);
+    assert_eq!(disp.filename, None);
+    assert!(disp.params.is_empty());
+
+    let disp = parse_content_disposition(
+        r#"inline ; NAME = "a;b" ; size=10;;creation-date="Wed, 12 Feb 1997 16:29:51 -0500""#
+    ).unwrap();
+    assert_eq!(disp.disposition_type, "inline");
+    assert_eq!(disp.name.unwrap(), "a;b");
+    assert_eq!(disp.params, [("size".to_owned(), "10".to_owned()),
+                             ("creation-date".to_owned(), "Wed, 12 Feb 1997 16:29:51 -0500".to_owned())]);
+
+    // RFC 2231 continuations, mixing encoded and plain sections, out of order
+    let disp = parse_content_disposition(
+        "attachment; filename*1=\" and \"; filename*2*=%E2%82%AC.txt; filename*0*=UTF-8'en'%C2%A3"
+    ).unwrap();
+    assert_eq!(disp.filename.unwrap(), "\u{A3} and \u{20AC}.txt");
+
+    // other extended parameters are decoded too, and an unterminated quote runs to the end
+    let disp = parse_content_disposition("form-data; title*=us-ascii''a%20b; name=\"open").unwrap();
+    assert_eq!(disp.name.unwrap(), "open");
+    assert_eq!(disp.params, [("title".to_owned(), "a b".to_owned())]);
+
+    assert_eq!(parse_content_disposition("; name=field").unwrap_err().kind(),
+               io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_read_to_end_with_hint() {
+    use server::Multipart;
+
+    let body: &[u8] = b"--boundary\r\n\
+                        Content-Disposition: form-data; name=\"field\"\r\n\r\n\
+                        field data\r\n\
+                        --boundary--";
+
+    // a hint that's too small or too large is harmless
+    for &hint in &[0, 4, 1024] {
+        let mut multipart = Multipart::with_body(body, "boundary");
+        let mut field = multipart.read_entry().unwrap().unwrap();
+
+        let mut buf = b"prefix ".to_vec();
+        assert_eq!(field.data.read_to_end_with_hint(&mut buf, hint).unwrap(), 10);
+        assert_eq!(buf, b"prefix field data");
+        assert!(buf.capacity() >= 7 + hint);
+    }
+}
+
+
+#[test]
+fn test_sniff_content_type() {
+    use server::Multipart;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR";
+
+    let mut body = b"--boundary\r\n\
+                     Content-Disposition: form-data; name=\"image\"; filename=\"image.txt\"\r\n\
+                     Content-Type: text/plain\r\n\r\n".to_vec();
+    body.extend_from_slice(PNG);
+    body.extend_from_slice(b"\r\n\
+                             --boundary\r\n\
+                             Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                             text\r\n\
+                             --boundary--");
+
+    let mut multipart = Multipart::with_body(&body[..], "boundary");
+
+    {
+        let mut field = multipart.read_entry().unwrap().unwrap();
+        assert_eq!(field.headers.content_type, Some(mime::TEXT_PLAIN));
+        assert_eq!(field.sniff_content_type().unwrap(), Some(mime::IMAGE_PNG));
+
+        // the sniffed bytes are read again
+        let mut data = Vec::new();
+        field.data.read_to_end(&mut data).unwrap();
+        assert_eq!(data, PNG);
+    }
+
+    let mut field = multipart.read_entry().unwrap().unwrap();
+    assert_eq!(field.sniff_content_type().unwrap(), None);
+    assert_eq!(field.read_text().unwrap(), "text");
+
+    assert_eq!(sniff_signature(b"GIF89a..."), "image/gif".parse().ok());
+    assert_eq!(sniff_signature(b"%PDF-1.4"), "application/pdf".parse().ok());
+    assert_eq!(sniff_signature(b"PK\x03\x04"), "application/zip".parse().ok());
+    assert_eq!(sniff_signature(b"\xFF\xD8\xFF\xE0"), Some(mime::IMAGE_JPEG));
+    assert_eq!(sniff_signature(b"PK"), None);
+}
+
+
+#[test]
+fn test_utf8_chars() {
+    use server::Multipart;
+    use super::InvalidUtf8;
+
+    let body: &[u8] = b"--boundary\r\n\
+                        Content-Disposition: form-data; name=\"valid\"\r\n\r\n\
+                        caf\xC3\xA9 \xE2\x82\xAC\r\n\
+                        --boundary\r\n\
+                        Content-Disposition: form-data; name=\"invalid\"\r\n\r\n\
+                        ab\xC3\xA9cd\xFFef\r\n\
+                        --boundary--";
+
+    let mut multipart = Multipart::with_body(body, "boundary");
+
+    {
+        let mut field = multipart.read_entry().unwrap().unwrap();
+        let text: String = field.data.utf8_chars().collect::<io::Result<_>>().unwrap();
+        assert_eq!(text, "caf\u{E9} \u{20AC}");
+    }
+
+    let mut field = multipart.read_entry().unwrap().unwrap();
+    let mut chars = field.data.utf8_chars();
+
+    assert_eq!(chars.by_ref().take(5).collect::<io::Result<String>>().unwrap(), "ab\u{E9}cd");
+    assert_eq!(chars.offset(), 6);
+
+    let err = chars.next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<InvalidUtf8>()),
+               Some(&InvalidUtf8 { offset: 6 }));
+
+    assert!(chars.next().is_none());
+}
+
+#[test]
+fn test_lines_capped() {
+    use server::Multipart;
+
+    let body: &[u8] = b"--boundary\r\n\
+                        Content-Disposition: form-data; name=\"events\"; filename=\"events.ndjson\"\r\n\
+                        Content-Type: application/x-ndjson\r\n\r\n\
+                        {\"id\":1}\n\
+                        {\"id\":2}\r\n\
+                        \n\
+                        {\"id\":3}\r\n\
+                        --boundary\r\n\
+                        Content-Disposition: form-data; name=\"long\"\r\n\r\n\
+                        {\"id\":4}\n\
+                        {\"id\":5,\"data\":\"too long\"}\n\
+                        {\"id\":6}\r\n\
+                        --boundary--";
+
+    let mut multipart = Multipart::with_body(body, "boundary");
+
+    {
+        let mut field = multipart.read_entry().unwrap().unwrap();
+        let lines: Vec<String> = field.data.lines_capped(8).collect::<io::Result<_>>().unwrap();
+        // the last line ends with the field
+        assert_eq!(lines, ["{\"id\":1}", "{\"id\":2}", "", "{\"id\":3}"]);
+    }
+
+    let mut field = multipart.read_entry().unwrap().unwrap();
+    let mut lines = field.data.lines_capped(8);
+
+    assert_eq!(lines.next().unwrap().unwrap(), "{\"id\":4}");
+    assert_eq!(lines.next().unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert!(lines.next().is_none());
+}
+
+#[test]
+fn test_raw_headers_order() {
+    use server::Multipart;
+
+    let body: &[u8] = b"--boundary\r\n\
+                        X-Signature: second\r\n\
+                        Content-Type: text/plain\r\n\
+                        content-disposition: form-data; name=\"signed\"\r\n\
+                        X-Signature: first\r\n\r\n\
+                        data\r\n\
+                        --boundary--";
+
+    let mut multipart = Multipart::with_body(body, "boundary");
+    let field = multipart.read_entry().unwrap().unwrap();
+
+    let raw: Vec<(&str, &str)> = field.headers.raw_headers().iter()
+        .map(|&(ref name, ref val)| (&**name, &**val))
+        .collect();
+
+    assert_eq!(raw, [
+        ("X-Signature", "second"),
+        ("Content-Type", "text/plain"),
+        ("content-disposition", "form-data; name=\"signed\""),
+        ("X-Signature", "first"),
+    ]);
+}
+
+#[test]
+fn test_buffered_seekable() {
+    use server::Multipart;
+
+    let mut data = b"\x89PNG\r\n\x1A\n".to_vec();
+    data.extend((0..4096u32).map(|i| (i % 251) as u8));
+
+    let mut body = Vec::new();
+    body.extend_from_slice(b"--boundary\r\n\
+                             Content-Disposition: form-data; name=\"image\"; filename=\"image.png\"\r\n\r\n");
+    body.extend_from_slice(&data);
+    body.extend_from_slice(b"\r\n\
+                             --boundary\r\n\
+                             Content-Disposition: form-data; name=\"small\"\r\n\r\n\
+                             small\r\n\
+                             --boundary--");
+
+    let mut multipart = Multipart::with_body(&body[..], "boundary");
+
+    let mut seekable = multipart.read_entry().unwrap().unwrap().buffered_seekable(1024).unwrap();
+    assert!(!seekable.is_in_memory());
+    assert_eq!(seekable.len(), data.len() as u64);
+
+    let mut magic = [0u8; 8];
+    seekable.read_exact(&mut magic).unwrap();
+    assert_eq!(sniff_signature(&magic).unwrap(), mime::IMAGE_PNG);
+
+    seekable.rewind().unwrap();
+    let mut read = Vec::new();
+    seekable.read_to_end(&mut read).unwrap();
+    assert_eq!(read, data);
+
+    let mut seekable = multipart.read_entry().unwrap().unwrap().buffered_seekable(1024).unwrap();
+    assert!(seekable.is_in_memory());
+
+    seekable.seek(SeekFrom::Start(2)).unwrap();
+    let mut read = String::new();
+    seekable.read_to_string(&mut read).unwrap();
+    assert_eq!(read, "all");
+}
+
+#[cfg(feature = "memmap2")]
+#[test]
+fn test_save_mmap() {
+    use server::Multipart;
+
+    let body: &[u8] = b"--boundary\r\n\
+                        Content-Disposition: form-data; name=\"sized\"; filename=\"sized.bin\"\r\n\
+                        Content-Length: 10\r\n\r\n\
+                        sized data\r\n\
+                        --boundary\r\n\
+                        Content-Disposition: form-data; name=\"unsized\"; filename=\"unsized.bin\"\r\n\r\n\
+                        unsized data\r\n\
+                        --boundary\r\n\
+                        Content-Disposition: form-data; name=\"wrong\"; filename=\"wrong.bin\"\r\n\
+                        Content-Length: 100\r\n\r\n\
+                        short\r\n\
+                        --boundary--";
+
+    let tempdir = TempDir::new("multipart_mmap_test").unwrap();
+    let mut multipart = Multipart::with_body(body, "boundary");
+
+    let (path, map) = multipart.read_entry().unwrap().unwrap()
+        .save_mmap(tempdir.path().join("sized.bin"), 1024).unwrap();
+    assert_eq!(path, tempdir.path().join("sized.bin"));
+    assert_eq!(&map[..], b"sized data");
+
+    let (_, map) = multipart.read_entry().unwrap().unwrap()
+        .save_mmap(tempdir.path().join("unsized.bin"), 1024).unwrap();
+    assert_eq!(&map[..], b"unsized data");
+
+    let err = multipart.read_entry().unwrap().unwrap()
+        .save_mmap(tempdir.path().join("wrong.bin"), 1024).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(!tempdir.path().join("wrong.bin").exists());
+
+    let body: &[u8] = b"--boundary\r\n\
+                        Content-Disposition: form-data; name=\"huge\"; filename=\"huge.bin\"\r\n\
+                        Content-Length: 1099511627776\r\n\r\n\
+                        short\r\n\
+                        --boundary\r\n\
+                        Content-Disposition: form-data; name=\"long\"; filename=\"long.bin\"\r\n\r\n\
+                        longer than the limit\r\n\
+                        --boundary--";
+
+    let mut multipart = Multipart::with_body(body, "boundary");
+
+    // the declared length is rejected before sizing the file
+    let err = multipart.read_entry().unwrap().unwrap()
+        .save_mmap(tempdir.path().join("huge.bin"), 1024).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(!tempdir.path().join("huge.bin").exists());
+
+    let err = multipart.read_entry().unwrap().unwrap()
+        .save_mmap(tempdir.path().join("long.bin"), 10).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(!tempdir.path().join("long.bin").exists());
+}
+
+#[test]
+fn test_decode_with() {
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use server::Multipart;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"the inflated contents of the file").unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut body = Vec::new();
+    body.extend_from_slice(b"--boundary\r\n\
+                             Content-Disposition: form-data; name=\"file\"; filename=\"file.txt\"\r\n\
+                             X-Content-Encoding: gzip\r\n\r\n");
+    body.extend_from_slice(&compressed);
+    body.extend_from_slice(b"\r\n\
+                             --boundary\r\n\
+                             Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                             text\r\n\
+                             --boundary--");
+
+    let mut multipart = Multipart::with_body(&body[..], "boundary");
+
+    {
+        let mut field = multipart.read_entry().unwrap().unwrap();
+        let mut data = String::new();
+        field.decode_with(GzDecoder::new).read_to_string(&mut data).unwrap();
+        assert_eq!(data, "the inflated contents of the file");
+    }
+
+    let mut field = multipart.read_entry().unwrap().unwrap();
+    assert_eq!(field.read_text().unwrap(), "text");
+}
+
+#[test]
+fn test_boundary_from_content_type() {
+    assert_eq!(boundary_from_content_type("multipart/form-data; boundary=abc123").unwrap(), "abc123");
+    assert_eq!(boundary_from_content_type("Multipart/Form-Data;boundary = abc123 ").unwrap(), "abc123");
+    assert_eq!(boundary_from_content_type("multipart/form-data; boundary=\"quoted; \\\"boundary\\\"\"")
+                   .unwrap(), "quoted; \"boundary\"");
+    assert_eq!(boundary_from_content_type("multipart/form-data; boundary=abc; charset=utf-8").unwrap(),
+               "abc");
+    assert_eq!(boundary_from_content_type("multipart/related; type=\"text/xml\"; BOUNDARY=\"abc\"; start=x")
+                   .unwrap(), "abc");
+
+    assert_eq!(boundary_from_content_type("multipart/form-data"), None);
+    assert_eq!(boundary_from_content_type("multipart/form-data; boundary="), None);
+    assert_eq!(boundary_from_content_type("multipart/form-data; boundary=\"\""), None);
+    assert_eq!(boundary_from_content_type("multipart/form-data; charset=utf-8"), None);
+    assert_eq!(boundary_from_content_type("text/plain; boundary=abc"), None);
+    assert_eq!(boundary_from_content_type("multipart; boundary=abc"), None);
+    assert_eq!(boundary_from_content_type(""), None);
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_read_json() {
+    use server::{LimitExceeded, Limits, Multipart};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Metadata {
+        title: String,
+        tags: Vec<String>,
+    }
+
+    let body: &[u8] = b"--boundary\r\n\
+                        Content-Disposition: form-data; name=\"metadata\"\r\n\
+                        Content-Type: application/json\r\n\r\n\
+                        {\"title\": \"Holiday\", \"tags\": [\"beach\", \"sun\"]}\r\n\
+                        --boundary\r\n\
+                        Content-Disposition: form-data; name=\"count\"\r\n\r\n\
+                        {\"title\": 3}\r\n\
+                        --boundary--";
+
+    let mut multipart = Multipart::with_body(body, "boundary");
+
+    {
+        let mut field = multipart.read_entry().unwrap().unwrap();
+        assert_eq!(field.data.read_json::<Metadata>().unwrap(), Metadata {
+            title: "Holiday".into(),
+            tags: vec!["beach".into(), "sun".into()],
+        });
+    }
+
+    let mut field = multipart.read_entry().unwrap().unwrap();
+    assert_eq!(field.data.read_json::<Metadata>().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let limits = Limits { max_text_total: Some(16), ..Limits::default() };
+    let mut multipart = Multipart::with_body(body, "boundary").with_limits(limits);
+
+    let mut field = multipart.read_entry().unwrap().unwrap();
+    let err = field.data.read_json::<Metadata>().unwrap_err();
+    assert!(err.get_ref().unwrap().is::<LimitExceeded>());
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_read_to_bytes() {
+    use server::{LimitExceeded, Limits, Multipart};
+
+    let body: &[u8] = b"--boundary\r\n\
+                        Content-Disposition: form-data; name=\"file\"; filename=\"file.bin\"\r\n\r\n\
+                        \x00\x01\x02 binary data \xFF\r\n\
+                        --boundary--";
+
+    let mut expected = Vec::new();
+    Multipart::with_body(body, "boundary").read_entry().unwrap().unwrap()
+        .data.read_to_end(&mut expected).unwrap();
+
+    let mut multipart = Multipart::with_body(body, "boundary");
+    let bytes = multipart.read_entry().unwrap().unwrap().data.read_to_bytes().unwrap();
+    assert_eq!(&bytes[..], &expected[..]);
+    assert_eq!(bytes.clone(), bytes);
+
+    let limits = Limits { max_file_total: Some(4), ..Limits::default() };
+    let mut multipart = Multipart::with_body(body, "boundary").with_limits(limits);
+
+    let err = multipart.read_entry().unwrap().unwrap().data.read_to_bytes().unwrap_err();
+    assert!(err.get_ref().unwrap().is::<LimitExceeded>());
+}
+
+#[cfg(all(test, feature = "bench"))]
+mod bench {
+    extern crate test;
+    use self::test::Bencher;
+
+    use server::Multipart;
+
+    use std::io::Read;
+
+    const FIELD_LEN: usize = 4 * 1024 * 1024;
+
+    fn large_field() -> Vec<u8> {
+        let mut body = b"--boundary\r\n\
+                         Content-Disposition: form-data; name=\"file\"; filename=\"large.bin\"\r\n\r\n"
+                         .to_vec();
+        body.resize(body.len() + FIELD_LEN, b'a');
+        body.extend_from_slice(b"\r\n--boundary--");
+        body
+    }
+
+    fn read_large_field(body: &[u8], hint: Option<usize>) -> Vec<u8> {
+        let mut multipart = Multipart::with_body(body, "boundary");
+        let mut field = multipart.read_entry().unwrap().unwrap();
+        let mut buf = Vec::new();
+
+        match hint {
+            Some(hint) => field.data.read_to_end_with_hint(&mut buf, hint).unwrap(),
+            None => field.data.read_to_end(&mut buf).unwrap(),
+        };
+
+        buf
+    }
+
+    #[bench]
+    fn bench_read_to_end(b: &mut Bencher) {
+        let body = large_field();
+        b.iter(|| read_large_field(&body, None));
+    }
+
+    #[bench]
+    fn bench_read_to_end_with_hint(b: &mut Bencher) {
+        let body = large_field();
+        b.iter(|| read_large_field(&body, Some(FIELD_LEN)));
+    }
+}
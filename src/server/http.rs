@@ -0,0 +1,221 @@
+// Copyright 2016 `multipart` Crate Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//! Decoding of raw HTTP request bodies, for servers which don't do it themselves.
+//!
+//! Requires the `http-chunked` feature.
+
+use std::io::{self, BufRead, Read};
+use std::{cmp, str};
+
+/// The maximum length of a chunk-size or trailer line, including chunk extensions.
+const MAX_LINE_LEN: u64 = 4096;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    /// Expecting a chunk-size line.
+    Size,
+    /// Reading chunk data, with this many bytes left in the chunk.
+    Data(u64),
+    /// Expecting the CRLF following a chunk's data.
+    DataEnd,
+    /// The last chunk and any trailers were read.
+    Done,
+}
+
+/// A `Read` adapter which decodes a body with `Transfer-Encoding: chunked`
+/// ([IETF RFC 7230, Section 4.1][rfc7230-4.1]), for when the server hands over the raw
+/// request body.
+///
+/// Chunk extensions and trailers are discarded. Reading stops at the last (zero-sized) chunk,
+/// so anything following the body in `inner` is left unread; malformed chunks return an error
+/// of kind `InvalidData`.
+///
+/// Wrap it in `Multipart::with_body()` to parse the decoded body. Use `io::BufReader` if
+/// the source isn't `BufRead` already.
+///
+/// [rfc7230-4.1]: https://tools.ietf.org/html/rfc7230#section-4.1
+#[derive(Debug)]
+pub struct ChunkedDecoder<R> {
+    inner: R,
+    state: State,
+}
+
+impl<R: BufRead> ChunkedDecoder<R> {
+    /// Decode the chunked body read from `inner`.
+    pub fn new(inner: R) -> Self {
+        ChunkedDecoder {
+            inner: inner,
+            state: State::Size,
+        }
+    }
+
+    /// Returns `true` if the end of the chunked body was reached.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Get a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner reader.
+    ///
+    /// Reading from it directly will likely corrupt the chunked stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner reader, e.g. to read the next request once `is_done()`.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read a line terminated by CRLF, returning it without the line ending.
+    fn read_line(&mut self) -> io::Result<Vec<u8>> {
+        let mut line = Vec::new();
+        (&mut self.inner).take(MAX_LINE_LEN).read_until(b'\n', &mut line)?;
+
+        if !line.ends_with(b"\r\n") {
+            return Err(if line.len() as u64 == MAX_LINE_LEN {
+                invalid_data("chunk-size or trailer line too long")
+            } else {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of chunked body")
+            });
+        }
+
+        let len = line.len() - 2;
+        line.truncate(len);
+        Ok(line)
+    }
+
+    fn read_size(&mut self) -> io::Result<u64> {
+        let line = self.read_line()?;
+
+        // strip chunk extensions (`;name=value`)
+        let size = line.split(|&b| b == b';').next().unwrap_or(&[]);
+
+        str::from_utf8(size).ok()
+            .and_then(|size| u64::from_str_radix(size.trim(), 16).ok())
+            .ok_or_else(|| invalid_data("invalid chunk size"))
+    }
+
+    /// Read and discard the trailer section after the last chunk.
+    fn read_trailers(&mut self) -> io::Result<()> {
+        while !self.read_line()?.is_empty() {}
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for ChunkedDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.state {
+                State::Size => {
+                    let size = self.read_size()?;
+
+                    if size == 0 {
+                        self.read_trailers()?;
+                        self.state = State::Done;
+                    } else {
+                        self.state = State::Data(size);
+                    }
+                },
+                State::Data(remaining) => {
+                    if buf.is_empty() {
+                        return Ok(0);
+                    }
+
+                    let len = cmp::min(buf.len() as u64, remaining) as usize;
+                    let read = self.inner.read(&mut buf[..len])?;
+
+                    if read == 0 {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                  "unexpected end of chunked body"));
+                    }
+
+                    let remaining = remaining - read as u64;
+                    self.state = if remaining == 0 { State::DataEnd } else { State::Data(remaining) };
+                    return Ok(read);
+                },
+                State::DataEnd => {
+                    if !self.read_line()?.is_empty() {
+                        return Err(invalid_data("expected CRLF after chunk data"));
+                    }
+
+                    self.state = State::Size;
+                },
+                State::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChunkedDecoder;
+
+    use server::Multipart;
+
+    use std::io::{self, Read};
+
+    fn decode(body: &[u8]) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        ChunkedDecoder::new(body).read_to_end(&mut data).map(|_| data)
+    }
+
+    #[test]
+    fn test_chunked_decoder() {
+        assert_eq!(decode(b"4\r\nWiki\r\n5;ext=val\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\n\r\n").unwrap(),
+                   &b"Wikipedia in\r\n\r\nchunks."[..]);
+
+        // trailers are discarded and data after the body is left unread
+        let mut body = &b"3\r\nabc\r\n0\r\nExpires: never\r\n\r\nnext request"[..];
+        let mut data = Vec::new();
+
+        {
+            let mut decoder = ChunkedDecoder::new(&mut body);
+            decoder.read_to_end(&mut data).unwrap();
+            assert!(decoder.is_done());
+        }
+
+        assert_eq!(data, b"abc");
+        assert_eq!(body, b"next request");
+
+        assert_eq!(decode(b"zz\r\nabc\r\n0\r\n\r\n").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(decode(b"3\r\nabcd\r\n0\r\n\r\n").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(decode(b"5\r\nabc").unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_chunked_multipart() {
+        let body: &[u8] = b"16\r\n\
+                            --boundary\r\n\
+                            Content-Di\r\n\
+                            29\r\n\
+                            sposition: form-data; name=\"text\"\r\n\r\n\
+                            some\r\n\
+                            13\r\n\
+                            \x20text\r\n\
+                            --boundary--\r\n\
+                            0\r\n\r\n";
+
+        let mut multipart = Multipart::with_body(ChunkedDecoder::new(body), "boundary");
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(&*field.headers.name, "text");
+            assert_eq!(field.read_text().unwrap(), "some text");
+        }
+
+        assert!(multipart.read_entry().unwrap().is_none());
+    }
+}
@@ -10,7 +10,7 @@
 //! Also contains an implementation of [`HttpRequest`](../trait.HttpRequest.html)
 //! for `hyper::server::Request` and `&mut hyper::server::Request`.
 use hyper::net::Fresh;
-use hyper::header::ContentType;
+use hyper::header::{ContentLength, ContentType};
 use hyper::method::Method;
 use hyper::server::{Handler, Request, Response};
 
@@ -101,6 +101,14 @@ impl<'a, 'b> HttpRequest for HyperRequest<'a, 'b> {
         })
     }
 
+    fn has_content_type(&self) -> bool {
+        self.headers.has::<ContentType>()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.headers.get::<ContentLength>().map(|len| len.0)
+    }
+
     fn body(self) -> Self {
         self
     }
@@ -135,6 +143,14 @@ impl<'r, 'a, 'b> HttpRequest for &'r mut HyperRequest<'a, 'b> {
         })
     }
 
+    fn has_content_type(&self) -> bool {
+        self.headers.has::<ContentType>()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.headers.get::<ContentLength>().map(|len| len.0)
+    }
+
     fn body(self) -> Self::Body {
         self
     }
@@ -3,7 +3,7 @@
 //! Not shown here: `impl `[`HttpRequest`](../trait.HttpRequest.html#implementors)` for
 //! iron::Request`.
 
-use iron::headers::ContentType;
+use iron::headers::{ContentLength, ContentType};
 use iron::mime::{Mime, TopLevel, SubLevel};
 use iron::request::{Body as IronBody, Request as IronRequest};
 use iron::typemap::Key;
@@ -28,6 +28,14 @@ impl<'r, 'a, 'b> HttpRequest for &'r mut IronRequest<'a, 'b> {
         }
     }
 
+    fn has_content_type(&self) -> bool {
+        self.headers.has::<ContentType>()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.headers.get::<ContentLength>().map(|len| len.0)
+    }
+
     fn body(self) -> &'r mut IronBody<'a, 'b> {
         &mut self.body
     }
@@ -15,25 +15,52 @@ pub extern crate buf_redux;
 extern crate httparse;
 extern crate twoway;
 
+use mime::{self, Mime};
+
+use std::ascii::AsciiExt;
 use std::borrow::Borrow;
+use std::error::Error;
+use std::fmt;
+use std::collections::{HashMap, HashSet};
 use std::io::prelude::*;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use std::io;
 
 use tempdir::TempDir;
 
-use self::boundary::BoundaryReader;
+use self::boundary::{BoundaryReader, DataLimit, Deadline};
+
+use self::field::{HeaderOpts, PrivReadEntry};
 
-use self::field::PrivReadEntry;
+pub use self::field::{boundary_from_content_type, parse_content_disposition, CharsetDecoder,
+                      DecodeError, Disposition, EmptyNamePolicy, ExternalBodyRef, FieldHeaders,
+                      InvalidUtf8, LinesCapped,
+                      MultipartField, MultipartData, Normalize, RawBody, ReadEntry,
+                      ReadEntryResult, SeekableData, UnknownEncoding, Utf8Chars};
 
-pub use self::field::{FieldHeaders, MultipartField, MultipartData, ReadEntry, ReadEntryResult};
+#[cfg(feature = "async-bridge")]
+pub use self::field::DataStream;
 
 use self::save::SaveBuilder;
 
+use self::shared::SharedMultipart;
+
+use self::timeout::{Clock, SystemClock};
+
 pub use self::save::{Entries, SaveResult, SavedField};
 
-use self::save::EntriesSaveResult;
+use self::save::{EntriesSaveResult, PartialReason};
+
+/// The maximum length of a boundary, from IETF RFC 2046.
+const MAX_BOUNDARY_LEN: usize = 70;
+
+/// The default for `Multipart::with_boundary_search_limit()`.
+const DEFAULT_BOUNDARY_SEARCH_LIMIT: u64 = 4 * 1024;
+
+/// The default for `Limits::max_nesting_depth`.
+const DEFAULT_MAX_NESTING_DEPTH: usize = 2;
 
 /// Default typedef for shared strings.
 ///
@@ -78,6 +105,7 @@ macro_rules! try_read_entry {
 
 mod boundary;
 mod field;
+mod transfer;
 
 #[cfg(feature = "hyper")]
 pub mod hyper;
@@ -91,28 +119,336 @@ pub mod tiny_http;
 #[cfg(feature = "nickel")]
 pub mod nickel;
 
+#[cfg(feature = "http-chunked")]
+pub mod http;
+
 pub mod save;
 
+pub mod shared;
+
+pub mod slices;
+
+pub mod timeout;
+
 /// The server-side implementation of `multipart/form-data` requests.
 ///
 /// Implements `Borrow<R>` to allow access to the request body, if desired.
 pub struct Multipart<R> {
     reader: BoundaryReader<R>,
+    header_opts: HeaderOpts,
+    text_newlines: Normalize,
+    urlencoded_text: bool,
+    strip_bom: bool,
+    related: Option<RelatedParams>,
+    seen_names: Option<HashSet<ArcStr>>,
+    content_len: Option<u64>,
+    strict_content_len: bool,
+    strict_part_len: bool,
+    require_boundary: bool,
+    boundary_search_limit: u64,
+    require_nonempty: bool,
+    fields_read: usize,
+    files_read: usize,
+    /// The headers of the next field, if read by `peek_name()`.
+    peeked: Option<FieldHeaders>,
+    /// The name and declared length of the current field with `strict_part_len`, and the
+    /// position of the start of its data in the body.
+    part_len: Option<(String, u64, u64)>,
+    charset_decoders: HashMap<String, Arc<CharsetDecoder>>,
+    name_mapper: Option<Box<Fn(&str) -> String + Send + Sync>>,
+    limits: Limits,
+    /// The patterns and limits set with `with_size_limit_for()`.
+    type_limits: Vec<(String, u64)>,
+    /// Whether the field being read is a file, for counting its data towards `limits`.
+    current_is_file: Option<bool>,
+    text_total: u64,
+    file_total: u64,
+    /// The depth of this body in the request, set by `MultipartField::read_nested()`.
+    nesting_depth: usize,
 }
 
 impl Multipart<()> {
     /// If the given `HttpRequest` is a multipart/form-data POST request,
     /// return the request body wrapped in the multipart reader. Otherwise,
     /// returns the original request.
+    ///
+    /// See `try_from_request()` to find out why the request was rejected.
     pub fn from_request<R: HttpRequest>(req: R) -> Result<Multipart<R::Body>, R> {
+        Multipart::try_from_request(req).map_err(FromRequestError::into_request)
+    }
+
+    /// Like `from_request()`, but the error says why the request was rejected,
+    /// e.g. `FromRequestError::MissingContentType` for a request which lacks the header entirely.
+    pub fn try_from_request<R: HttpRequest>(req: R)
+                                            -> Result<Multipart<R::Body>, FromRequestError<R>> {
         //FIXME: move `map` expr to `Some` arm when nonlexical borrow scopes land.
+        let content_len = req.content_length();
+
         let boundary = match req.multipart_boundary().map(String::from) {
             Some(boundary) => boundary,
-            None => return Err(req),
+            None => return match req.multipart_related() {
+                Some(params) => Ok(Multipart::with_body(req.body(), params.boundary.clone())
+                    .with_related(params).with_declared_len(content_len)),
+                None if !req.has_content_type() => Err(FromRequestError::MissingContentType(req)),
+                None => Err(FromRequestError::NotMultipart(req)),
+            },
         };
 
-        Ok(Multipart::with_body(req.body(), boundary))        
-    }   
+        Ok(Multipart::with_body(req.body(), boundary).with_declared_len(content_len))
+    }
+}
+
+/// The body length differed from the declared `Content-Length`, with
+/// `Multipart::with_strict_content_length()`.
+///
+/// Returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ContentLengthMismatch {
+    /// The declared length of the body.
+    pub declared: u64,
+    /// The actual length of the body.
+    ///
+    /// Data after the closing boundary is only read up to one byte past the declared length,
+    /// so if the body is longer than declared this may be less than its true length.
+    pub actual: u64,
+}
+
+impl fmt::Display for ContentLengthMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.actual > self.declared {
+            write!(f, "{}: declared {} bytes, got more", self.description(), self.declared)
+        } else {
+            write!(f, "{}: declared {} bytes, got {}", self.description(), self.declared,
+                   self.actual)
+        }
+    }
+}
+
+impl Error for ContentLengthMismatch {
+    fn description(&self) -> &str {
+        "request body length does not match `Content-Length`"
+    }
+}
+
+/// The length of a field's data differed from its declared `Content-Length`, with
+/// `Multipart::with_strict_part_length()`.
+///
+/// Returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartLengthMismatch {
+    /// The name of the field.
+    pub name: String,
+    /// The declared length of the field's data.
+    pub declared: u64,
+    /// The actual length of the field's data.
+    pub actual: u64,
+}
+
+impl fmt::Display for PartLengthMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (field {:?}): declared {} bytes, got {}", self.description(), self.name,
+               self.declared, self.actual)
+    }
+}
+
+impl Error for PartLengthMismatch {
+    fn description(&self) -> &str {
+        "field data length does not match its `Content-Length`"
+    }
+}
+
+/// The first boundary wasn't found within the limit set with
+/// `Multipart::with_boundary_search_limit()`, with `Multipart::with_require_boundary_present()`.
+///
+/// Returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BoundaryNotFound {
+    /// The number of bytes searched for the boundary.
+    pub limit: u64,
+}
+
+impl fmt::Display for BoundaryNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (searched {} bytes)", self.description(), self.limit)
+    }
+}
+
+impl Error for BoundaryNotFound {
+    fn description(&self) -> &str {
+        "the boundary does not occur in the start of the request body"
+    }
+}
+
+/// The request has no fields, with `Multipart::with_require_nonempty()`.
+///
+/// Returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct EmptyForm;
+
+impl fmt::Display for EmptyForm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl Error for EmptyForm {
+    fn description(&self) -> &str {
+        "the request has no fields"
+    }
+}
+
+/// Limits on the total size of the data of all fields in a request, and on the number of
+/// files, set with `Multipart::with_limits()`.
+///
+/// A field counts as a file if it has a `filename`, and as text otherwise. `None` means unlimited.
+///
+/// Also limits how deeply bodies may be nested with `MultipartField::read_nested()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum number of bytes of data in all text fields.
+    pub max_text_total: Option<u64>,
+    /// The maximum number of bytes of data in all file fields.
+    pub max_file_total: Option<u64>,
+    /// The maximum number of file fields; text fields don't count towards it.
+    pub max_files: Option<usize>,
+    /// The maximum depth of a body nested with `MultipartField::read_nested()`, where the
+    /// request body is depth 0. Defaults to 2, e.g. a group of files in a nested
+    /// `multipart/mixed` field, and one more level within that.
+    pub max_nesting_depth: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_text_total: None,
+            max_file_total: None,
+            max_files: None,
+            max_nesting_depth: Some(DEFAULT_MAX_NESTING_DEPTH),
+        }
+    }
+}
+
+/// Reading a field would exceed one of the `Limits`; contains the limit.
+///
+/// Returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// `Limits::max_text_total` was exceeded.
+    TextTotal(u64),
+    /// `Limits::max_file_total` was exceeded.
+    FileTotal(u64),
+    /// `Limits::max_files` was exceeded.
+    TooManyFiles(usize),
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitExceeded::TextTotal(limit) | LimitExceeded::FileTotal(limit) =>
+                write!(f, "{} (limit: {} bytes)", self.description(), limit),
+            LimitExceeded::TooManyFiles(limit) =>
+                write!(f, "{} (limit: {})", self.description(), limit),
+        }
+    }
+}
+
+impl Error for LimitExceeded {
+    fn description(&self) -> &str {
+        match *self {
+            LimitExceeded::TextTotal(_) => "total size of text fields exceeded the limit",
+            LimitExceeded::FileTotal(_) => "total size of file fields exceeded the limit",
+            LimitExceeded::TooManyFiles(_) => "number of file fields exceeded the limit",
+        }
+    }
+}
+
+/// A field's data exceeded the limit for its content type, set with
+/// `Multipart::with_size_limit_for()`.
+///
+/// Returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldTooLarge {
+    /// The pattern which matched the field's content type, as it was passed.
+    pub pattern: String,
+    /// The limit for the pattern, in bytes.
+    pub limit: u64,
+}
+
+impl fmt::Display for FieldTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (pattern: {:?}, limit: {} bytes)", self.description(), self.pattern, self.limit)
+    }
+}
+
+impl Error for FieldTooLarge {
+    fn description(&self) -> &str {
+        "field data exceeded the limit for its content type"
+    }
+}
+
+/// `MultipartField::read_nested()` would exceed `Limits::max_nesting_depth`; contains the limit.
+///
+/// Returned wrapped in an `io::Error` of kind `InvalidData`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NestingTooDeep {
+    /// The maximum nesting depth.
+    pub limit: usize,
+}
+
+impl fmt::Display for NestingTooDeep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (limit: {})", self.description(), self.limit)
+    }
+}
+
+impl Error for NestingTooDeep {
+    fn description(&self) -> &str {
+        "nested multipart bodies exceeded the maximum depth"
+    }
+}
+
+/// The error returned by `Multipart::try_from_request()`, which gives back the request.
+pub enum FromRequestError<R> {
+    /// The request has no `Content-Type` header.
+    ///
+    /// Only detected if the `HttpRequest` implementation supports it; otherwise
+    /// `NotMultipart` is returned instead.
+    MissingContentType(R),
+    /// The request is not a multipart POST request with a boundary.
+    NotMultipart(R),
+}
+
+impl<R> FromRequestError<R> {
+    /// Get back the original request.
+    pub fn into_request(self) -> R {
+        match self {
+            FromRequestError::MissingContentType(req) | FromRequestError::NotMultipart(req) => req,
+        }
+    }
+}
+
+impl<R> fmt::Debug for FromRequestError<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FromRequestError::MissingContentType(_) => f.write_str("MissingContentType(..)"),
+            FromRequestError::NotMultipart(_) => f.write_str("NotMultipart(..)"),
+        }
+    }
+}
+
+impl<R> fmt::Display for FromRequestError<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl<R> Error for FromRequestError<R> {
+    fn description(&self) -> &str {
+        match *self {
+            FromRequestError::MissingContentType(_) => "request has no `Content-Type` header",
+            FromRequestError::NotMultipart(_) => "request is not a multipart POST request",
+        }
+    }
 }
 
 impl<R: Read> Multipart<R> {
@@ -130,90 +466,3130 @@ impl<R: Read> Multipart<R> {
 
         info!("Multipart::with_boundary(_, {:?}", boundary);
 
-        Multipart { 
-            reader: BoundaryReader::from_reader(body, boundary),
+        Multipart::with_reader(BoundaryReader::from_reader(body, boundary))
+    }
+
+    /// Construct a new `Multipart` with the given boundary as raw bytes and body reader.
+    ///
+    /// Like `with_body()`, but the boundary doesn't need to be valid UTF-8 and is matched
+    /// byte-for-byte. Returns an error of kind `InvalidInput` if it is empty or longer than
+    /// the 70 bytes allowed by [IETF RFC 2046, Section 5.1.1][rfc2046-5.1.1].
+    ///
+    /// [rfc2046-5.1.1]: https://tools.ietf.org/html/rfc2046#section-5.1.1
+    pub fn from_boundary_bytes(boundary: Vec<u8>, body: R) -> io::Result<Self> {
+        if boundary.is_empty() || boundary.len() > MAX_BOUNDARY_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("boundary must be 1 to {} bytes long, got {}",
+                                              MAX_BOUNDARY_LEN, boundary.len())));
+        }
+
+        info!("Multipart::from_boundary_bytes({:?}, _)", String::from_utf8_lossy(&boundary));
+
+        Ok(Multipart::with_reader(BoundaryReader::from_reader(body, boundary)))
+    }
+
+    fn with_reader(reader: BoundaryReader<R>) -> Self {
+        Multipart {
+            reader: reader,
+            header_opts: HeaderOpts::default(),
+            text_newlines: Normalize::None,
+            urlencoded_text: false,
+            strip_bom: false,
+            related: None,
+            seen_names: None,
+            content_len: None,
+            strict_content_len: false,
+            strict_part_len: false,
+            require_boundary: false,
+            boundary_search_limit: DEFAULT_BOUNDARY_SEARCH_LIMIT,
+            require_nonempty: false,
+            fields_read: 0,
+            files_read: 0,
+            peeked: None,
+            part_len: None,
+            charset_decoders: HashMap::new(),
+            name_mapper: None,
+            limits: Limits::default(),
+            type_limits: Vec::new(),
+            current_is_file: None,
+            text_total: 0,
+            file_total: 0,
+            nesting_depth: 0,
         }
     }
 
-    /// Read the next entry from this multipart request, returning a struct with the field's name and
-    /// data. See `MultipartField` for more info.
+    /// Set the maximum length, in bytes, of the `filename` parameter of any field.
     ///
-    /// ## Warning: Risk of Data Loss
-    /// If the previously returned entry had contents of type `MultipartField::File`,
-    /// calling this again will discard any unread contents of that entry.
-    pub fn read_entry(&mut self) -> io::Result<Option<MultipartField<&mut Self>>> {
-        self.read_entry_mut().into_result()
+    /// A field whose filename is longer than this will return an error from `read_entry()`
+    /// before any of its body is read. Filenames are often used to build paths on disk,
+    /// so this is worth setting even if the header section itself is already size-limited.
+    ///
+    /// Unlimited by default.
+    pub fn with_filename_limit(mut self, limit: usize) -> Self {
+        self.header_opts.filename_limit = Some(limit);
+        self
     }
 
-    /// Read the next entry from this multipart request, returning a struct with the field's name and
-    /// data. See `MultipartField` for more info.
-    pub fn into_entry(self) -> ReadEntryResult<Self> {
-        self.read_entry()
+    /// Limit the total size of the data of text fields and of file fields separately;
+    /// see `Limits`.
+    ///
+    /// Reading field data returns an error of kind `InvalidData` wrapping a `LimitExceeded`
+    /// as soon as the field is known to have more data than remains of the limit for its type,
+    /// which may be before all of the allowed data was read. Data which is skipped rather than
+    /// read (by reading the next field) doesn't count towards the limits.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
     }
 
-    /// Call `f` for each entry in the multipart request.
-    /// 
-    /// This is a substitute for Rust not supporting streaming iterators (where the return value
-    /// from `next()` borrows the iterator for a bound lifetime).
+    /// Get the limits set with `with_limits()`, e.g. to log the configuration.
     ///
-    /// Returns `Ok(())` when all fields have been read, or the first error.
-    pub fn foreach_entry<F>(&mut self, mut foreach: F) -> io::Result<()> where F: FnMut(MultipartField<&mut Self>) {
-        loop {
-            match self.read_entry() {
-                Ok(Some(field)) => foreach(field),
-                Ok(None) => return Ok(()),
-                Err(err) => return Err(err),
-            }
+    /// These are the defaults (no limits but `max_nesting_depth`) if it wasn't called.
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Limit the size of the data of each field whose content type matches `mime_pattern`, e.g.
+    /// `"image/*"` or `"application/json"`; may be called several times for different patterns.
+    ///
+    /// The pattern may be a full `type/subtype`, `type/*`, or `*/*`, compared case-insensitively.
+    /// Each field is only checked against the most specific pattern matching its `Content-Type`
+    /// (parameters are ignored), or `text/plain` if it has none; setting the same pattern again
+    /// replaces its limit. Patterns without a `/` never match.
+    ///
+    /// Reading more field data than this returns an error of kind `InvalidData` wrapping a
+    /// `FieldTooLarge`. The `Limits` set with `with_limits()` still apply as well.
+    pub fn with_size_limit_for(mut self, mime_pattern: &str, limit: u64) -> Self {
+        match self.type_limits.iter()
+            .position(|&(ref pattern, _)| pattern.eq_ignore_ascii_case(mime_pattern)) {
+            Some(idx) => self.type_limits[idx].1 = limit,
+            None => self.type_limits.push((mime_pattern.to_owned(), limit)),
         }
+
+        self
     }
 
-    /// Get a builder type for saving the files in this request to the filesystem.
+    /// If `true`, trim leading and trailing ASCII whitespace from field names, for clients
+    /// which pad them inconsistently.
     ///
-    /// See [`SaveBuilder`](save/struct.SaveBuilder.html) for more information.
-    pub fn save(&mut self) -> SaveBuilder<&mut Self> {
-        SaveBuilder::new(self)
+    /// Field data and filenames are not affected. Off by default.
+    pub fn with_trim_field_names(mut self, trim: bool) -> Self {
+        self.header_opts.trim_names = trim;
+        self
     }
-}
 
-impl<R> Borrow<R> for Multipart<R> {
-    fn borrow(&self) -> &R {
-        self.reader.borrow()
+    /// If `true`, return an error of kind `InvalidData` from `read_entry()` for a field whose
+    /// name or filename contains an ASCII control character (`0x00 - 0x1F` or `0x7F`,
+    /// including tabs and line breaks), e.g. to keep them out of logs and paths.
+    ///
+    /// Names and filenames are checked after decoding, so this also catches percent-encoded
+    /// control characters in an extended `filename*` parameter. Off by default.
+    pub fn with_reject_control_chars(mut self, reject: bool) -> Self {
+        self.header_opts.reject_control_chars = reject;
+        self
     }
-}
 
-impl<R: Read> PrivReadEntry for Multipart<R> {
-    type Source = BoundaryReader<R>;
+    /// If `true`, trim leading and trailing ASCII whitespace from field header values before
+    /// parsing them, so e.g. `Content-Type: text/plain ` is read as `text/plain`.
+    ///
+    /// If `false`, values are interpreted exactly as sent, e.g. `FieldHeaders::content_id` keeps
+    /// any trailing whitespace, a padded `Content-Length` is ignored as invalid and a padded
+    /// `Content-Type` returns an error. Either way, `FieldHeaders::raw_headers()` returns the
+    /// untrimmed values. Whitespace between the colon and the value is never part of it.
+    /// On by default.
+    pub fn with_trim_header_values(mut self, trim: bool) -> Self {
+        self.header_opts.keep_header_whitespace = !trim;
+        self
+    }
 
-    fn source_mut(&mut self) -> &mut BoundaryReader<R> {
-        &mut self.reader
+    /// If `true`, decode the data of each field according to its `Content-Transfer-Encoding`.
+    ///
+    /// `base64` and `quoted-printable` data is decoded as it is read, and invalid data returns
+    /// an error of kind `InvalidData`; the identity encodings `7bit`, `8bit` and `binary` are
+    /// passed through unchanged. Fields with any other encoding return an error from
+    /// `read_entry()` unless `on_unknown_encoding(UnknownEncoding::PassThrough)` is set.
+    ///
+    /// `Limits` still apply to the data as sent, before decoding.
+    ///
+    /// Off by default, in which case the header is only exposed as
+    /// `FieldHeaders::transfer_encoding`.
+    pub fn with_transfer_decoding(mut self, decode: bool) -> Self {
+        self.header_opts.transfer_decoding = decode;
+        self
     }
 
-    fn set_min_buf_size(&mut self, min_buf_size: usize) {
-        self.reader.set_min_buf_size(min_buf_size)
+    /// Set what to do with a field with an unsupported `Content-Transfer-Encoding`,
+    /// with `with_transfer_decoding()`; see `UnknownEncoding`.
+    pub fn on_unknown_encoding(mut self, policy: UnknownEncoding) -> Self {
+        self.header_opts.unknown_encoding = policy;
+        self
     }
 
-    /// Consume the next boundary.
-    /// Returns `true` if the last boundary was read, `false` otherwise.
-    fn consume_boundary(&mut self) -> io::Result<bool> {
-        debug!("Consume boundary!");
-        self.reader.consume_boundary()
+    /// If `true`, return an error from `read_entry()` when a field has the same name as one
+    /// read previously, for APIs which expect each field at most once.
+    ///
+    /// Names are compared exactly (after trimming, if `with_trim_field_names()` is set, and
+    /// mapping, if `with_name_mapper()` is set).
+    /// Off by default.
+    pub fn with_unique_field_names(mut self, unique: bool) -> Self {
+        self.seen_names = if unique { Some(HashSet::new()) } else { None };
+        self
     }
-}
 
-/// A server-side HTTP request that may or may not be multipart.
-///
-/// May be implemented by mutable references if providing the request or body by-value is
-/// undesirable.
-pub trait HttpRequest {
-    /// The body of this request.
-    type Body: Read;
-    /// Get the boundary string of this request if it is a POST request
-    /// with the `Content-Type` header set to `multipart/form-data`.
+    /// Set what to do with a field which has a `filename` but an empty or whitespace-only `name`;
+    /// see `EmptyNamePolicy`.
     ///
-    /// The boundary string should be supplied as an extra value of the `Content-Type` header, e.g.
-    /// `Content-Type: multipart/form-data; boundary={boundary}`.
-    fn multipart_boundary(&self) -> Option<&str>;
+    /// By default (`EmptyNamePolicy::AllowEmpty`) the empty name is passed through. Note that
+    /// consumers which group fields by name, such as `Entries::fields` when saving, will then
+    /// collect all such fields under the same empty name.
+    pub fn on_empty_name(mut self, policy: EmptyNamePolicy) -> Self {
+        self.header_opts.empty_name = policy;
+        self
+    }
 
-    /// Return the request body for reading.
-    fn body(self) -> Self::Body;
+    /// Set the line ending that `MultipartField::read_text()` converts line endings in text
+    /// fields to, as clients may send bare `\n` or `\r` depending on the platform.
+    ///
+    /// File fields are never touched. `Normalize::None` (the default) preserves the original bytes.
+    pub fn with_text_newline_normalization(mut self, newline: Normalize) -> Self {
+        self.text_newlines = newline;
+        self
+    }
+
+    /// If `true`, `MultipartField::read_text()` percent-decodes text fields as if they were
+    /// `application/x-www-form-urlencoded` values (`+` to a space and `%xx` to the escaped byte),
+    /// for clients which wrongly encode them.
+    ///
+    /// A stray `%` is kept as-is. File fields are never decoded. Off by default.
+    pub fn with_urlencoded_text_values(mut self, decode: bool) -> Self {
+        self.urlencoded_text = decode;
+        self
+    }
+
+    /// If `true`, `MultipartField::read_text()` removes a UTF-8 byte order mark (`EF BB BF`) from
+    /// the start of text fields, as prepended by some Windows clients.
+    ///
+    /// Only applies to fields read as UTF-8, not those decoded with a decoder registered with
+    /// `with_charset_decoder()`. File fields are never touched. Off by default.
+    pub fn with_strip_bom(mut self, strip: bool) -> Self {
+        self.strip_bom = strip;
+        self
+    }
+
+    /// Register a decoder for text fields whose `Content-Type` has the given `charset` parameter,
+    /// used by `MultipartField::read_text()` instead of UTF-8, e.g. for `Shift_JIS` or `GBK`
+    /// with a crate like `encoding_rs`.
+    ///
+    /// Charset names are compared case-insensitively. Registering the same name again replaces
+    /// the previous decoder.
+    pub fn with_charset_decoder<F>(mut self, name: &str, decoder: F) -> Self
+    where F: Fn(&[u8]) -> Result<String, DecodeError> + Send + Sync + 'static {
+        self.charset_decoders.insert(name.to_ascii_lowercase(), Arc::new(decoder));
+        self
+    }
+
+    /// Pass the name of each field through `mapper` as its headers are read, e.g. to convert
+    /// `user[address][city]` to `user.address.city` for building nested structures.
+    ///
+    /// `FieldHeaders::name` is the mapped name, and everything else operating on names, such as
+    /// `with_unique_field_names()` or the grouping in `Entries`, sees only the mapped names.
+    /// The mapper runs after `with_trim_field_names()` and `on_empty_name()` are applied.
+    pub fn with_name_mapper<F>(mut self, mapper: F) -> Self
+    where F: Fn(&str) -> String + Send + Sync + 'static {
+        self.name_mapper = Some(Box::new(mapper));
+        self
+    }
+
+    /// Read the body as `multipart/related` ([IETF RFC 2387][rfc2387]) with the given parameters,
+    /// parsed from the request's `Content-Type` header with `RelatedParams::parse()`.
+    ///
+    /// Parts of such a body are not required to have a `Content-Disposition` header; their
+    /// `FieldHeaders::name` is empty if it is missing. Use `related_root()` to find the root part.
+    ///
+    /// `Multipart::from_request()` already does this if the `HttpRequest` implementation supports
+    /// `multipart/related`.
+    ///
+    /// [rfc2387]: https://tools.ietf.org/html/rfc2387
+    pub fn with_related(mut self, params: RelatedParams) -> Self {
+        self.header_opts.related = true;
+        self.related = Some(params);
+        self
+    }
+
+    /// Read the body as the data of a nested `multipart/mixed` field, as sent for several files
+    /// under one name ([IETF RFC 2388, section 4.6][rfc2388]) by e.g.
+    /// `client::lazy::Multipart::add_file_group()`.
+    ///
+    /// Parts of such a body are not required to have a `Content-Disposition` header, and it may
+    /// have any type (usually `file`) and no `name`; `FieldHeaders::name` is empty if it is
+    /// missing. Get the boundary from the field's `Content-Type` with
+    /// `boundary_from_content_type()`.
+    ///
+    /// [rfc2388]: https://tools.ietf.org/html/rfc2388#section-4.6
+    pub fn with_nested_mixed(mut self) -> Self {
+        self.header_opts.mixed = true;
+        self
+    }
+
+    /// Return an error of kind `TimedOut` if a single read of the request body takes longer than
+    /// `timeout`, e.g. because the client is trickling bytes to hold the connection open.
+    ///
+    /// The body reader can't be interrupted, so the error can only be returned once a read does
+    /// complete. To bound the time spent waiting on a stalled connection, the body needs to
+    /// support timeouts itself, e.g. via `TcpStream::set_read_timeout()`.
+    /// `server::timeout::TimeoutReader` can be used to apply the same check to any reader.
+    pub fn with_read_timeout(self, timeout: Duration) -> Self {
+        self.with_read_timeout_clock(timeout, SystemClock)
+    }
+
+    /// Like `with_read_timeout()`, but measure the time with `clock`, e.g. to mock it in tests.
+    pub fn with_read_timeout_clock<C>(mut self, timeout: Duration, clock: C) -> Self
+    where C: Clock + Send + Sync + 'static {
+        self.reader.set_read_timeout(Some(timeout), clock);
+        self
+    }
+
+    /// Return an error of kind `TimedOut` wrapping a `timeout::DeadlineExceeded` from
+    /// `read_entry()` or reads of field data once `timeout` has passed since this was called,
+    /// however fast each single read is; see `with_read_timeout()` for that.
+    ///
+    /// As with `with_read_timeout()`, a read which is waiting on the client can't be
+    /// interrupted, so the error is returned from the next call after the deadline.
+    pub fn with_total_timeout(self, timeout: Duration) -> Self {
+        self.with_total_timeout_clock(timeout, SystemClock)
+    }
+
+    /// Like `with_total_timeout()`, but measure the time with `clock`, e.g. to mock it in tests.
+    pub fn with_total_timeout_clock<C>(mut self, timeout: Duration, clock: C) -> Self
+    where C: Clock + Send + Sync + 'static {
+        self.reader.set_deadline(Some(Deadline::new(timeout, clock)));
+        self
+    }
+
+    /// If `true`, accept a closing boundary (`--{boundary}--`) which is not preceded by a CRLF,
+    /// as produced by some encoders; the last field's data then ends right before the boundary.
+    ///
+    /// By default, a boundary must be preceded by a CRLF as required by the spec, so such
+    /// a closing boundary is read as field data and `read_entry()` then returns an
+    /// `UnexpectedEof` error.
+    pub fn with_lenient_final_boundary(mut self, lenient: bool) -> Self {
+        self.reader.set_lenient_final(lenient);
+        self
+    }
+
+    /// If `true`, also accept the boundary without its leading `--` (`CRLF{boundary}`) as
+    /// a delimiter, as produced by some buggy encoders. Delimiters with the dashes are still
+    /// accepted, and either kind must still be followed by a CRLF or `--`.
+    ///
+    /// This is a compatibility shim for such clients only: field data containing a line which
+    /// starts with the bare boundary is then cut short. By default, only `--{boundary}` is
+    /// a delimiter, as required by the spec.
+    pub fn with_flexible_delimiter(mut self, flexible: bool) -> Self {
+        self.reader.set_flexible_delimiter(flexible);
+        self
+    }
+
+    /// If `true`, treat the end of the body as the end of the request even if the closing
+    /// boundary is missing, e.g. to recover what was received of an interrupted upload.
+    /// The data of the last field is then whatever was received of it, and the next
+    /// `read_entry()` returns `None`.
+    ///
+    /// ### Warning: Data May Be Incomplete
+    /// There is no way to tell a truncated field from a complete one, so the last field may
+    /// be silently cut short; don't use this where that matters. A body which ends within
+    /// the headers of a field is still an error.
+    ///
+    /// By default, the missing boundary is an `UnexpectedEof` error from `read_entry()`.
+    pub fn with_allow_unterminated(mut self, allow: bool) -> Self {
+        self.reader.set_allow_unterminated(allow);
+        self
+    }
+
+    /// If `true`, return an error of kind `InvalidData` wrapping a `BoundaryNotFound` from the
+    /// first `read_entry()` if the first boundary doesn't start within the first
+    /// `with_boundary_search_limit()` bytes of the body, instead of reading the whole body
+    /// looking for it, e.g. if the request declared the wrong boundary.
+    ///
+    /// An empty body is still accepted as a request with no fields. Off by default.
+    pub fn with_require_boundary_present(mut self, require: bool) -> Self {
+        self.require_boundary = require;
+        self.update_preamble_limit();
+        self
+    }
+
+    /// If `true`, return an error of kind `InvalidData` wrapping `EmptyForm` instead of `None`
+    /// from `read_entry()` if the request has no fields at all, for endpoints which require
+    /// something to be submitted.
+    ///
+    /// This covers both an empty body and one with only a closing boundary. Off by default.
+    pub fn with_require_nonempty(mut self, require: bool) -> Self {
+        self.require_nonempty = require;
+        self
+    }
+
+    /// Set the number of bytes at the start of the body to search for the first boundary,
+    /// with `with_require_boundary_present()`. Defaults to 4 KiB.
+    pub fn with_boundary_search_limit(mut self, limit: u64) -> Self {
+        self.boundary_search_limit = limit;
+        self.update_preamble_limit();
+        self
+    }
+
+    fn update_preamble_limit(&mut self) {
+        let limit = if self.require_boundary { Some(self.boundary_search_limit) } else { None };
+        self.reader.set_preamble_limit(limit);
+    }
+
+    /// If `true`, accept a bare `\n\n` or a mixed `\r\n\n` as well as `\r\n\r\n` as the
+    /// separator between a field's headers and its data, as sent by some clients.
+    ///
+    /// The field data starts right after the separator either way. By default, a header block
+    /// which doesn't end in `\r\n\r\n` returns an `InvalidData` error from `read_entry()`.
+    ///
+    /// Boundary lines preceded or terminated by a bare `\n` are recognized as well, so a body
+    /// written entirely with LF line endings can be read. A `\r\n` before a boundary is still
+    /// removed from the field data in full.
+    pub fn with_lenient_line_endings(mut self, lenient: bool) -> Self {
+        self.header_opts.lenient_line_endings = lenient;
+        self.reader.set_lenient_line_endings(lenient);
+        self
+    }
+
+    /// If `true` and the request declared a `Content-Length`, return an error of kind
+    /// `InvalidData` wrapping a `ContentLengthMismatch` once the end of the body is reached,
+    /// if the number of bytes in the body differs from the declared length.
+    ///
+    /// Any data after the closing boundary is counted (and discarded) as part of the body.
+    /// The declared length is taken from the request with `from_request()`, or may be set with
+    /// `with_content_length()`. Off by default.
+    pub fn with_strict_content_length(mut self, strict: bool) -> Self {
+        self.strict_content_len = strict;
+        self
+    }
+
+    /// If `true`, check the data of each field which declares its own `Content-Length`
+    /// (exposed as `FieldHeaders::content_length`) against it, returning an error of kind
+    /// `InvalidData` wrapping a `PartLengthMismatch` from the next `read_entry()` if they differ.
+    ///
+    /// Any unread data of the field is discarded first, as usual. Fields without the header
+    /// aren't checked. Off by default.
+    pub fn with_strict_part_length(mut self, strict: bool) -> Self {
+        self.strict_part_len = strict;
+        self
+    }
+
+    /// Set the declared length of the body, for `with_strict_content_length()`.
+    ///
+    /// `from_request()` already does this if the `HttpRequest` implementation supports it.
+    pub fn with_content_length(self, content_len: u64) -> Self {
+        self.with_declared_len(Some(content_len))
+    }
+
+    fn with_declared_len(mut self, content_len: Option<u64>) -> Self {
+        self.content_len = content_len;
+        self
+    }
+
+    /// Add the data read from the last field to the total for its type.
+    fn count_field_data(&mut self) {
+        match self.current_is_file.take() {
+            Some(true) => self.file_total += self.reader.data_read(),
+            Some(false) => self.text_total += self.reader.data_read(),
+            None => (),
+        }
+    }
+
+    /// Limit the data of the field about to be read to what is left of the limit for its type,
+    /// or the limit for its content type if that is smaller.
+    fn set_field_limit(&mut self, is_file: bool, content_type: Option<&Mime>) {
+        let total_limit = if is_file {
+            self.limits.max_file_total
+                .map(|max| (max.saturating_sub(self.file_total), LimitExceeded::FileTotal(max)))
+        } else {
+            self.limits.max_text_total
+                .map(|max| (max.saturating_sub(self.text_total), LimitExceeded::TextTotal(max)))
+        };
+
+        let total_limit = total_limit.map(|(left, exceeded)| (left, DataLimit::Total(exceeded)));
+
+        let type_limit = content_type_limit(&self.type_limits, content_type)
+            .map(|&(ref pattern, limit)| (limit, DataLimit::ContentType(FieldTooLarge {
+                pattern: pattern.clone(),
+                limit: limit,
+            })));
+
+        let limit = match (total_limit, type_limit) {
+            (Some(total), Some(type_)) => Some(if type_.0 < total.0 { type_ } else { total }),
+            (total, type_) => total.or(type_),
+        };
+
+        self.current_is_file = Some(is_file);
+        self.reader.set_data_limit(limit);
+    }
+
+    /// Check the length of the last field's data against its declared length, if set.
+    fn check_part_len(&mut self) -> io::Result<()> {
+        let (name, declared, start) = match self.part_len.take() {
+            Some(part_len) => part_len,
+            None => return Ok(()),
+        };
+
+        self.reader.drain_to_boundary()?;
+
+        let actual = self.reader.consumed() - start;
+
+        if actual == declared {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, PartLengthMismatch {
+                name: name,
+                declared: declared,
+                actual: actual,
+            }))
+        }
+    }
+
+    /// Check the body length against the declared length after reading the closing boundary.
+    fn check_content_len(&mut self) -> io::Result<()> {
+        let declared = match self.content_len {
+            Some(declared) if self.strict_content_len => declared,
+            _ => return Ok(()),
+        };
+
+        // read one byte past the declared length at most
+        let limit = (declared + 1).saturating_sub(self.reader.consumed());
+        self.reader.consume_to_end(limit)?;
+
+        let actual = self.reader.consumed();
+
+        if actual == declared {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData,
+                               ContentLengthMismatch { declared: declared, actual: actual }))
+        }
+    }
+
+    /// Returns `true` if the request body is empty, i.e. the client submitted no form data at all.
+    ///
+    /// `read_entry()` returns `None` for both an empty body and one that has been read to the end;
+    /// use this to tell them apart. Only peeks at the body, so no fields are consumed.
+    pub fn is_empty_body(&mut self) -> io::Result<bool> {
+        self.reader.is_empty()
+    }
+
+    /// Read the next entry from this multipart request, returning a struct with the field's name and
+    /// data. See `MultipartField` for more info.
+    ///
+    /// ## Warning: Risk of Data Loss
+    /// If the previously returned entry had contents of type `MultipartField::File`,
+    /// calling this again will discard any unread contents of that entry.
+    pub fn read_entry(&mut self) -> io::Result<Option<MultipartField<&mut Self>>> {
+        self.read_entry_mut().into_result()
+    }
+
+    /// Read the next entry from this multipart request, returning a struct with the field's name and
+    /// data. See `MultipartField` for more info.
+    pub fn into_entry(self) -> ReadEntryResult<Self> {
+        self.read_entry()
+    }
+
+    /// Read up to and return the root part of a `multipart/related` body: the part whose
+    /// `Content-ID` matches the `start` parameter, or the first part if there isn't one.
+    ///
+    /// Any parts before the root are discarded. Returns `Ok(None)` if the end of the body
+    /// is reached first.
+    ///
+    /// ## Returns Error
+    /// If `with_related()` was not called.
+    pub fn related_root(&mut self) -> io::Result<Option<MultipartField<&mut Self>>> {
+        let start = match self.related {
+            Some(ref params) => params.start.as_ref().map(|start| trim_content_id(start).to_owned()),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "not reading a `multipart/related` body")),
+        };
+
+        let mut multipart = self;
+
+        loop {
+            let field = match ReadEntry::read_entry(multipart) {
+                ReadEntryResult::Entry(field) => field,
+                ReadEntryResult::End(_) => return Ok(None),
+                ReadEntryResult::Error(_, err) => return Err(err),
+            };
+
+            let is_root = match (start.as_ref(), field.headers.content_id.as_ref()) {
+                (Some(start), Some(content_id)) => start == trim_content_id(content_id),
+                (Some(_), None) => false,
+                (None, _) => true,
+            };
+
+            if is_root {
+                return Ok(Some(field));
+            }
+
+            multipart = field.data.into_inner();
+        }
+    }
+
+    /// Get the name of the next field without reading it, e.g. to decide how to handle it,
+    /// or `None` if the end of the request was reached.
+    ///
+    /// The headers of the field are read and kept for the next `read_entry()`, which returns
+    /// them without parsing them again; peeking again until then returns the same name.
+    /// Any unread data of the previous field is discarded, as with `read_entry()`, and errors
+    /// from reading the headers are returned here instead.
+    pub fn peek_name(&mut self) -> io::Result<Option<String>> {
+        if self.peeked.is_none() {
+            if self.consume_boundary()? {
+                return Ok(None);
+            }
+
+            let headers = self.read_headers()?;
+            self.peeked = Some(headers);
+        }
+
+        Ok(self.peeked.as_ref().map(|headers| headers.name.to_string()))
+    }
+
+    /// Call `f` for each entry in the multipart request.
+    /// 
+    /// This is a substitute for Rust not supporting streaming iterators (where the return value
+    /// from `next()` borrows the iterator for a bound lifetime).
+    ///
+    /// Returns `Ok(())` when all fields have been read, or the first error.
+    pub fn foreach_entry<F>(&mut self, mut foreach: F) -> io::Result<()> where F: FnMut(MultipartField<&mut Self>) {
+        loop {
+            match self.read_entry() {
+                Ok(Some(field)) => foreach(field),
+                Ok(None) => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// The number of fields returned by `read_entry()` (or the other methods reading entries)
+    /// so far, e.g. to log how many fields a request had once it has been read.
+    ///
+    /// For a saved request, see `Entries::fields_count()`.
+    pub fn fields_read(&self) -> usize {
+        self.fields_read
+    }
+
+    /// Read the rest of the request, returning the names of the fields in the order they appear.
+    ///
+    /// Field data is skipped over without being buffered. Options affecting field headers
+    /// (e.g. `with_filename_limit()` or `with_unique_field_names()`) are still applied, and the
+    /// first error is returned.
+    pub fn field_names(&mut self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+
+        while let Some(field) = self.read_entry()? {
+            // any unread data is discarded by the next `read_entry()`
+            names.push(field.headers.name.to_string());
+        }
+
+        Ok(names)
+    }
+
+    /// Read up to the first file field (one with a `filename`) named `name`, returning its headers
+    /// and data, or `None` if the end of the request is reached first.
+    ///
+    /// Other fields before it are skipped and their data discarded. The data is read into memory
+    /// subject to the limits set on this `Multipart` (e.g. `with_limits()`); set one if the
+    /// size of uploads must be bounded.
+    pub fn take_file(&mut self, name: &str) -> io::Result<Option<(FieldHeaders, Vec<u8>)>> {
+        while let Some(mut field) = self.read_entry()? {
+            if field.headers.filename.is_none() || &*field.headers.name != name {
+                continue;
+            }
+
+            let mut data = Vec::new();
+            field.data.read_to_end(&mut data)?;
+            return Ok(Some((field.headers, data)));
+        }
+
+        Ok(None)
+    }
+
+    /// Convert this into a request whose fields share ownership of it instead of borrowing it,
+    /// so they can be stored and passed around.
+    ///
+    /// Fields must still be read in order; see the `shared` module for more info.
+    pub fn into_shared(self) -> SharedMultipart<R> {
+        SharedMultipart::new(self)
+    }
+
+    /// Get an adapter which reads at most `n` more fields from this request.
+    ///
+    /// See `Take` for more info.
+    pub fn take(&mut self, n: usize) -> Take<R> {
+        Take {
+            multipart: self,
+            remaining: n,
+        }
+    }
+
+    /// Get an adapter which reads only the file fields (those with a `filename`) of this request.
+    ///
+    /// See `FilesOnly` for more info.
+    pub fn files_only(&mut self) -> FilesOnly<R> {
+        FilesOnly {
+            multipart: self,
+        }
+    }
+
+    /// Get the number of bytes which have been read from the request body but not yet consumed,
+    /// i.e. returned as field data or skipped over as headers and boundaries.
+    ///
+    /// These are the bytes at the start of `into_remainder()`.
+    pub fn buffered_len(&self) -> usize {
+        self.reader.buffered_len()
+    }
+
+    /// Unwrap the request body, returning a reader over the rest of it.
+    ///
+    /// The reader starts with any data which was buffered but not yet read as part of a field,
+    /// so after `Take` has finished the body begins at the (unread) next boundary. Useful if
+    /// the body has more data embedded after the fields you're expecting.
+    pub fn into_remainder(self) -> io::Chain<io::Cursor<Vec<u8>>, R> {
+        self.reader.into_remainder()
+    }
+
+    /// Skip any fields which haven't been read, then unwrap the request body, returning
+    /// a reader over whatever follows the closing boundary (the epilogue, if any), e.g. to reuse
+    /// the connection.
+    ///
+    /// Like `into_remainder()`, the reader starts with any bytes which were buffered past the
+    /// closing boundary, followed by the rest of the body.
+    ///
+    /// ## Returns Error
+    /// If reading the rest of the fields fails, e.g. if the body ends before the closing boundary.
+    pub fn finish(mut self) -> io::Result<io::Chain<io::Cursor<Vec<u8>>, R>> {
+        while self.read_entry()?.is_some() {}
+
+        Ok(self.into_remainder())
+    }
+
+    /// Read all text fields (those without a `filename`) as `(name, value)` pairs, like the
+    /// fields of an `application/x-www-form-urlencoded` body, e.g. to reuse a handler written
+    /// for one.
+    ///
+    /// Pairs are in the order the fields were sent, including duplicate names. Values are read
+    /// with `MultipartField::read_text()`, so the charset decoder and other text options apply.
+    /// File fields are skipped without being read.
+    ///
+    /// ## Returns Error
+    /// If reading a field fails, or a text field isn't valid UTF-8.
+    pub fn into_pairs(mut self) -> io::Result<Vec<(String, String)>> {
+        let mut pairs = Vec::new();
+
+        while let Some(mut field) = self.read_entry()? {
+            if field.headers.filename.is_some() {
+                continue;
+            }
+
+            let value = field.read_text()?;
+            pairs.push((field.headers.name.to_string(), value));
+        }
+
+        Ok(pairs)
+    }
+
+    /// Get a builder type for saving the files in this request to the filesystem.
+    ///
+    /// See [`SaveBuilder`](save/struct.SaveBuilder.html) for more information.
+    pub fn save(&mut self) -> SaveBuilder<&mut Self> {
+        SaveBuilder::new(self)
+    }
+
+    /// Save all the fields in this request with the default `SaveBuilder` settings, into a
+    /// temporary directory.
+    ///
+    /// Small fields are kept in memory and larger ones are written to disk; see
+    /// `SaveBuilder::memory_threshold()`. If one of the default limits is hit, the fields saved
+    /// so far are discarded and an error of kind `InvalidData` is returned. Use `save()` to
+    /// change the limits or to handle partial results.
+    pub fn save_all(mut self) -> io::Result<Entries> {
+        let result = self.save().temp();
+
+        match result {
+            SaveResult::Full(entries) => Ok(entries),
+            SaveResult::Partial(_, PartialReason::IoError(e)) | SaveResult::Error(e) => Err(e),
+            SaveResult::Partial(_, reason) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("could not save all fields in request: {:?}", reason),
+            )),
+        }
+    }
+}
+
+impl<R> Borrow<R> for Multipart<R> {
+    fn borrow(&self) -> &R {
+        self.reader.borrow()
+    }
+}
+
+impl<R: Read> PrivReadEntry for Multipart<R> {
+    type Source = BoundaryReader<R>;
+
+    fn source_mut(&mut self) -> &mut BoundaryReader<R> {
+        &mut self.reader
+    }
+
+    fn set_min_buf_size(&mut self, min_buf_size: usize) {
+        self.reader.set_min_buf_size(min_buf_size)
+    }
+
+    /// Consume the next boundary.
+    /// Returns `true` if the last boundary was read, `false` otherwise.
+    fn consume_boundary(&mut self) -> io::Result<bool> {
+        // already consumed by `peek_name()`
+        if self.peeked.is_some() {
+            return Ok(false);
+        }
+
+        debug!("Consume boundary!");
+        self.check_part_len()?;
+
+        let at_end = self.reader.consume_boundary()?;
+
+        if at_end {
+            if self.require_nonempty && self.fields_read == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, EmptyForm));
+            }
+
+            self.check_content_len()?;
+        }
+
+        Ok(at_end)
+    }
+
+    fn read_headers(&mut self) -> io::Result<FieldHeaders> {
+        if let Some(headers) = self.peeked.take() {
+            return Ok(headers);
+        }
+
+        self.count_field_data();
+
+        let mut headers = field::read_field_headers(&mut self.reader, &self.header_opts)?;
+        self.fields_read += 1;
+
+        if let Some(ref mapper) = self.name_mapper {
+            headers.name = (*mapper)(&headers.name).into();
+        }
+
+        if let Some(ref mut seen) = self.seen_names {
+            field::check_unique_name(seen, &headers)?;
+        }
+
+        if headers.filename.is_some() {
+            self.files_read += 1;
+
+            match self.limits.max_files {
+                Some(max) if self.files_read > max =>
+                    return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                              LimitExceeded::TooManyFiles(max))),
+                _ => (),
+            }
+        }
+
+        self.set_field_limit(headers.filename.is_some(), headers.content_type.as_ref());
+
+        if let (true, Some(declared)) = (self.strict_part_len, headers.content_length) {
+            self.part_len = Some((headers.name.to_string(), declared, self.reader.consumed()));
+        }
+
+        Ok(headers)
+    }
+
+    fn text_newlines(&self) -> Normalize {
+        self.text_newlines
+    }
+
+    fn charset_decoder(&self, charset: &str) -> Option<Arc<CharsetDecoder>> {
+        self.charset_decoders.get(&charset.to_ascii_lowercase()).cloned()
+    }
+
+    fn urlencoded_text(&self) -> bool {
+        self.urlencoded_text
+    }
+
+    fn strip_bom(&self) -> bool {
+        self.strip_bom
+    }
+
+    fn nesting_depth(&self) -> usize {
+        self.nesting_depth
+    }
+
+    fn nested_limits(&self) -> Limits {
+        self.limits
+    }
+
+    fn transfer_decoding(&self) -> bool {
+        self.header_opts.transfer_decoding
+    }
+}
+
+/// An adapter which reads at most a fixed number of fields from a `Multipart`.
+///
+/// Returned by `Multipart::take()`.
+///
+/// Once the limit is reached, `read_entry()` drains whatever is left of the last field and
+/// returns `None`, without reading any further into the request; the next boundary is left
+/// unread. Use `Multipart::into_remainder()` to get the rest of the request body afterwards.
+pub struct Take<'a, R: 'a> {
+    multipart: &'a mut Multipart<R>,
+    remaining: usize,
+}
+
+impl<'a, R: Read + 'a> Take<'a, R> {
+    /// Read the next entry, or return `None` if the limit was reached or the request ended.
+    pub fn read_entry(&mut self) -> io::Result<Option<MultipartField<&mut Multipart<R>>>> {
+        if self.remaining == 0 {
+            self.multipart.reader.drain_to_boundary()?;
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+        self.multipart.read_entry()
+    }
+
+    /// The number of fields that may still be read.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+}
+
+/// An adapter which reads only the file fields (those with a `filename`) from a `Multipart`.
+///
+/// Returned by `Multipart::files_only()`.
+///
+/// Text fields are skipped and their data discarded without being buffered; file fields are
+/// returned in the order they appear in the request.
+pub struct FilesOnly<'a, R: 'a> {
+    multipart: &'a mut Multipart<R>,
+}
+
+impl<'a, R: Read + 'a> FilesOnly<'a, R> {
+    /// Read the next file field, or return `None` if the request ended.
+    pub fn read_entry(&mut self) -> io::Result<Option<MultipartField<&mut Multipart<R>>>> {
+        let mut multipart = &mut *self.multipart;
+
+        loop {
+            let field = match ReadEntry::read_entry(multipart) {
+                ReadEntryResult::Entry(field) => field,
+                ReadEntryResult::End(_) => return Ok(None),
+                ReadEntryResult::Error(_, err) => return Err(err),
+            };
+
+            if field.headers.filename.is_some() {
+                return Ok(Some(field));
+            }
+
+            multipart = field.data.into_inner();
+        }
+    }
+}
+
+/// Parse all fields in an in-memory `multipart/form-data` body delimited by `boundary`,
+/// returning the headers and data of each field in order.
+///
+/// This uses the same parser as the streaming API. It is intended as a simple one-shot API
+/// for bodies that are already in memory, and as a deterministic entry point for fuzzing.
+pub fn parse_all(body: &[u8], boundary: &str) -> io::Result<Vec<(FieldHeaders, Vec<u8>)>> {
+    let mut multipart = Multipart::with_body(body, boundary);
+    let mut fields = Vec::new();
+
+    while let Some(mut field) = multipart.read_entry()? {
+        let mut data = Vec::new();
+        field.data.read_to_end(&mut data)?;
+        fields.push((field.headers, data));
+    }
+
+    Ok(fields)
+}
+
+/// Find the pattern and limit in `limits` which most specifically match `content_type`
+/// (`text/plain` if `None`).
+fn content_type_limit<'a>(limits: &'a [(String, u64)], content_type: Option<&Mime>)
+                          -> Option<&'a (String, u64)> {
+    let (type_, subtype) = content_type.map_or(("text", "plain"), |ct| {
+        (ct.type_().as_str(), ct.subtype().as_str())
+    });
+
+    limits.iter()
+        .filter_map(|limit| {
+            let slash = try_opt!(limit.0.find('/'));
+            let (pattern_type, pattern_subtype) = (&limit.0[..slash], &limit.0[slash + 1..]);
+
+            let specificity = if pattern_type == "*" && pattern_subtype == "*" {
+                0
+            } else if !pattern_type.eq_ignore_ascii_case(type_) {
+                return None;
+            } else if pattern_subtype == "*" {
+                1
+            } else if pattern_subtype.eq_ignore_ascii_case(subtype) {
+                2
+            } else {
+                return None;
+            };
+
+            Some((specificity, limit))
+        })
+        .max_by_key(|&(specificity, _)| specificity)
+        .map(|(_, limit)| limit)
+}
+
+/// The parameters of a `multipart/related` `Content-Type` header
+/// ([IETF RFC 2387, Section 3](https://tools.ietf.org/html/rfc2387#section-3)).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelatedParams {
+    /// The `boundary` parameter.
+    pub boundary: String,
+    /// The `start` parameter: the `Content-ID` of the root part, if not the first part.
+    pub start: Option<String>,
+    /// The `type` parameter: the MIME type of the root part.
+    pub type_: Option<String>,
+}
+
+impl RelatedParams {
+    /// Parse the value of a `Content-Type` header, returning `None` if it is not
+    /// `multipart/related` or doesn't have a `boundary` parameter.
+    pub fn parse(content_type: &str) -> Option<RelatedParams> {
+        let parsed = match content_type.parse::<Mime>() {
+            Ok(parsed) => parsed,
+            Err(_) => return None,
+        };
+
+        if parsed.type_() != mime::MULTIPART || parsed.subtype() != "related" {
+            return None;
+        }
+
+        let param = |name: &str| parsed.get_param(name)
+            .map(|val| val.as_str().trim_matches('"').to_owned());
+
+        Some(RelatedParams {
+            boundary: try_opt!(param("boundary")),
+            start: param("start"),
+            type_: param("type"),
+        })
+    }
+}
+
+fn trim_content_id(content_id: &str) -> &str {
+    content_id.trim().trim_left_matches('<').trim_right_matches('>')
+}
+
+/// A server-side HTTP request that may or may not be multipart.
+///
+/// May be implemented by mutable references if providing the request or body by-value is
+/// undesirable.
+pub trait HttpRequest {
+    /// The body of this request.
+    type Body: Read;
+    /// Get the boundary string of this request if it is a POST request
+    /// with the `Content-Type` header set to `multipart/form-data`.
+    ///
+    /// The boundary string should be supplied as an extra value of the `Content-Type` header, e.g.
+    /// `Content-Type: multipart/form-data; boundary={boundary}`.
+    fn multipart_boundary(&self) -> Option<&str>;
+
+    /// Get the parameters of this request if it is a POST request with the `Content-Type` header
+    /// set to `multipart/related`.
+    ///
+    /// Optional; `multipart/related` requests are not accepted by `Multipart::from_request()`
+    /// unless this is implemented.
+    fn multipart_related(&self) -> Option<RelatedParams> {
+        None
+    }
+
+    /// Returns `true` if the request has a `Content-Type` header at all, so
+    /// `Multipart::try_from_request()` can tell a missing header apart from a request which
+    /// isn't multipart.
+    ///
+    /// Defaults to `true`, which makes no distinction.
+    fn has_content_type(&self) -> bool {
+        true
+    }
+
+    /// Get the value of the `Content-Length` header, if set.
+    ///
+    /// Optional; used by `Multipart::with_strict_content_length()`.
+    fn content_length(&self) -> Option<u64> {
+        None
+    }
+
+    /// Return the request body for reading.
+    fn body(self) -> Self::Body;
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_all, EmptyNamePolicy, HttpRequest, LimitExceeded, Limits, Multipart, Normalize,
+                RelatedParams};
+
+    use std::borrow::Borrow;
+    use std::io::prelude::*;
+    use std::io::{self, ErrorKind};
+
+    const BOUNDARY: &'static str = "boundary";
+
+    #[test]
+    fn test_filename_limit() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; \
+                            filename=\"a-rather-long-filename.txt\"\r\n\r\n\
+                            contents\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_filename_limit(8);
+
+        match multipart.read_entry() {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+            Ok(field) => panic!("expected error, got {:?}", field.map(|f| f.headers)),
+        }
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_filename_limit(64);
+        let field = multipart.read_entry().unwrap().unwrap();
+        assert_eq!(field.headers.filename.as_ref().unwrap(), "a-rather-long-filename.txt");
+    }
+
+    #[test]
+    fn test_reject_control_chars() {
+        fn body(filename_param: &str) -> Vec<u8> {
+            format!("--boundary\r\n\
+                     Content-Disposition: form-data; name=\"file\"; {}\r\n\r\n\
+                     contents\r\n\
+                     --boundary--", filename_param).into_bytes()
+        }
+
+        let newline = body("filename*=UTF-8''evil%0D%0Aname.txt");
+        let nul = body("filename*=UTF-8''evil%00.txt.exe");
+        let tab = body("filename=\"evil\tname.txt\"");
+
+        for body in &[&newline, &nul, &tab] {
+            let mut multipart = Multipart::with_body(&body[..], BOUNDARY)
+                .with_reject_control_chars(true);
+
+            match multipart.read_entry() {
+                Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+                Ok(field) => panic!("expected error, got {:?}", field.map(|f| f.headers)),
+            }
+        }
+
+        // off by default
+        let mut multipart = Multipart::with_body(&nul[..], BOUNDARY);
+        let field = multipart.read_entry().unwrap().unwrap();
+        assert_eq!(field.headers.filename.as_ref().unwrap(), "evil\0.txt.exe");
+
+        let clean = body("filename=\"clean.txt\"");
+        let mut multipart = Multipart::with_body(&clean[..], BOUNDARY).with_reject_control_chars(true);
+        let field = multipart.read_entry().unwrap().unwrap();
+        assert_eq!(field.headers.filename.as_ref().unwrap(), "clean.txt");
+    }
+
+    #[test]
+    fn test_parse_all() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            some text\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.bin\"\r\n\
+                            Content-Type: application/octet-stream\r\n\r\n\
+                            \x00\x01\x02\r\n\
+                            --boundary--";
+
+        let fields = parse_all(body, BOUNDARY).unwrap();
+        assert_eq!(fields.len(), 2);
+
+        assert_eq!(&*fields[0].0.name, "text");
+        assert_eq!(fields[0].1, b"some text");
+
+        assert_eq!(&*fields[1].0.name, "file");
+        assert_eq!(fields[1].0.filename.as_ref().unwrap(), "file.bin");
+        assert_eq!(fields[1].1, b"\x00\x01\x02");
+    }
+
+    #[test]
+    fn test_lenient_final_boundary() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"first\"\r\n\r\n\
+                            first value\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"last\"\r\n\r\n\
+                            last value--boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_lenient_final_boundary(true);
+        let mut data = String::new();
+
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "first value");
+        data.clear();
+
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "last value");
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        // the closing boundary is field data when strict
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        data.clear();
+
+        multipart.read_entry().unwrap().unwrap();
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "last value--boundary--");
+
+        match multipart.read_entry() {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+            Ok(field) => panic!("expected error, got {:?}", field.map(|f| f.headers)),
+        }
+    }
+
+    #[test]
+    fn test_flexible_delimiter() {
+        let body: &[u8] = b"boundary\r\n\
+                            Content-Disposition: form-data; name=\"first\"\r\n\r\n\
+                            first value\r\n\
+                            boundary\r\n\
+                            Content-Disposition: form-data; name=\"last\"\r\n\r\n\
+                            last value\r\n\
+                            boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_flexible_delimiter(true);
+        let mut data = String::new();
+
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "first value");
+        data.clear();
+
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "last value");
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        // standard delimiters are still accepted
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            text\r\n\
+                            boundary\r\n\
+                            Content-Disposition: form-data; name=\"other\"\r\n\r\n\
+                            other\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_flexible_delimiter(true);
+        data.clear();
+
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "text");
+        data.clear();
+
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "other");
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        // when strict, the bare boundary is field data
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        data.clear();
+
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "text\r\nboundary\r\n\
+                          Content-Disposition: form-data; name=\"other\"\r\n\r\n\
+                          other");
+        assert!(multipart.read_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_allow_unterminated() {
+        let truncated: &[u8] = b"--boundary\r\n\
+                                 Content-Disposition: form-data; name=\"first\"\r\n\r\n\
+                                 first value\r\n\
+                                 --boundary\r\n\
+                                 Content-Disposition: form-data; name=\"last\"; filename=\"a.bin\"\r\n\r\n\
+                                 the upload was cut sh";
+
+        let mut multipart = Multipart::with_body(truncated, BOUNDARY).with_allow_unterminated(true);
+        let mut data = String::new();
+
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "first value");
+        data.clear();
+
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "the upload was cut sh");
+        assert!(multipart.read_entry().unwrap().is_none());
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        // the closing boundary without its hyphens
+        let body = b"--boundary\r\n\
+                     Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                     text\r\n\
+                     --boundary";
+
+        let mut multipart = Multipart::with_body(&body[..], BOUNDARY).with_allow_unterminated(true);
+        data.clear();
+
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "text");
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        // an error by default
+        let mut multipart = Multipart::with_body(truncated, BOUNDARY);
+        multipart.read_entry().unwrap().unwrap();
+        multipart.read_entry().unwrap().unwrap();
+
+        match multipart.read_entry() {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+            Ok(field) => panic!("expected error, got {:?}", field.map(|f| f.headers)),
+        }
+    }
+
+    #[test]
+    fn test_require_boundary_present() {
+        use super::BoundaryNotFound;
+
+        let preamble = vec![b'a'; 100];
+        let mut body = preamble.clone();
+        body.extend_from_slice(b"\r\n--boundary\r\n\
+                                 Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                                 text\r\n\
+                                 --boundary--");
+
+        fn read_entry(body: &[u8], limit: u64) -> io::Result<bool> {
+            let mut multipart = Multipart::with_body(body, BOUNDARY)
+                .with_require_boundary_present(true).with_boundary_search_limit(limit);
+            multipart.read_entry().map(|field| field.is_some())
+        }
+
+        fn assert_not_found(body: &[u8], limit: u64) {
+            match read_entry(body, limit) {
+                Err(err) => {
+                    assert_eq!(err.kind(), ErrorKind::InvalidData);
+                    assert_eq!(err.get_ref().and_then(|err| err.downcast_ref::<BoundaryNotFound>()),
+                               Some(&BoundaryNotFound { limit: limit }));
+                },
+                Ok(found) => panic!("expected error, got {:?}", found),
+            }
+        }
+
+        assert!(read_entry(&body, 1024).unwrap());
+        assert_not_found(&body, 64);
+
+        // the declared boundary never occurs, in a body shorter or longer than the limit
+        let wrong = b"--another\r\n\
+                      Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                      text\r\n\
+                      --another--";
+
+        assert_not_found(wrong, 1024);
+        assert_not_found(&preamble, 64);
+
+        // not checked by default
+        let mut multipart = Multipart::with_body(&preamble[..], BOUNDARY);
+
+        match multipart.read_entry() {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+            Ok(field) => panic!("expected error, got {:?}", field.map(|f| f.headers)),
+        }
+    }
+
+    #[test]
+    fn test_require_nonempty() {
+        use super::EmptyForm;
+
+        fn read_entry(body: &[u8]) -> io::Result<bool> {
+            let mut multipart = Multipart::with_body(body, BOUNDARY).with_require_nonempty(true);
+            multipart.read_entry().map(|field| field.is_some())
+        }
+
+        let empty: [&[u8]; 3] = [b"", b"--boundary--", b"--boundary--\r\n"];
+
+        for body in &empty {
+            match read_entry(body) {
+                Err(err) => {
+                    assert_eq!(err.kind(), ErrorKind::InvalidData);
+                    assert!(err.get_ref().unwrap().is::<EmptyForm>());
+                },
+                Ok(found) => panic!("expected error for {:?}, got {:?}", body, found),
+            }
+        }
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            text\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_require_nonempty(true);
+        assert!(multipart.read_entry().unwrap().is_some());
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        // allowed by default
+        assert!(Multipart::with_body(&b""[..], BOUNDARY).read_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_lenient_line_endings() {
+        fn body(separator: &str) -> String {
+            format!("--boundary\r\n\
+                     Content-Disposition: form-data; name=\"text\"{}\
+                     \nbody data\r\n\
+                     --boundary--", separator)
+        }
+
+        for separator in &["\n\n", "\r\n\r\n", "\r\n\n"] {
+            let body = body(separator);
+            let mut multipart = Multipart::with_body(body.as_bytes(), BOUNDARY)
+                .with_lenient_line_endings(true);
+
+            let mut data = String::new();
+
+            {
+                let mut field = multipart.read_entry().unwrap().unwrap();
+                assert_eq!(&*field.headers.name, "text");
+                field.data.read_to_string(&mut data).unwrap();
+            }
+
+            // the leading `\n` of the data must not be taken as part of the separator
+            assert_eq!(data, "\nbody data", "separator: {:?}", separator);
+            assert!(multipart.read_entry().unwrap().is_none());
+        }
+
+        // only CRLF-CRLF is accepted when strict
+        for separator in &["\n\n", "\r\n\n"] {
+            let body = body(separator);
+            let mut multipart = Multipart::with_body(body.as_bytes(), BOUNDARY);
+
+            match multipart.read_entry() {
+                Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+                Ok(field) => panic!("expected error, got {:?}", field.map(|f| f.headers)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_lenient_line_endings_lf_body() {
+        let body = "--boundary\r\n\
+                    Content-Disposition: form-data; name=\"first\"\r\n\r\n\
+                    first value\r\n\
+                    --boundary\r\n\
+                    Content-Disposition: form-data; name=\"empty\"\r\n\r\n\
+                    \r\n\
+                    --boundary\r\n\
+                    Content-Disposition: form-data; name=\"file\"; filename=\"file.txt\"\r\n\
+                    Content-Type: text/plain\r\n\r\n\
+                    line one\r\n\
+                    line two\r\n\
+                    --boundary--";
+
+        fn parse(body: &str) -> Vec<(String, Option<String>, String)> {
+            let mut multipart = Multipart::with_body(body.as_bytes(), BOUNDARY)
+                .with_lenient_line_endings(true);
+
+            let mut fields = Vec::new();
+
+            while let Some(mut field) = multipart.read_entry().unwrap() {
+                let mut data = String::new();
+                field.data.read_to_string(&mut data).unwrap();
+                fields.push((field.headers.name.to_string(), field.headers.filename.clone(),
+                             data.replace("\r\n", "\n")));
+            }
+
+            fields
+        }
+
+        let lf_body = body.replace("\r\n", "\n");
+        let fields = parse(&lf_body);
+
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields, parse(body));
+        assert_eq!(fields[0].2, "first value");
+        assert_eq!(fields[1].2, "");
+        assert_eq!(fields[2].1.as_ref().unwrap(), "file.txt");
+        assert_eq!(fields[2].2, "line one\nline two");
+
+        // LF boundaries aren't recognized when strict
+        assert!(Multipart::with_body(lf_body.as_bytes(), BOUNDARY).read_entry().is_err());
+    }
+
+    #[test]
+    fn test_save_transform() {
+        use super::save::{SaveResult, SavedData};
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            some text\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+
+        let entries = match multipart.save().transform(|_, data| Box::new(data.take(4))).temp() {
+            SaveResult::Full(entries) => entries,
+            _ => panic!("expected full save"),
+        };
+
+        let field = &entries.fields.values().next().unwrap()[0];
+        assert_eq!(&*field.headers.name, "text");
+
+        match field.data {
+            SavedData::Text(ref text) => assert_eq!(text, "some"),
+            ref other => panic!("expected text, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_is_empty_body() {
+        let mut multipart = Multipart::with_body(&b""[..], BOUNDARY);
+        assert!(multipart.is_empty_body().unwrap());
+        assert!(multipart.read_entry().unwrap().is_none());
+        assert!(multipart.is_empty_body().unwrap());
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            some text\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        assert!(!multipart.is_empty_body().unwrap());
+
+        let mut data = String::new();
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "some text");
+        assert!(multipart.read_entry().unwrap().is_none());
+        assert!(!multipart.is_empty_body().unwrap());
+    }
+
+    #[test]
+    fn test_empty_name_policy() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\" \"; filename=\"file.txt\"\r\n\r\n\
+                            contents\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        assert_eq!(&*multipart.read_entry().unwrap().unwrap().headers.name, " ");
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY)
+            .on_empty_name(EmptyNamePolicy::AllowEmpty);
+        assert_eq!(&*multipart.read_entry().unwrap().unwrap().headers.name, " ");
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY)
+            .on_empty_name(EmptyNamePolicy::UseFilename);
+        assert_eq!(&*multipart.read_entry().unwrap().unwrap().headers.name, "file.txt");
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY)
+            .on_empty_name(EmptyNamePolicy::Error);
+
+        match multipart.read_entry() {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+            Ok(field) => panic!("expected error, got {:?}", field.map(|f| f.headers)),
+        }
+    }
+
+    #[test]
+    fn test_text_newline_normalization() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            one\r\ntwo\nthree\rfour\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.txt\"\r\n\r\n\
+                            one\r\ntwo\nthree\rfour\r\n\
+                            --boundary--";
+
+        let read_texts = |newline| {
+            let mut multipart = Multipart::with_body(body, BOUNDARY)
+                .with_text_newline_normalization(newline);
+            let text = multipart.read_entry().unwrap().unwrap().read_text().unwrap();
+            let file = multipart.read_entry().unwrap().unwrap().read_text().unwrap();
+            (text, file)
+        };
+
+        let original = "one\r\ntwo\nthree\rfour";
+
+        assert_eq!(read_texts(Normalize::None), (original.to_owned(), original.to_owned()));
+        assert_eq!(read_texts(Normalize::CrLf),
+                   ("one\r\ntwo\r\nthree\r\nfour".to_owned(), original.to_owned()));
+        assert_eq!(read_texts(Normalize::Lf),
+                   ("one\ntwo\nthree\nfour".to_owned(), original.to_owned()));
+    }
+
+    #[test]
+    fn test_urlencoded_text_values() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            a%20b+c%2B100%+%E2%82%AC%zz\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.txt\"\r\n\r\n\
+                            a%20b+c\r\n\
+                            --boundary--";
+
+        let read_texts = |decode| {
+            let mut multipart = Multipart::with_body(body, BOUNDARY)
+                .with_urlencoded_text_values(decode);
+            let text = multipart.read_entry().unwrap().unwrap().read_text().unwrap();
+            let file = multipart.read_entry().unwrap().unwrap().read_text().unwrap();
+            (text, file)
+        };
+
+        assert_eq!(read_texts(true), ("a b c+100% \u{20AC}%zz".to_owned(), "a%20b+c".to_owned()));
+        assert_eq!(read_texts(false),
+                   ("a%20b+c%2B100%+%E2%82%AC%zz".to_owned(), "a%20b+c".to_owned()));
+    }
+
+    #[test]
+    fn test_strip_bom() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            \xEF\xBB\xBFvalue\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.txt\"\r\n\r\n\
+                            \xEF\xBB\xBFcontents\r\n\
+                            --boundary--";
+
+        let read_texts = |strip| {
+            let mut multipart = Multipart::with_body(body, BOUNDARY).with_strip_bom(strip);
+            let text = multipart.read_entry().unwrap().unwrap().read_text().unwrap();
+            let file = multipart.read_entry().unwrap().unwrap().read_text().unwrap();
+            (text, file)
+        };
+
+        assert_eq!(read_texts(true), ("value".to_owned(), "\u{FEFF}contents".to_owned()));
+        assert_eq!(read_texts(false), ("\u{FEFF}value".to_owned(), "\u{FEFF}contents".to_owned()));
+    }
+
+    #[test]
+    fn test_name_mapper() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"user[address][city]\"\r\n\r\n\
+                            Berlin\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"user.address.city\"\r\n\r\n\
+                            Paris\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"plain\"\r\n\r\n\
+                            value\r\n\
+                            --boundary--";
+
+        fn brackets_to_dots(name: &str) -> String {
+            name.replace("][", ".").replace('[', ".").replace(']', "")
+        }
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_name_mapper(brackets_to_dots);
+        assert_eq!(multipart.field_names().unwrap(), ["user.address.city", "user.address.city", "plain"]);
+
+        // uniqueness is checked on the mapped names
+        let mut multipart = Multipart::with_body(body, BOUNDARY)
+            .with_name_mapper(brackets_to_dots)
+            .with_unique_field_names(true);
+
+        multipart.read_entry().unwrap().unwrap();
+
+        match multipart.read_entry() {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+            Ok(field) => panic!("expected error, got {:?}", field.map(|f| f.headers)),
+        }
+    }
+
+    #[test]
+    fn test_charset_decoder() {
+        use super::DecodeError;
+
+        fn decode_latin1(bytes: &[u8]) -> Result<String, DecodeError> {
+            Ok(bytes.iter().map(|&b| b as char).collect())
+        }
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"latin1\"\r\n\
+                            Content-Type: text/plain; charset=ISO-8859-1\r\n\r\n\
+                            caf\xE9\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"utf8\"\r\n\
+                            Content-Type: text/plain; charset=utf-8\r\n\r\n\
+                            caf\xC3\xA9\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY)
+            .with_charset_decoder("iso-8859-1", decode_latin1);
+
+        assert_eq!(multipart.read_entry().unwrap().unwrap().read_text().unwrap(), "café");
+        // no decoder registered, so it's read as UTF-8
+        assert_eq!(multipart.read_entry().unwrap().unwrap().read_text().unwrap(), "café");
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY)
+            .with_charset_decoder("ISO-8859-1", |_: &[u8]| Err(DecodeError::new("invalid data")));
+
+        match multipart.read_entry().unwrap().unwrap().read_text() {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+            Ok(text) => panic!("expected error, got {:?}", text),
+        };
+    }
+
+    #[test]
+    fn test_channel_reader() {
+        use mock::ChannelReader;
+        use std::sync::mpsc;
+        use std::thread;
+
+        let (tx, rx) = mpsc::channel();
+
+        let producer = thread::spawn(move || {
+            let chunks: &[&[u8]] = &[
+                b"--boundary\r\nContent-Disposition: form-",
+                b"data; name=\"text\"\r\n\r\nsome ",
+                b"",
+                b"text\r\n--bound",
+                b"ary--",
+            ];
+
+            for chunk in chunks {
+                tx.send(chunk.to_vec()).unwrap();
+            }
+        });
+
+        let mut multipart = Multipart::with_body(ChannelReader::new(rx), BOUNDARY);
+
+        let mut data = String::new();
+        multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "some text");
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        producer.join().unwrap();
+    }
+
+    const RELATED_BODY: &'static [u8] = b"--boundary\r\n\
+                                          Content-Type: image/png\r\n\
+                                          Content-ID: <image@example>\r\n\r\n\
+                                          not really a png\r\n\
+                                          --boundary\r\n\
+                                          Content-Type: application/xop+xml\r\n\
+                                          Content-ID: <root@example>\r\n\r\n\
+                                          <root/>\r\n\
+                                          --boundary--";
+
+    const RELATED_CONTENT_TYPE: &'static str = "multipart/related; boundary=boundary; \
+                                                type=\"application/xop+xml\"; \
+                                                start=\"<root@example>\"";
+
+    struct RelatedRequest;
+
+    impl HttpRequest for RelatedRequest {
+        type Body = &'static [u8];
+
+        fn multipart_boundary(&self) -> Option<&str> {
+            None
+        }
+
+        fn multipart_related(&self) -> Option<RelatedParams> {
+            RelatedParams::parse(RELATED_CONTENT_TYPE)
+        }
+
+        fn body(self) -> &'static [u8] {
+            RELATED_BODY
+        }
+    }
+
+    #[test]
+    fn test_related_params() {
+        assert_eq!(RelatedParams::parse(RELATED_CONTENT_TYPE), Some(RelatedParams {
+            boundary: BOUNDARY.into(),
+            start: Some("<root@example>".into()),
+            type_: Some("application/xop+xml".into()),
+        }));
+
+        assert_eq!(RelatedParams::parse("multipart/form-data; boundary=boundary"), None);
+        assert_eq!(RelatedParams::parse("multipart/related"), None);
+    }
+
+    #[test]
+    fn test_related_root() {
+        let mut multipart = Multipart::from_request(RelatedRequest).ok().unwrap();
+
+        let mut data = String::new();
+        {
+            let mut root = multipart.related_root().unwrap().unwrap();
+            assert_eq!(root.headers.content_id.as_ref().unwrap(), "<root@example>");
+            assert_eq!(&*root.headers.name, "");
+            root.data.read_to_string(&mut data).unwrap();
+        }
+        assert_eq!(data, "<root/>");
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        // without `start`, the first part is the root
+        let params = RelatedParams { start: None, .. RelatedParams::parse(RELATED_CONTENT_TYPE).unwrap() };
+        let mut multipart = Multipart::with_body(RELATED_BODY, BOUNDARY).with_related(params);
+        let root = multipart.related_root().unwrap().unwrap();
+        assert_eq!(root.headers.content_id.as_ref().unwrap(), "<image@example>");
+
+        // not `multipart/related`
+        let mut multipart = Multipart::with_body(RELATED_BODY, BOUNDARY);
+
+        match multipart.related_root() {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidInput),
+            Ok(field) => panic!("expected error, got {:?}", field.map(|f| f.headers)),
+        }
+    }
+
+    #[cfg(feature = "timing")]
+    #[test]
+    fn test_read_duration() {
+        use std::{cmp, io, thread};
+        use std::time::Duration;
+
+        /// Returns at most one chunk per read, sleeping before each but the first.
+        struct SlowReader {
+            chunks: Vec<&'static [u8]>,
+            started: bool,
+        }
+
+        impl Read for SlowReader {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.chunks.is_empty() {
+                    return Ok(0);
+                }
+
+                if self.started {
+                    thread::sleep(Duration::from_millis(1));
+                }
+
+                self.started = true;
+
+                let chunk = self.chunks.remove(0);
+                let read = cmp::min(buf.len(), chunk.len());
+                buf[..read].copy_from_slice(&chunk[..read]);
+
+                if read < chunk.len() {
+                    self.chunks.insert(0, &chunk[read..]);
+                }
+
+                Ok(read)
+            }
+        }
+
+        const DATA: &[u8] = b"some text which arrives one byte at a time";
+
+        // some chunks may be read ahead with the headers, but not all of them
+        let mut chunks: Vec<&'static [u8]> =
+            vec![b"--boundary\r\nContent-Disposition: form-data; name=\"text\"\r\n\r\n"];
+        chunks.extend(DATA.chunks(1));
+        chunks.push(b"\r\n--boundary--");
+
+        let reader = SlowReader { chunks: chunks, started: false };
+
+        let mut multipart = Multipart::with_body(reader, BOUNDARY);
+        let mut field = multipart.read_entry().unwrap().unwrap();
+        assert_eq!(field.data.read_duration(), None);
+
+        let mut data = Vec::new();
+        field.data.read_to_end(&mut data).unwrap();
+        assert_eq!(data, DATA);
+
+        assert!(field.data.read_duration().unwrap() > Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        use mock::MockClock;
+        use std::{cmp, io};
+        use std::time::Duration;
+
+        /// Returns at most one chunk per read, advancing `clock` by `delay` before the last one.
+        struct SlowReader {
+            chunks: Vec<&'static [u8]>,
+            clock: MockClock,
+            delay: Duration,
+        }
+
+        impl Read for SlowReader {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.chunks.is_empty() {
+                    return Ok(0);
+                }
+
+                if self.chunks.len() == 1 {
+                    self.clock.advance(self.delay);
+                }
+
+                let chunk = self.chunks.remove(0);
+                let read = cmp::min(buf.len(), chunk.len());
+                buf[..read].copy_from_slice(&chunk[..read]);
+
+                if read < chunk.len() {
+                    self.chunks.insert(0, &chunk[read..]);
+                }
+
+                Ok(read)
+            }
+        }
+
+        let read_slow_field = |timeout_secs| {
+            let clock = MockClock::new();
+
+            let reader = SlowReader {
+                chunks: vec![b"--boundary\r\nContent-Disposition: form-data; name=\"text\"\r\n\r\nsome ",
+                             b"text\r\n--boundary--"],
+                clock: clock.clone(),
+                delay: Duration::from_secs(10),
+            };
+
+            let mut multipart = Multipart::with_body(reader, BOUNDARY)
+                .with_read_timeout_clock(Duration::from_secs(timeout_secs), clock);
+
+            let mut data = String::new();
+            multipart.read_entry().unwrap().unwrap().data.read_to_string(&mut data).map(|_| data)
+        };
+
+        assert_eq!(read_slow_field(5).unwrap_err().kind(), ErrorKind::TimedOut);
+        assert_eq!(read_slow_field(60).unwrap(), "some text");
+    }
+
+    #[test]
+    fn test_content_type_params() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"data.csv\"\r\n\
+                            Content-Type: text/csv; charset=utf-8; header=present; delimiter=\",\"\r\n\r\n\
+                            a,b\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            text\r\n\
+                            --boundary--";
+
+        let fields = parse_all(body, BOUNDARY).unwrap();
+
+        let params: Vec<(&str, &str)> = fields[0].0.content_type_params().iter()
+            .map(|&(ref name, ref val)| (&**name, &**val))
+            .collect();
+
+        assert_eq!(params, [("charset", "utf-8"), ("header", "present"), ("delimiter", ",")]);
+        assert!(fields[1].0.content_type_params().is_empty());
+    }
+
+    #[test]
+    fn test_external_body() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"\r\n\
+                            Content-Type: message/external-body; access-type=URL;\
+                             URL=\"http://example.com/file.bin\"; size=1024\r\n\r\n\
+                            Content-Type: application/octet-stream\r\n\r\n\
+                            \r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            text\r\n\
+                            --boundary--";
+
+        let fields = parse_all(body, BOUNDARY).unwrap();
+
+        let external = fields[0].0.external_body().unwrap();
+        assert_eq!(external.access_type, "URL");
+        assert_eq!(external.url(), Some("http://example.com/file.bin"));
+        assert_eq!(external.param("SIZE"), Some("1024"));
+        assert_eq!(&fields[0].1[..], &b"Content-Type: application/octet-stream\r\n\r\n"[..]);
+
+        assert!(fields[1].0.external_body().is_none());
+    }
+
+    #[test]
+    fn test_trim_field_names() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\" padded\t\"; filename=\" file.txt \"\r\n\r\n\
+                            \x20data \r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        assert_eq!(&*multipart.read_entry().unwrap().unwrap().headers.name, " padded\t");
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_trim_field_names(true);
+        let mut field = multipart.read_entry().unwrap().unwrap();
+        assert_eq!(&*field.headers.name, "padded");
+        assert_eq!(field.headers.filename.as_ref().unwrap(), " file.txt ");
+
+        let mut data = String::new();
+        field.data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, " data ");
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn test_save_hashing() {
+        use super::save::{SaveResult, SavedData};
+        use sha2::{Digest, Sha256};
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"small\"\r\n\r\n\
+                            text\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"large\"\r\n\r\n\
+                            text larger than the memory threshold\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+
+        let entries = match multipart.save().memory_threshold(8).with_hashing::<Sha256>().temp() {
+            SaveResult::Full(entries) => entries,
+            _ => panic!("expected full save"),
+        };
+
+        let field = |name: &str| entries.fields.values().flat_map(|fields| fields.iter())
+            .find(|field| &*field.headers.name == name).unwrap();
+
+        let small = field("small");
+        assert_eq!(small.hash.as_ref().unwrap()[..], Sha256::digest(b"text")[..]);
+
+        let large = field("large");
+        match large.data {
+            SavedData::File(..) => (),
+            ref other => panic!("expected file, got {:?}", other),
+        }
+
+        let mut saved = Vec::new();
+        large.data.readable().unwrap().read_to_end(&mut saved).unwrap();
+        assert_eq!(saved, &b"text larger than the memory threshold"[..]);
+        assert_eq!(large.hash.as_ref().unwrap()[..], Sha256::digest(&saved)[..]);
+    }
+
+    #[test]
+    fn test_save_collect_errors() {
+        use super::save::{PartialReason, SaveResult};
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"one\"\r\n\r\n\
+                            one\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"large\"\r\n\r\n\
+                            text larger than the size limit\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"two\"\r\n\r\n\
+                            two\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"three\"\r\n\r\n\
+                            three\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+
+        let entries = match multipart.save().size_limit(8).collect_errors(true).temp() {
+            SaveResult::Full(entries) => entries,
+            _ => panic!("expected full save"),
+        };
+
+        assert_eq!(entries.fields_count(), 3);
+
+        let mut names: Vec<_> = entries.fields.keys().map(|name| name.to_string()).collect();
+        names.sort();
+        assert_eq!(names, ["one", "three", "two"]);
+
+        assert_eq!(entries.errors.len(), 1);
+        assert_eq!(&*entries.errors[0].0, "large");
+
+        match entries.errors[0].1 {
+            PartialReason::SizeLimit => (),
+            ref other => panic!("expected size limit, got {:?}", other),
+        }
+
+        // the first error stops the save by default
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+
+        match multipart.save().size_limit(8).temp() {
+            SaveResult::Partial(partial, PartialReason::SizeLimit) =>
+                assert_eq!(partial.entries.fields_count(), 1),
+            SaveResult::Partial(_, other) => panic!("expected size limit, got {:?}", other),
+            _ => panic!("expected partial save"),
+        }
+    }
+
+    #[test]
+    fn test_save_atomic() {
+        use super::save::{SaveResult, SavedData};
+        use std::fs;
+        use tempdir::TempDir;
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.txt\"\r\n\r\n\
+                            file contents\r\n\
+                            --boundary--";
+
+        let dir = TempDir::new("multipart_atomic_test").unwrap();
+        let path = dir.path().join("file.txt");
+
+        let dir_entries = || fs::read_dir(dir.path()).unwrap().count();
+
+        {
+            let mut multipart = Multipart::with_body(body, BOUNDARY);
+            let mut field = multipart.read_entry().unwrap().unwrap();
+
+            match field.data.save().memory_threshold(0).size_limit(4).atomic(true).with_path(&path) {
+                SaveResult::Partial(SavedData::Bytes(ref bytes), _) if bytes.is_empty() => (),
+                other => panic!("expected empty partial result, got {:?}", other),
+            }
+        }
+
+        // neither the file nor the temporary file exists
+        assert!(!path.exists());
+        assert_eq!(dir_entries(), 0);
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        let mut field = multipart.read_entry().unwrap().unwrap();
+
+        match field.data.save().memory_threshold(0).atomic(true).with_path(&path) {
+            SaveResult::Full(SavedData::File(ref saved, 13)) => assert_eq!(*saved, path),
+            other => panic!("expected file, got {:?}", other),
+        }
+
+        let mut contents = String::new();
+        fs::File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "file contents");
+        assert_eq!(dir_entries(), 1);
+    }
+
+    #[test]
+    fn test_empty_fields() {
+        use super::save::{SaveResult, SavedData};
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"empty_text\"\r\n\r\n\
+                            \r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            text\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"empty_file\"; filename=\"empty.bin\"\r\n\
+                            Content-Type: application/octet-stream\r\n\r\n\
+                            \r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.bin\"\r\n\
+                            Content-Type: application/octet-stream\r\n\r\n\
+                            file\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"last\"\r\n\r\n\
+                            \r\n\
+                            --boundary--";
+
+        let expected = [("empty_text", ""), ("text", "text"), ("empty_file", ""), ("file", "file"),
+                        ("last", "")];
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+
+        for &(name, data) in &expected {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(&*field.headers.name, name);
+
+            let mut buf = Vec::new();
+            field.data.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf, data.as_bytes(), "field {:?}", name);
+        }
+
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+
+        let entries = match multipart.save().temp() {
+            SaveResult::Full(entries) => entries,
+            _ => panic!("expected full save"),
+        };
+
+        assert_eq!(entries.fields_count(), expected.len() as u32);
+
+        for &(name, data) in &expected {
+            let field = entries.fields.values().flat_map(|fields| fields.iter())
+                .find(|field| &*field.headers.name == name).unwrap();
+
+            match field.data {
+                SavedData::Text(ref text) => assert_eq!(text, data),
+                SavedData::Bytes(ref bytes) => assert_eq!(&bytes[..], data.as_bytes()),
+                ref other => panic!("expected field {:?} in memory, got {:?}", name, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_limits() {
+        let multipart = Multipart::with_body(&b""[..], BOUNDARY);
+        assert_eq!(multipart.limits(), Limits::default());
+
+        let limits = Limits { max_text_total: Some(1024), ..Limits::default() };
+        let multipart = Multipart::with_body(&b""[..], BOUNDARY).with_limits(limits);
+        assert_eq!(multipart.limits(), limits);
+        assert_eq!(multipart.limits().max_text_total, Some(1024));
+    }
+
+    #[test]
+    fn test_size_limit_for() {
+        use super::FieldTooLarge;
+
+        const BODY: &[u8] = b"--boundary\r\n\
+                              Content-Disposition: form-data; name=\"data\"; filename=\"data.json\"\r\n\
+                              Content-Type: application/json; charset=utf-8\r\n\r\n\
+                              {\"key\": \"value\"}\r\n\
+                              --boundary\r\n\
+                              Content-Disposition: form-data; name=\"icon\"; filename=\"icon.png\"\r\n\
+                              Content-Type: image/png\r\n\r\n\
+                              0123456789abcdef\r\n\
+                              --boundary\r\n\
+                              Content-Disposition: form-data; name=\"photo\"; filename=\"photo.gif\"\r\n\
+                              Content-Type: image/gif\r\n\r\n\
+                              0123456789abcdef\r\n\
+                              --boundary--";
+
+        /// Read all fields, returning the names of those read before any error.
+        fn read_all(multipart: Multipart<&[u8]>) -> (Vec<String>, io::Result<()>) {
+            let mut multipart = multipart;
+            let mut names = Vec::new();
+
+            loop {
+                let mut field = match multipart.read_entry() {
+                    Ok(Some(field)) => field,
+                    Ok(None) => return (names, Ok(())),
+                    Err(e) => return (names, Err(e)),
+                };
+
+                if let Err(e) = field.data.read_to_end(&mut Vec::new()) {
+                    return (names, Err(e));
+                }
+
+                names.push(field.headers.name.to_string());
+            }
+        }
+
+        fn too_large(res: io::Result<()>) -> FieldTooLarge {
+            let err = res.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            err.get_ref().unwrap().downcast_ref::<FieldTooLarge>().unwrap().clone()
+        }
+
+        let (names, res) = read_all(Multipart::with_body(BODY, BOUNDARY)
+            .with_size_limit_for("application/json", 8)
+            .with_size_limit_for("image/*", 64));
+        assert!(names.is_empty());
+        assert_eq!(too_large(res), FieldTooLarge { pattern: "application/json".into(), limit: 8 });
+
+        // the most specific pattern wins
+        let (names, res) = read_all(Multipart::with_body(BODY, BOUNDARY)
+            .with_size_limit_for("application/json", 64)
+            .with_size_limit_for("image/*", 8)
+            .with_size_limit_for("IMAGE/PNG", 16)
+            .with_size_limit_for("*/*", 1));
+        assert_eq!(names, ["data", "icon"]);
+        assert_eq!(too_large(res), FieldTooLarge { pattern: "image/*".into(), limit: 8 });
+
+        // setting a pattern again replaces its limit
+        let (names, res) = read_all(Multipart::with_body(BODY, BOUNDARY)
+            .with_size_limit_for("image/*", 8)
+            .with_size_limit_for("image/*", 16));
+        assert_eq!(names, ["data", "icon", "photo"]);
+        res.unwrap();
+    }
+
+    #[test]
+    fn test_max_nesting_depth() {
+        use super::NestingTooDeep;
+
+        // a field holding a `multipart/mixed` body nested three levels deep
+        let mut data = "innermost".to_owned();
+        let mut content_type = "text/plain".to_owned();
+
+        for level in (1 .. 4).rev() {
+            data = format!("--level{0}\r\n\
+                            Content-Disposition: file; filename=\"level{0}\"\r\n\
+                            Content-Type: {1}\r\n\r\n\
+                            {2}\r\n\
+                            --level{0}--", level, content_type, data);
+            content_type = format!("multipart/mixed; boundary=level{}", level);
+        }
+
+        let body = format!("--boundary\r\n\
+                            Content-Disposition: form-data; name=\"nested\"\r\n\
+                            Content-Type: {}\r\n\r\n\
+                            {}\r\n\
+                            --boundary--", content_type, data);
+
+        // each nested body borrows the field it is read from, so the levels are spelled out
+        fn read_innermost(body: &[u8], limits: Limits) -> io::Result<String> {
+            let mut multipart = Multipart::with_body(body, BOUNDARY).with_limits(limits);
+            let mut field = multipart.read_entry()?.unwrap();
+
+            let mut level1 = field.read_nested()?;
+            let mut field1 = level1.read_entry()?.unwrap();
+
+            let mut level2 = field1.read_nested()?;
+            let mut field2 = level2.read_entry()?.unwrap();
+
+            let mut level3 = field2.read_nested()?;
+            let mut field3 = level3.read_entry()?.unwrap();
+            assert_eq!(field3.headers.filename.as_ref().unwrap(), "level3");
+
+            field3.read_text()
+        }
+
+        let limits = Limits { max_nesting_depth: Some(3), ..Limits::default() };
+        assert_eq!(read_innermost(body.as_bytes(), limits).unwrap(), "innermost");
+
+        let err = read_innermost(body.as_bytes(), Limits::default()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<NestingTooDeep>()),
+                   Some(&NestingTooDeep { limit: 2 }));
+
+        // a field which isn't multipart can't be read as nested
+        let mut multipart = Multipart::with_body(&b"--boundary\r\n\
+                                                   Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                                                   text\r\n\
+                                                   --boundary--"[..], BOUNDARY);
+        let mut field = multipart.read_entry().unwrap().unwrap();
+
+        match field.read_nested() {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+            Ok(_) => panic!("expected error reading a text field as nested"),
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        const BODY: &[u8] = b"--boundary\r\n\
+                              Content-Disposition: form-data; name=\"text1\"\r\n\r\n\
+                              12345\r\n\
+                              --boundary\r\n\
+                              Content-Disposition: form-data; name=\"file1\"; filename=\"a.txt\"\r\n\r\n\
+                              0123456789\r\n\
+                              --boundary\r\n\
+                              Content-Disposition: form-data; name=\"text2\"\r\n\r\n\
+                              67890\r\n\
+                              --boundary\r\n\
+                              Content-Disposition: form-data; name=\"file2\"; filename=\"b.txt\"\r\n\r\n\
+                              0123456789\r\n\
+                              --boundary--";
+
+        /// Read all fields, returning the names of those read before any error.
+        fn read_all(limits: Limits) -> (Vec<String>, io::Result<()>) {
+            let mut multipart = Multipart::with_body(BODY, BOUNDARY).with_limits(limits);
+            let mut names = Vec::new();
+
+            loop {
+                let mut field = match multipart.read_entry() {
+                    Ok(Some(field)) => field,
+                    Ok(None) => return (names, Ok(())),
+                    Err(e) => return (names, Err(e)),
+                };
+
+                if let Err(e) = field.data.read_to_end(&mut Vec::new()) {
+                    return (names, Err(e));
+                }
+
+                names.push(field.headers.name.to_string());
+            }
+        }
+
+        fn limit_exceeded(res: io::Result<()>) -> LimitExceeded {
+            let err = res.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            *err.get_ref().unwrap().downcast_ref::<LimitExceeded>().unwrap()
+        }
+
+        // exactly at both limits
+        let (names, res) = read_all(Limits { max_text_total: Some(10), max_file_total: Some(20), ..Limits::default() });
+        assert_eq!(names, ["text1", "file1", "text2", "file2"]);
+        res.unwrap();
+
+        // the text limit doesn't affect files
+        let (names, res) = read_all(Limits { max_text_total: Some(9), ..Limits::default() });
+        assert_eq!(names, ["text1", "file1"]);
+        assert_eq!(limit_exceeded(res), LimitExceeded::TextTotal(9));
+
+        // and vice versa
+        let (names, res) = read_all(Limits { max_file_total: Some(19), ..Limits::default() });
+        assert_eq!(names, ["text1", "file1", "text2"]);
+        assert_eq!(limit_exceeded(res), LimitExceeded::FileTotal(19));
+
+        // only file fields count towards the number of files
+        let (names, res) = read_all(Limits { max_files: Some(2), ..Limits::default() });
+        assert_eq!(names, ["text1", "file1", "text2", "file2"]);
+        res.unwrap();
+
+        // skipped data doesn't count
+        let mut multipart = Multipart::with_body(BODY, BOUNDARY)
+            .with_limits(Limits { max_text_total: Some(5), max_file_total: Some(0), ..Limits::default() });
+
+        let mut count = 0;
+        while multipart.read_entry().unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn test_max_files() {
+        let mut body = Vec::new();
+
+        for i in 0..6 {
+            write!(body, "--boundary\r\n\
+                          Content-Disposition: form-data; name=\"text{0}\"\r\n\r\n\
+                          text\r\n\
+                          --boundary\r\n\
+                          Content-Disposition: form-data; name=\"image{0}\"; filename=\"{0}.png\"\r\n\r\n\
+                          image\r\n", i).unwrap();
+        }
+
+        body.extend_from_slice(b"--boundary--");
+
+        let limits = Limits { max_files: Some(5), ..Limits::default() };
+        let mut multipart = Multipart::with_body(&body[..], BOUNDARY).with_limits(limits);
+        let mut files = 0;
+        let err;
+
+        loop {
+            match multipart.read_entry() {
+                Ok(Some(field)) => if field.headers.filename.is_some() { files += 1 },
+                Ok(None) => panic!("expected the limit to be exceeded"),
+                Err(e) => { err = e; break; },
+            }
+        }
+
+        assert_eq!(files, 5);
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(*err.get_ref().unwrap().downcast_ref::<LimitExceeded>().unwrap(),
+                   LimitExceeded::TooManyFiles(5));
+        // the text field before the sixth file was still read
+        assert_eq!(multipart.fields_read(), 12);
+    }
+
+    #[test]
+    fn test_boundary_bytes() {
+        let boundary = b"\x00bound\xFF\x80ary".to_vec();
+
+        let mut body = Vec::new();
+        body.extend_from_slice(b"--");
+        body.extend_from_slice(&boundary);
+        body.extend_from_slice(b"\r\nContent-Disposition: form-data; name=\"text\"\r\n\r\n\
+                                 data\r\n--");
+        body.extend_from_slice(&boundary);
+        body.extend_from_slice(b"--");
+
+        let mut multipart = match Multipart::from_boundary_bytes(boundary, &body[..]) {
+            Ok(multipart) => multipart,
+            Err(e) => panic!("unexpected error: {}", e),
+        };
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(&*field.headers.name, "text");
+            assert_eq!(field.read_text().unwrap(), "data");
+        }
+
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        for &len in &[0, 71] {
+            match Multipart::from_boundary_bytes(vec![b'a'; len], &body[..]) {
+                Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidInput),
+                Ok(_) => panic!("expected error for boundary length {}", len),
+            }
+        }
+
+        assert!(Multipart::from_boundary_bytes(vec![b'a'; 70], &body[..]).is_ok());
+    }
+
+    #[test]
+    fn test_strict_part_length() {
+        use super::PartLengthMismatch;
+
+        fn body(declared: u64) -> String {
+            format!("--boundary\r\n\
+                     Content-Disposition: form-data; name=\"text\"\r\n\
+                     Content-Length: {}\r\n\r\n\
+                     some text\r\n\
+                     --boundary\r\n\
+                     Content-Disposition: form-data; name=\"unchecked\"\r\n\r\n\
+                     more text\r\n\
+                     --boundary--", declared)
+        }
+
+        fn read_fields(body: &str) -> io::Result<Vec<Option<u64>>> {
+            let mut multipart = Multipart::with_body(body.as_bytes(), BOUNDARY)
+                .with_strict_part_length(true);
+            let mut lengths = Vec::new();
+
+            while let Some(field) = multipart.read_entry()? {
+                lengths.push(field.headers.content_length);
+            }
+
+            Ok(lengths)
+        }
+
+        assert_eq!(read_fields(&body(9)).unwrap(), vec![Some(9), None]);
+
+        for &declared in &[4, 32] {
+            let err = read_fields(&body(declared)).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+            let mismatch = err.get_ref()
+                .and_then(|err| err.downcast_ref::<PartLengthMismatch>()).unwrap();
+            assert_eq!(*mismatch, PartLengthMismatch {
+                name: "text".into(),
+                declared: declared,
+                actual: 9,
+            });
+        }
+
+        // not checked by default
+        let body = body(4);
+        let mut multipart = Multipart::with_body(body.as_bytes(), BOUNDARY);
+        assert_eq!(multipart.read_entry().unwrap().unwrap().headers.content_length, Some(4));
+        assert!(multipart.read_entry().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_take_file() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"upload\"\r\n\r\n\
+                            a text field of the same name\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"other\"; filename=\"other.txt\"\r\n\r\n\
+                            another file\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"upload\"; filename=\"upload.bin\"\r\n\
+                            Content-Type: application/octet-stream\r\n\r\n\
+                            \x00\x01\x02\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"after\"\r\n\r\n\
+                            after\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        let (headers, data) = multipart.take_file("upload").unwrap().unwrap();
+
+        assert_eq!(headers.filename.as_ref().unwrap(), "upload.bin");
+        assert_eq!(data, b"\x00\x01\x02");
+
+        // the rest of the request can still be read
+        assert_eq!(&*multipart.read_entry().unwrap().unwrap().headers.name, "after");
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        assert!(multipart.take_file("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_finish() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"read\"\r\n\r\n\
+                            read\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"unread\"\r\n\r\n\
+                            unread\r\n\
+                            --boundary--\r\n\
+                            trailing bytes";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        multipart.read_entry().unwrap().unwrap();
+
+        let mut rest = String::new();
+        multipart.finish().unwrap().read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "\r\ntrailing bytes");
+
+        let truncated = &body[..body.len() - 40];
+        assert!(Multipart::with_body(truncated, BOUNDARY).finish().is_err());
+    }
+
+    #[test]
+    fn test_fields_read() {
+        use super::save::SaveResult;
+
+        let mut body = String::new();
+
+        for i in 0..10 {
+            body.push_str(&format!("--boundary\r\n\
+                                    Content-Disposition: form-data; name=\"field{}\"\r\n\r\n\
+                                    value {}\r\n", i, i));
+        }
+
+        body.push_str("--boundary--");
+
+        let mut multipart = Multipart::with_body(body.as_bytes(), BOUNDARY);
+        assert_eq!(multipart.fields_read(), 0);
+
+        multipart.read_entry().unwrap().unwrap();
+        assert_eq!(multipart.fields_read(), 1);
+
+        while multipart.read_entry().unwrap().is_some() {}
+        assert_eq!(multipart.fields_read(), 10);
+
+        let mut multipart = Multipart::with_body(body.as_bytes(), BOUNDARY);
+
+        match multipart.save().temp() {
+            SaveResult::Full(entries) => assert_eq!(entries.fields_count(), 10),
+            _ => panic!("expected full save"),
+        }
+
+        assert_eq!(multipart.fields_read(), 10);
+    }
+
+    #[test]
+    fn test_files_only() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+                            holiday\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"first\"; filename=\"a.txt\"\r\n\r\n\
+                            file a\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"description\"\r\n\r\n\
+                            a long description which is never read\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"second\"; filename=\"b.txt\"\r\n\r\n\
+                            file b\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"tags\"\r\n\r\n\
+                            beach\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        let mut files = Vec::new();
+
+        {
+            let mut files_only = multipart.files_only();
+
+            while let Some(mut field) = files_only.read_entry().unwrap() {
+                let mut data = String::new();
+                field.data.read_to_string(&mut data).unwrap();
+                files.push((field.headers.name.to_string(), data));
+            }
+
+            assert!(files_only.read_entry().unwrap().is_none());
+        }
+
+        assert_eq!(files, [("first".to_string(), "file a".to_string()),
+                           ("second".to_string(), "file b".to_string())]);
+
+        // the text fields were all read through, up to the end of the request
+        assert_eq!(multipart.fields_read(), 5);
+        assert!(multipart.read_entry().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_peek_name() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"first\"\r\n\r\n\
+                            first value\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"second\"; filename=\"second.txt\"\r\n\r\n\
+                            second value\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"third\"\r\n\r\n\
+                            third value\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+
+        assert_eq!(multipart.peek_name().unwrap().unwrap(), "first");
+        assert_eq!(multipart.peek_name().unwrap().unwrap(), "first");
+        assert_eq!(multipart.fields_read(), 1);
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(&*field.headers.name, "first");
+            assert_eq!(field.read_text().unwrap(), "first value");
+        }
+
+        assert_eq!(multipart.fields_read(), 1);
+
+        // the second field is skipped without reading its data
+        assert_eq!(multipart.peek_name().unwrap().unwrap(), "second");
+        assert_eq!(multipart.read_entry().unwrap().unwrap().headers.filename.unwrap(), "second.txt");
+
+        assert_eq!(multipart.peek_name().unwrap().unwrap(), "third");
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(&*field.headers.name, "third");
+            assert_eq!(field.read_text().unwrap(), "third value");
+        }
+
+        assert!(multipart.peek_name().unwrap().is_none());
+        assert!(multipart.read_entry().unwrap().is_none());
+        assert_eq!(multipart.fields_read(), 3);
+    }
+
+    #[test]
+    fn test_total_timeout() {
+        use mock::MockClock;
+        use server::timeout::DeadlineExceeded;
+        use std::time::Duration;
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"first\"\r\n\r\n\
+                            first\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"second\"\r\n\r\n\
+                            second\r\n\
+                            --boundary--";
+
+        let clock = MockClock::new();
+        let timeout = Duration::from_secs(10);
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY)
+            .with_total_timeout_clock(timeout, clock.clone());
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(field.read_text().unwrap(), "first");
+        }
+
+        clock.advance(Duration::from_secs(11));
+
+        let err = match multipart.read_entry() {
+            Err(err) => err,
+            Ok(_) => panic!("expected error reading past the deadline"),
+        };
+
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+        assert_eq!(err.get_ref().and_then(|e| e.downcast_ref::<DeadlineExceeded>()),
+                   Some(&DeadlineExceeded { timeout: timeout }));
+    }
+
+    #[test]
+    fn test_into_pairs() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"a\"\r\n\r\n\
+                            first\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.txt\"\r\n\r\n\
+                            file contents\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"b\"\r\n\r\n\
+                            \r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"a\"\r\n\r\n\
+                            second\r\n\
+                            --boundary--";
+
+        let pairs = Multipart::with_body(body, BOUNDARY).into_pairs().unwrap();
+
+        let expected = [("a", "first"), ("b", ""), ("a", "second")];
+        let expected: Vec<(String, String)> = expected.iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn test_take() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"one\"\r\n\r\n\
+                            1\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"two\"\r\n\r\n\
+                            second field\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"three\"\r\n\r\n\
+                            3\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"four\"\r\n\r\n\
+                            4\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"five\"\r\n\r\n\
+                            5\r\n\
+                            --boundary--\
+                            trailing data";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+
+        {
+            let mut take = multipart.take(2);
+
+            assert_eq!(&*take.read_entry().unwrap().unwrap().headers.name, "one");
+
+            {
+                // only partially read the last field; the rest should be drained
+                let mut field = take.read_entry().unwrap().unwrap();
+                assert_eq!(&*field.headers.name, "two");
+
+                let mut start = [0u8; 6];
+                field.data.read_exact(&mut start).unwrap();
+                assert_eq!(&start, b"second");
+            }
+
+            assert_eq!(take.remaining(), 0);
+            assert!(take.read_entry().unwrap().is_none());
+            assert!(take.read_entry().unwrap().is_none());
+        }
+
+        let mut remainder = String::new();
+        multipart.into_remainder().read_to_string(&mut remainder).unwrap();
+
+        assert!(remainder.starts_with("\r\n--boundary\r\n"), "remainder: {:?}", remainder);
+        assert!(remainder.ends_with("--boundary--trailing data"), "remainder: {:?}", remainder);
+
+        let names: Vec<String> = parse_all(remainder.as_bytes(), BOUNDARY).unwrap().into_iter()
+            .map(|(headers, _)| headers.name.to_string()).collect();
+        assert_eq!(names, ["three", "four", "five"]);
+    }
+
+    #[test]
+    fn test_unique_field_names() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"field\"\r\n\r\n\
+                            one\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"other\"\r\n\r\n\
+                            two\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"field\"\r\n\r\n\
+                            three\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        let mut count = 0;
+        multipart.foreach_entry(|_| count += 1).unwrap();
+        assert_eq!(count, 3);
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_unique_field_names(true);
+        assert_eq!(&*multipart.read_entry().unwrap().unwrap().headers.name, "field");
+        assert_eq!(&*multipart.read_entry().unwrap().unwrap().headers.name, "other");
+
+        let err = match multipart.read_entry() {
+            Err(err) => err,
+            Ok(_) => panic!("expected an error for the duplicate field"),
+        };
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("duplicate field name"), "{}", err);
+    }
+
+    #[test]
+    fn test_field_save_to_path() {
+        use std::fs::File;
+        use tempdir::TempDir;
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.bin\"\r\n\
+                            Content-Type: application/octet-stream\r\n\r\n\
+                            \x00\x01binary\x02\r\n\
+                            --boundary--";
+
+        let dir = TempDir::new("multipart-test").unwrap();
+        let path = dir.path().join("saved.bin");
+
+        // the file should be truncated
+        File::create(&path).unwrap().write_all(b"previous contents which are longer").unwrap();
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        let written = multipart.read_entry().unwrap().unwrap().save_to_path(&path).unwrap();
+
+        let mut saved = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut saved).unwrap();
+
+        assert_eq!(written, 9);
+        assert_eq!(saved, b"\x00\x01binary\x02");
+    }
+
+    #[test]
+    fn test_field_names() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"b\"\r\n\r\n\
+                            first\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"a\"; filename=\"file.bin\"\r\n\
+                            Content-Type: application/octet-stream\r\n\r\n\
+                            \x00\x01\x02\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"b\"\r\n\r\n\
+                            second\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        assert_eq!(multipart.field_names().unwrap(), ["b", "a", "b"]);
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_unique_field_names(true);
+        assert_eq!(multipart.field_names().unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_missing_content_type() {
+        use super::FromRequestError;
+        use mock::ServerRequest;
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            some text\r\n\
+                            --boundary--";
+
+        match Multipart::try_from_request(ServerRequest::new(body, BOUNDARY).without_content_type()) {
+            Err(FromRequestError::MissingContentType(req)) => assert_eq!(req.data, body),
+            Err(err) => panic!("expected `MissingContentType`, got {:?}", err),
+            Ok(_) => panic!("expected an error for a request without `Content-Type`"),
+        }
+
+        assert!(Multipart::from_request(ServerRequest::new(body, BOUNDARY).without_content_type())
+            .is_err());
+
+        let mut multipart = Multipart::try_from_request(ServerRequest::new(body, BOUNDARY))
+            .ok().unwrap();
+        assert_eq!(&*multipart.read_entry().unwrap().unwrap().headers.name, "text");
+    }
+
+    #[test]
+    fn test_strict_content_length() {
+        use super::ContentLengthMismatch;
+        use mock::ServerRequest;
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            some text\r\n\
+                            --boundary--";
+
+        let len = body.len() as u64;
+
+        let read_all = |multipart: Multipart<&[u8]>| {
+            let mut multipart = multipart.with_strict_content_length(true);
+            multipart.foreach_entry(|_| ())
+        };
+
+        let mismatch = |err: io::Error| {
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            *err.get_ref().and_then(|err| err.downcast_ref::<ContentLengthMismatch>()).unwrap()
+        };
+
+        read_all(Multipart::with_body(body, BOUNDARY).with_content_length(len)).unwrap();
+        // not checked without a declared length
+        read_all(Multipart::with_body(body, BOUNDARY)).unwrap();
+
+        // short body
+        let err = read_all(Multipart::with_body(body, BOUNDARY).with_content_length(len + 5))
+            .unwrap_err();
+        assert_eq!(mismatch(err), ContentLengthMismatch { declared: len + 5, actual: len });
+
+        // long body: the closing boundary or trailing data is past the declared length
+        let err = read_all(Multipart::with_body(body, BOUNDARY).with_content_length(len - 2))
+            .unwrap_err();
+        assert_eq!(mismatch(err), ContentLengthMismatch { declared: len - 2, actual: len });
+
+        let padded = [body, &b"\r\npadding"[..]].concat();
+        let err = read_all(Multipart::with_body(&padded[..], BOUNDARY).with_content_length(len))
+            .unwrap_err();
+        assert_eq!(mismatch(err), ContentLengthMismatch { declared: len, actual: len + 1 });
+
+        // the declared length is taken from the request
+        let mut req = ServerRequest::new(body, BOUNDARY);
+        req.content_len = Some(len + 1);
+
+        let mut multipart = Multipart::from_request(req).ok().unwrap()
+            .with_strict_content_length(true);
+        let err = multipart.foreach_entry(|_| ()).unwrap_err();
+        assert_eq!(mismatch(err), ContentLengthMismatch { declared: len + 1, actual: len });
+    }
+
+    #[cfg(feature = "async-bridge")]
+    #[test]
+    fn test_data_stream() {
+        use futures::{Future, Stream};
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.txt\"\r\n\r\n\
+                            streamed field data\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"next\"\r\n\r\n\
+                            next\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+
+        {
+            let field = multipart.read_entry().unwrap().unwrap();
+            let chunks = field.data.into_stream().collect().wait().unwrap();
+            assert_eq!(chunks.concat(), b"streamed field data");
+        }
+
+        assert_eq!(&*multipart.read_entry().unwrap().unwrap().headers.name, "next");
+    }
+
+    #[test]
+    fn test_buffered_len() {
+        let headers: &[u8] = b"--boundary\r\n\
+                               Content-Disposition: form-data; name=\"text\"\r\n\r\n";
+
+        let body = [headers, &b"some text\r\n--boundary--"[..]].concat();
+
+        let mut multipart = Multipart::with_body(&body[..], BOUNDARY);
+        assert_eq!(multipart.buffered_len(), 0);
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            let mut start = [0u8; 4];
+            field.data.read_exact(&mut start).unwrap();
+        }
+
+        // what's left of the body is either buffered or still in the source
+        let unread = {
+            let source: &&[u8] = multipart.borrow();
+            source.len()
+        };
+
+        assert!(multipart.buffered_len() > 0);
+        assert_eq!(multipart.buffered_len() + unread, body.len() - headers.len() - 4);
+
+        let buffered_len = multipart.buffered_len();
+
+        let mut remainder = Vec::new();
+        multipart.into_remainder().read_to_end(&mut remainder).unwrap();
+        assert_eq!(remainder, &body[body.len() - buffered_len - unread..]);
+    }
+
+    #[test]
+    fn test_transfer_encoding() {
+        use super::UnknownEncoding;
+
+        fn body(encoding: &str) -> Vec<u8> {
+            format!("--boundary\r\n\
+                     Content-Disposition: form-data; name=\"text\"\r\n\
+                     Content-Transfer-Encoding: {}\r\n\r\n\
+                     some text\r\n\
+                     --boundary--", encoding).into_bytes()
+        }
+
+        let seven_bit = body("7bit");
+        let mut multipart = Multipart::with_body(&seven_bit[..], BOUNDARY)
+            .with_transfer_decoding(true);
+
+        let mut field = multipart.read_entry().unwrap().unwrap();
+        assert_eq!(field.headers.transfer_encoding.as_ref().unwrap(), "7bit");
+
+        let mut data = String::new();
+        field.data.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "some text");
+
+        let base64 = b"--boundary\r\n\
+                       Content-Disposition: form-data; name=\"base64\"\r\n\
+                       Content-Transfer-Encoding: base64\r\n\r\n\
+                       c29tZSB0\r\nZXh0\r\n\
+                       --boundary\r\n\
+                       Content-Disposition: form-data; name=\"qp\"\r\n\
+                       Content-Transfer-Encoding: Quoted-Printable\r\n\r\n\
+                       some=\r\n text =3D =C3=A9\r\n\
+                       --boundary--";
+
+        let mut multipart = Multipart::with_body(&base64[..], BOUNDARY)
+            .with_transfer_decoding(true);
+
+        assert_eq!(multipart.read_entry().unwrap().unwrap().read_text().unwrap(), "some text");
+        assert_eq!(multipart.read_entry().unwrap().unwrap().read_text().unwrap(),
+                   "some text = \u{E9}");
+
+        // but not when decoding is off
+        let mut multipart = Multipart::with_body(&base64[..], BOUNDARY);
+        assert_eq!(multipart.read_entry().unwrap().unwrap().read_text().unwrap(),
+                   "c29tZSB0\r\nZXh0");
+
+        let bogus = body("x-bogus");
+
+        match Multipart::with_body(&bogus[..], BOUNDARY).with_transfer_decoding(true).read_entry() {
+            Err(err) => assert_eq!(err.kind(), ErrorKind::InvalidData),
+            Ok(_) => panic!("expected an error for an unknown encoding"),
+        }
+
+        let mut multipart = Multipart::with_body(&bogus[..], BOUNDARY)
+            .with_transfer_decoding(true).on_unknown_encoding(UnknownEncoding::PassThrough);
+        let field = multipart.read_entry().unwrap().unwrap();
+        assert_eq!(field.headers.transfer_encoding.as_ref().unwrap(), "x-bogus");
+
+        // not checked by default
+        let mut multipart = Multipart::with_body(&bogus[..], BOUNDARY);
+        assert!(multipart.read_entry().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_trim_header_values() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\
+                            Content-Type: text/plain \t\r\n\
+                            Content-ID: <text@example.com> \r\n\r\n\
+                            text\r\n\
+                            --boundary--";
+
+        fn raw_header<'a>(headers: &'a super::FieldHeaders, name: &str) -> &'a str {
+            &headers.raw_headers().iter().find(|&&(ref raw_name, _)| raw_name == name).unwrap().1
+        }
+
+        let headers = &parse_all(body, BOUNDARY).unwrap()[0].0;
+        assert_eq!(headers.content_type, Some(::mime::TEXT_PLAIN));
+        assert_eq!(headers.content_id.as_ref().unwrap(), "<text@example.com>");
+        // `httparse` trims trailing whitespace, which `raw_headers()` restores
+        assert_eq!(raw_header(headers, "Content-Type"), "text/plain \t");
+        assert_eq!(raw_header(headers, "Content-ID"), "<text@example.com> ");
+
+        // a padded `Content-Type` isn't valid as sent
+        match Multipart::with_body(body, BOUNDARY).with_trim_header_values(false).read_entry() {
+            Err(err) => assert_eq!(err.kind(), ErrorKind::InvalidData),
+            Ok(_) => panic!("expected an error for the padded `Content-Type`"),
+        }
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\
+                            Content-ID: <text@example.com> \r\n\r\n\
+                            text\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_trim_header_values(false);
+        let headers = multipart.read_entry().unwrap().unwrap().headers;
+        assert_eq!(headers.content_id.as_ref().unwrap(), "<text@example.com> ");
+    }
+
+    #[test]
+    fn test_is_transfer_decoded() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"encoded\"\r\n\
+                            Content-Transfer-Encoding: base64\r\n\r\n\
+                            c29tZSB0ZXh0\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"plain\"\r\n\r\n\
+                            some text\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_transfer_decoding(true);
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(field.data.transfer_encoding(), Some("base64"));
+            assert!(field.data.is_transfer_decoded());
+            assert_eq!(field.read_text().unwrap(), "some text");
+        }
+
+        let field = multipart.read_entry().unwrap().unwrap();
+        assert_eq!(field.data.transfer_encoding(), None);
+        assert!(!field.data.is_transfer_decoded());
+
+        // not decoded when decoding is off
+        let mut multipart = Multipart::with_body(body, BOUNDARY);
+        assert!(!multipart.read_entry().unwrap().unwrap().data.is_transfer_decoded());
+    }
+
+    #[test]
+    fn test_raw_body() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"encoded\"\r\n\
+                            Content-Transfer-Encoding: base64\r\n\r\n\
+                            c29tZSB0ZXh0\r\nbW9yZQ==\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"decoded\"\r\n\
+                            Content-Transfer-Encoding: base64\r\n\r\n\
+                            c29tZSB0ZXh0\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, BOUNDARY).with_transfer_decoding(true);
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+
+            let mut raw = Vec::new();
+            field.raw_body().read_to_end(&mut raw).unwrap();
+            assert_eq!(raw, b"c29tZSB0ZXh0\r\nbW9yZQ==");
+
+            // the field can't be read decoded after being read raw
+            let err = field.data.read(&mut [0; 4]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+
+        let mut field = multipart.read_entry().unwrap().unwrap();
+
+        let mut decoded = [0; 4];
+        field.data.read_exact(&mut decoded).unwrap();
+        assert_eq!(&decoded, b"some");
+
+        // nor raw after being read decoded
+        let err = field.raw_body().read(&mut [0; 4]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
 }
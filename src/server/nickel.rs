@@ -2,7 +2,7 @@
 pub extern crate nickel;
 
 use self::nickel::hyper;
-use self::hyper::header::ContentType;
+use self::hyper::header::{ContentLength, ContentType};
 
 pub use self::nickel::Request as NickelRequest;
 pub use self::nickel::hyper::server::Request as HyperRequest;
@@ -24,6 +24,14 @@ impl<'r, 'mw: 'r, 'server: 'mw, D: 'mw> HttpRequest for Maybe<'r, 'mw, 'server,
         cont_type.get_param("boundary").map(|v| v.as_str())
     }
 
+    fn has_content_type(&self) -> bool {
+        self.0.origin.headers.has::<ContentType>()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.0.origin.headers.get::<ContentLength>().map(|len| len.0)
+    }
+
     fn body(self) -> Self::Body {
         &mut self.0.origin
     }
@@ -6,8 +6,14 @@
 // copied, modified, or distributed except according to those terms.
 //! Utilities for saving request entries to the filesystem.
 
+#[cfg(feature = "digest")]
+use digest::Digest;
+
 use mime::Mime;
 
+#[cfg(feature = "serde")]
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
 pub use server::buf_redux::BufReader;
 
 pub use tempdir::TempDir;
@@ -69,6 +75,70 @@ enum TextPolicy {
     Ignore
 }
 
+/// A transform applied to each field's data before it is saved; see `SaveBuilder::transform()`.
+type Transform = Box<for<'r> FnMut(&FieldHeaders, &'r mut Read) -> Box<Read + 'r>>;
+
+/// Creates a new hasher for each saved field; see `SaveBuilder::with_hashing()`.
+type NewHasher = fn() -> Box<FieldHasher>;
+
+/// Object-safe wrapper for `Digest` so `SaveBuilder` doesn't need a type parameter for it.
+trait FieldHasher {
+    fn input(&mut self, data: &[u8]);
+
+    fn finish(self: Box<Self>) -> Vec<u8>;
+}
+
+#[cfg(feature = "digest")]
+impl<D: Digest> FieldHasher for D {
+    fn input(&mut self, data: &[u8]) {
+        Digest::input(self, data)
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        (*self).result().to_vec()
+    }
+}
+
+#[cfg(feature = "digest")]
+fn new_hasher<D: Digest + 'static>() -> Box<FieldHasher> {
+    Box::new(D::default())
+}
+
+/// Feeds all bytes consumed from the inner reader to `hasher`, if set.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Option<Box<FieldHasher>>,
+}
+
+impl<R: BufRead> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        if let Some(ref mut hasher) = self.hasher {
+            hasher.input(&buf[..read]);
+        }
+
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for HashingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(ref mut hasher) = self.hasher {
+            // the bytes being consumed are still at the front of the buffer
+            if let Ok(buf) = self.inner.fill_buf() {
+                hasher.input(&buf[..cmp::min(amt, buf.len())]);
+            }
+        }
+
+        self.inner.consume(amt)
+    }
+}
+
 /// A builder for saving a file or files to the local filesystem.
 ///
 /// ### `OpenOptions`
@@ -130,6 +200,15 @@ enum TextPolicy {
 /// permissions, but you should still not use user input directly as filesystem paths.
 /// If it is truly necessary, you should sanitize user input such that it cannot cause a path to be
 /// misinterpreted by the OS. Such functionality is outside the scope of this crate.
+///
+/// ### Transforming Field Data
+/// When saving whole requests, you can use `transform()` to wrap each field's data in another
+/// `Read` implementation (e.g. to strip metadata, transcode or encrypt files) before it is saved.
+/// The output of the transform is what is written to memory or disk.
+///
+/// ### Hashing Field Data
+/// With the `digest` feature enabled, `with_hashing()` computes a digest of each field's data
+/// as it is saved, which is stored in `SavedField::hash`.
 #[must_use = "nothing saved to the filesystem yet"]
 pub struct SaveBuilder<S> {
     savable: S,
@@ -138,6 +217,10 @@ pub struct SaveBuilder<S> {
     count_limit: u32,
     memory_threshold: u64,
     text_policy: TextPolicy,
+    transform: Option<Transform>,
+    hasher: Option<NewHasher>,
+    collect_errors: bool,
+    atomic: bool,
 }
 
 /// Common methods for whole requests as well as individual fields.
@@ -159,6 +242,10 @@ impl<S> SaveBuilder<S> {
             // https://commons.apache.org/proper/commons-fileupload/apidocs/org/apache/commons/fileupload/disk/DiskFileItemFactory.html
             memory_threshold: 10 * 1024,
             text_policy: TextPolicy::Try,
+            transform: None,
+            hasher: None,
+            collect_errors: false,
+            atomic: false,
         }
     }
 
@@ -180,6 +267,19 @@ impl<S> SaveBuilder<S> {
         self
     }
 
+    /// If `true`, write each file to a temporary name in the same directory first, and only
+    /// rename it to its final path once the field has been saved completely, so a partially
+    /// written file is never seen there.
+    ///
+    /// If the field can't be saved completely, the temporary file is removed and the data
+    /// returned in `SaveResult::Partial` is empty. The `OpenOptions` apply to the temporary
+    /// file; note that renaming replaces any file already at the final path on most
+    /// platforms. Off by default.
+    pub fn atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+
     /// Set the threshold at which to switch from copying a field into memory to copying
     /// it to disk.
     ///
@@ -226,6 +326,41 @@ impl<M> SaveBuilder<M> where M: ReadEntry {
         self
     }
 
+    /// Wrap the data of each field in the `Read` returned by `transform` before saving it.
+    ///
+    /// The transform is called once per field with the field's headers and data; the output
+    /// of the returned reader is what will be saved, subject to the same limits and text policy
+    /// as the untransformed data. Any errors reading from it will be returned in the `SaveResult`.
+    pub fn transform<F>(mut self, transform: F) -> Self
+    where F: for<'r> FnMut(&FieldHeaders, &'r mut Read) -> Box<Read + 'r> + 'static {
+        self.transform = Some(Box::new(transform));
+        self
+    }
+
+    /// Compute a digest of the data of each saved field with `D`, storing it in
+    /// `SavedField::hash`.
+    ///
+    /// The digest is computed as the data is written and covers exactly the bytes which were
+    /// saved (after `transform()`, if set), whether to memory or to disk.
+    #[cfg(feature = "digest")]
+    pub fn with_hashing<D: Digest + 'static>(mut self) -> Self {
+        self.hasher = Some(new_hasher::<D>);
+        self
+    }
+
+    /// If `true`, keep going when a field fails to save and record the error in
+    /// `Entries::errors` with the field's name, instead of returning `SaveResult::Partial`.
+    ///
+    /// This covers errors saving an individual field (e.g. `PartialReason::SizeLimit`); any
+    /// partially written file is removed. Errors reading the request itself, such as malformed
+    /// field headers, still stop the operation. Failed fields count towards `count_limit()`.
+    ///
+    /// Off by default, in which case the first error stops the operation.
+    pub fn collect_errors(mut self, collect: bool) -> Self {
+        self.collect_errors = collect;
+        self
+    }
+
     /// Save all fields in the request using a new temporary directory prefixed with
     /// `multipart-rs` in the OS temporary directory.
     ///
@@ -289,25 +424,37 @@ impl<M> SaveBuilder<M> where M: ReadEntry {
     pub fn with_entries(mut self, mut entries: Entries) -> EntriesSaveResult<M> {
         let SaveBuilder {
             savable, open_opts, count_limit, size_limit,
-            memory_threshold, text_policy
+            memory_threshold, text_policy, mut transform, hasher, collect_errors, atomic
         } = self;
 
         let mut res = ReadEntry::read_entry(savable);
 
         let _ = entries.recount_fields();
 
-        let save_field = |field: &mut MultipartField<M>, entries: &Entries| {
+        let mut save_field = |field: &mut MultipartField<M>, entries: &Entries| {
             let text_policy = if field.is_text() { text_policy } else { Ignore };
+            let path = entries.save_dir.as_path().join(rand_filename());
+
+            if let Some(ref mut transform) = transform {
+                let saver = SaveBuilder {
+                    savable: BufReader::new(transform(&field.headers, &mut field.data)),
+                    open_opts: open_opts.clone(), count_limit, size_limit, memory_threshold,
+                    text_policy, transform: None, hasher: None, collect_errors: false, atomic,
+                };
 
-            let mut saver = SaveBuilder {
+                return saver.save_hashed(path, hasher);
+            }
+
+            let saver = SaveBuilder {
                 savable: &mut field.data, open_opts: open_opts.clone(),
-                count_limit, size_limit, memory_threshold, text_policy
+                count_limit, size_limit, memory_threshold, text_policy,
+                transform: None, hasher: None, collect_errors: false, atomic,
             };
 
-            saver.with_dir(entries.save_dir.as_path())
+            saver.save_hashed(path, hasher)
         };
 
-        while entries.fields_count < count_limit {
+        while entries.fields_count.saturating_add(entries.errors_count()) < count_limit {
             let mut field: MultipartField<M> = match res {
                 ReadEntryResult::Entry(field) => field,
                 ReadEntryResult::End(_) => return Full(entries), // normal exit point
@@ -320,9 +467,11 @@ impl<M> SaveBuilder<M> where M: ReadEntry {
                 )
             };
 
-            let (dest, reason) = match save_field(&mut field, &entries) {
+            let (res_saved, hash) = save_field(&mut field, &entries);
+
+            let (dest, reason) = match res_saved {
                 Full(saved) => {
-                    entries.push_field(field.headers, saved);
+                    entries.push_field(field.headers, saved, hash);
                     res = ReadEntry::read_entry(field.data.into_inner());
                     continue;
                 },
@@ -330,6 +479,16 @@ impl<M> SaveBuilder<M> where M: ReadEntry {
                 Error(error) => (None, PartialReason::IoError(error)),
             };
 
+            if collect_errors {
+                if let Some(SavedData::File(ref path, _)) = dest {
+                    let _ = fs::remove_file(path);
+                }
+
+                entries.errors.push((field.headers.name, reason));
+                res = ReadEntry::read_entry(field.data.into_inner());
+                continue;
+            }
+
             return Partial(
                 PartialEntries {
                     entries,
@@ -393,6 +552,40 @@ impl<'m, M: 'm> SaveBuilder<&'m mut MultipartData<M>> where MultipartData<M>: Bu
     /// If `size_limit` is set and less than or equal to `memory_threshold`,
     /// then the disk will never be touched.
     pub fn with_path<P: Into<PathBuf>>(&mut self, path: P) -> FieldSaveResult {
+        self.save_to_path(path)
+    }
+
+    /// Write out the field data to `dest`, truncating if a limit was set.
+    ///
+    /// Returns the number of bytes copied, and whether or not the limit was reached
+    /// (tested by `MultipartFile::fill_buf().is_empty()` so no bytes are consumed).
+    ///
+    /// Retries on interrupts.
+    pub fn write_to<W: Write>(&mut self, dest: W) -> SaveResult<u64, u64> {
+        self.copy_to(dest)
+    }
+}
+
+/// Implementation of the field save API, shared with transformed fields.
+impl<S> SaveBuilder<S> where S: BufRead {
+    /// Save to `path`, also returning the digest of the saved data if `hasher` is set.
+    fn save_hashed<P: Into<PathBuf>>(self, path: P, hasher: Option<NewHasher>)
+                                     -> (FieldSaveResult, Option<Vec<u8>>) {
+        let SaveBuilder {
+            savable, open_opts, size_limit, count_limit, memory_threshold, text_policy, atomic, ..
+        } = self;
+
+        let mut saver = SaveBuilder {
+            savable: HashingReader { inner: savable, hasher: hasher.map(|new| new()) },
+            open_opts, size_limit, count_limit, memory_threshold, text_policy,
+            transform: None, hasher: None, collect_errors: false, atomic,
+        };
+
+        let res = saver.save_to_path(path);
+        (res, saver.savable.hasher.map(FieldHasher::finish))
+    }
+
+    fn save_to_path<P: Into<PathBuf>>(&mut self, path: P) -> FieldSaveResult {
         let bytes = if self.text_policy != Ignore {
             let (text, reason) = try_partial!(self.save_text());
             match reason {
@@ -413,27 +606,50 @@ impl<'m, M: 'm> SaveBuilder<&'m mut MultipartData<M>> where MultipartData<M>: Bu
 
         let path = path.into();
 
+        if self.atomic {
+            self.save_to_file_atomic(&bytes, path)
+        } else {
+            self.save_to_file(&bytes, path)
+        }
+    }
+
+    /// Write `bytes` and then the rest of the data to a new file at `path`.
+    fn save_to_file(&mut self, bytes: &[u8], path: PathBuf) -> FieldSaveResult {
         let mut file = match create_dir_all(&path).and_then(|_| self.open_opts.open(&path)) {
             Ok(file) => file,
             Err(e) => return Error(e),
         };
 
         let data = try_full!(
-            try_write_all(&bytes, &mut file)
+            try_write_all(bytes, &mut file)
                 .map(move |size| SavedData::File(path, size as u64))
         );
 
-        self.write_to(file).map(move |written| data.add_size(written))
+        self.copy_to(file).map(move |written| data.add_size(written))
     }
 
+    /// Like `save_to_file()`, but write to a temporary file and rename it to `path` on success.
+    fn save_to_file_atomic(&mut self, bytes: &[u8], path: PathBuf) -> FieldSaveResult {
+        let temp_path = path.with_file_name(format!(".{}.tmp", rand_filename()));
 
-    /// Write out the field data to `dest`, truncating if a limit was set.
-    ///
-    /// Returns the number of bytes copied, and whether or not the limit was reached
-    /// (tested by `MultipartFile::fill_buf().is_empty()` so no bytes are consumed).
-    ///
-    /// Retries on interrupts.
-    pub fn write_to<W: Write>(&mut self, mut dest: W) -> SaveResult<u64, u64> {
+        let reason = match self.save_to_file(bytes, temp_path.clone()) {
+            Full(data) => match fs::rename(&temp_path, &path) {
+                Ok(()) => return Full(SavedData::File(path, data.size())),
+                Err(e) => Err(e),
+            },
+            Partial(_, reason) => Ok(reason),
+            Error(e) => Err(e),
+        };
+
+        let _ = fs::remove_file(&temp_path);
+
+        match reason {
+            Ok(reason) => Partial(SavedData::Bytes(Vec::new()), reason),
+            Err(e) => Error(e),
+        }
+    }
+
+    fn copy_to<W: Write>(&mut self, mut dest: W) -> SaveResult<u64, u64> {
         if self.size_limit < u64::MAX {
             try_copy_limited(&mut self.savable, |buf| try_write_all(buf, &mut dest), self.size_limit)
         } else {
@@ -492,6 +708,10 @@ pub struct SavedField {
     pub headers: FieldHeaders,
     /// The data of the field which may reside in memory or on disk.
     pub data: SavedData,
+    /// The digest of `data` if `SaveBuilder::with_hashing()` was used.
+    ///
+    /// Always `None` for a field added with `PartialEntries::keep_partial()`.
+    pub hash: Option<Vec<u8>>,
 }
 
 /// A saved field's data container (in memory or on disk)
@@ -618,6 +838,9 @@ pub struct Entries {
     pub fields: HashMap<ArcStr, Vec<SavedField>>,
     /// The directory that the entries in `fields` were saved into.
     pub save_dir: SaveDir,
+    /// The names of the fields which failed to save and why, in order, with
+    /// `SaveBuilder::collect_errors()`.
+    pub errors: Vec<(ArcStr, PartialReason)>,
     fields_count: u32,
 }
 
@@ -627,6 +850,7 @@ impl Entries {
         Entries {
             fields: HashMap::new(),
             save_dir,
+            errors: Vec::new(),
             fields_count: 0,
         }
     }
@@ -647,6 +871,11 @@ impl Entries {
         self.fields_count
     }
 
+    /// The number of fields in `errors`, saturated to `u32::MAX`.
+    fn errors_count(&self) -> u32 {
+        cmp::min(u32::MAX as usize, self.errors.len()) as u32
+    }
+
     /// Sum the number of fields in this `Entries` and then return the updated value.
     pub fn recount_fields(&mut self) -> u32 {
         let fields_count = self.fields.values().map(Vec::len).sum();
@@ -655,15 +884,15 @@ impl Entries {
         self.fields_count
     }
 
-    fn push_field(&mut self, mut headers: FieldHeaders, data: SavedData) {
+    fn push_field(&mut self, mut headers: FieldHeaders, data: SavedData, hash: Option<Vec<u8>>) {
         use std::collections::hash_map::Entry::*;
 
         match self.fields.entry(headers.name.clone()) {
-            Vacant(vacant) => { vacant.insert(vec![SavedField { headers, data }]); },
+            Vacant(vacant) => { vacant.insert(vec![SavedField { headers, data, hash }]); },
             Occupied(occupied) => {
                 // dedup the field name by reusing the key's `Arc`
                 headers.name = occupied.key().clone();
-                occupied.into_mut().push({ SavedField { headers, data }});
+                occupied.into_mut().push({ SavedField { headers, data, hash }});
             },
         }
 
@@ -692,6 +921,90 @@ impl Entries {
 
         Ok(())
     }
+
+    /// Get a summary of the saved fields which implements `serde::Serialize`, e.g. to respond
+    /// to an upload with what was received.
+    ///
+    /// Fields are sorted by name, and fields with the same name are kept in the order they were
+    /// saved. The data itself is not included.
+    ///
+    /// Requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn summary(&self) -> EntriesSummary {
+        let mut names: Vec<&ArcStr> = self.fields.keys().collect();
+        names.sort();
+
+        let fields = names.into_iter()
+            .flat_map(|name| self.fields[name].iter())
+            .map(|field| FieldSummary {
+                name: &field.headers.name,
+                filename: field.headers.filename.as_ref().map(|f| &**f),
+                content_type: field.headers.content_type.as_ref().map(|ct| ct.as_ref()),
+                size: field.data.size(),
+                path: match field.data {
+                    SavedData::File(ref path, _) => Some(path),
+                    _ => None,
+                },
+            })
+            .collect();
+
+        EntriesSummary { fields }
+    }
+}
+
+/// A summary of the fields in `Entries`, returned by `Entries::summary()`.
+///
+/// Serializes as `{"fields": [...]}`, with each field as described in `FieldSummary`.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug)]
+pub struct EntriesSummary<'a> {
+    /// The summaries of the saved fields.
+    pub fields: Vec<FieldSummary<'a>>,
+}
+
+/// A summary of a single saved field.
+///
+/// Serializes as `{"name": ..., "filename": ..., "content_type": ..., "size": ..., "path": ...}`,
+/// where `filename`, `content_type` and `path` may be `null`.
+///
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug)]
+pub struct FieldSummary<'a> {
+    /// The field's name.
+    pub name: &'a str,
+    /// The field's filename, if supplied.
+    pub filename: Option<&'a str>,
+    /// The field's `Content-Type`, if supplied.
+    pub content_type: Option<&'a str>,
+    /// The size of the saved data.
+    pub size: u64,
+    /// The path of the file the data was saved to, or `None` if it was kept in memory.
+    pub path: Option<&'a PathBuf>,
+}
+
+#[cfg(feature = "serde")]
+impl<'a> Serialize for EntriesSummary<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut summary = serializer.serialize_struct("EntriesSummary", 1)?;
+        summary.serialize_field("fields", &self.fields)?;
+        summary.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a> Serialize for FieldSummary<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut summary = serializer.serialize_struct("FieldSummary", 5)?;
+        summary.serialize_field("name", self.name)?;
+        summary.serialize_field("filename", &self.filename)?;
+        summary.serialize_field("content_type", &self.content_type)?;
+        summary.serialize_field("size", &self.size)?;
+        summary.serialize_field("path", &self.path)?;
+        summary.end()
+    }
 }
 
 /// The save directory for `Entries`. May be temporary (delete-on-drop) or permanent.
@@ -865,7 +1178,7 @@ impl<M: ReadEntry> PartialEntries<M> {
     pub fn keep_partial(mut self) -> Entries {
         if let Some(partial) = self.partial {
             if let Some(saved) = partial.dest {
-                self.entries.push_field(partial.source.headers, saved);
+                self.entries.push_field(partial.source.headers, saved, None);
             }
         }
 
@@ -0,0 +1,183 @@
+// Copyright 2016 `multipart` Crate Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//! Fields which share ownership of the request, returned by `Multipart::into_shared()`.
+//!
+//! The fields of a request are still read in order from the same body, but unlike
+//! `MultipartField` these don't borrow the `Multipart`, so they can be stored in a collection,
+//! returned from functions or sent to other threads.
+//!
+//! ### Note: Sequential Reads
+//! Reading the next field skips the rest of the data of the current one, after which
+//! reading the data of the previous field returns an error of kind `InvalidInput`.
+//! For example, headers of all fields may be collected into a `Vec` and inspected in any
+//! order, but only the data of the last field read is still available.
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::field::PrivReadEntry;
+use super::transfer::TransferDecoder;
+use super::{FieldHeaders, Multipart};
+
+struct Shared<R> {
+    multipart: Multipart<R>,
+    /// The index of the field whose data the reader is positioned at.
+    field_idx: usize,
+}
+
+/// A multipart request whose fields share ownership of it.
+///
+/// See the module docs for more info.
+pub struct SharedMultipart<R> {
+    shared: Arc<Mutex<Shared<R>>>,
+}
+
+impl<R: Read> SharedMultipart<R> {
+    /// Implementation detail; use `Multipart::into_shared()`.
+    #[doc(hidden)]
+    pub fn new(multipart: Multipart<R>) -> Self {
+        SharedMultipart {
+            shared: Arc::new(Mutex::new(Shared {
+                multipart: multipart,
+                field_idx: 0,
+            })),
+        }
+    }
+
+    /// Read the next field, or return `None` if the end of the request was reached.
+    ///
+    /// Any data left unread in the previous field is discarded, and it can no longer be read.
+    pub fn read_entry(&mut self) -> io::Result<Option<SharedField<R>>> {
+        let mut shared = lock(&self.shared)?;
+
+        shared.field_idx += 1;
+        let field_idx = shared.field_idx;
+
+        let headers = match shared.multipart.read_entry()? {
+            Some(field) => field.headers,
+            None => return Ok(None),
+        };
+
+        let decoder = if shared.multipart.transfer_decoding() {
+            headers.transfer_encoding.as_ref()
+                .and_then(|encoding| TransferDecoder::for_encoding(encoding))
+        } else {
+            None
+        };
+
+        Ok(Some(SharedField {
+            headers: headers,
+            data: SharedData {
+                shared: self.shared.clone(),
+                field_idx: field_idx,
+                decoder: decoder,
+            },
+        }))
+    }
+}
+
+/// A field of a `SharedMultipart`, which can be kept or moved around independently of it.
+pub struct SharedField<R> {
+    /// The headers for this field, including the name, filename, and content-type, if provided.
+    ///
+    /// ### Warning: Values are Client-Provided
+    /// See `MultipartField::headers`.
+    pub headers: FieldHeaders,
+
+    /// The field's data, which can only be read until the next field is read.
+    pub data: SharedData<R>,
+}
+
+/// The data of a `SharedField`.
+///
+/// Reading returns an error of kind `InvalidInput` once a later field has been read from
+/// the request.
+pub struct SharedData<R> {
+    shared: Arc<Mutex<Shared<R>>>,
+    field_idx: usize,
+    /// Decodes the field's `Content-Transfer-Encoding`, with `Multipart::with_transfer_decoding()`.
+    decoder: Option<TransferDecoder>,
+}
+
+impl<R: Read> SharedData<R> {
+    /// Returns `true` if this field's data can still be read, i.e. no later field has been read.
+    pub fn is_current(&self) -> bool {
+        lock(&self.shared).map(|shared| shared.field_idx == self.field_idx).unwrap_or(false)
+    }
+}
+
+impl<R: Read> Read for SharedData<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut shared = lock(&self.shared)?;
+
+        if shared.field_idx != self.field_idx {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "a later field was already read; fields must be read in order"));
+        }
+
+        let source = shared.multipart.source_mut();
+
+        match self.decoder {
+            Some(ref mut decoder) => decoder.read(source, buf),
+            None => source.read(buf),
+        }
+    }
+}
+
+fn lock<R>(shared: &Mutex<Shared<R>>) -> io::Result<MutexGuard<Shared<R>>> {
+    shared.lock().map_err(|_| io::Error::new(io::ErrorKind::Other,
+                                              "a thread panicked while reading the request"))
+}
+
+#[cfg(test)]
+mod test {
+    use server::Multipart;
+
+    use std::io::{ErrorKind, Read};
+    use std::thread;
+
+    #[test]
+    fn test_shared_fields() {
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"first\"\r\n\r\n\
+                            first data\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"second\"\r\n\r\n\
+                            second data\r\n\
+                            --boundary--";
+
+        let mut multipart = Multipart::with_body(body, "boundary").into_shared();
+
+        let mut fields = Vec::new();
+        fields.push(multipart.read_entry().unwrap().unwrap());
+        fields.push(multipart.read_entry().unwrap().unwrap());
+
+        assert!(!fields[0].data.is_current());
+        assert!(fields[1].data.is_current());
+
+        let mut second = fields.pop().unwrap();
+        assert_eq!(&*second.headers.name, "second");
+
+        // the field can be read on another thread
+        let data = thread::spawn(move || {
+            let mut data = String::new();
+            second.data.read_to_string(&mut data).unwrap();
+            data
+        }).join().unwrap();
+
+        assert_eq!(data, "second data");
+        assert!(multipart.read_entry().unwrap().is_none());
+
+        // the first field's data was skipped to read the second
+        let mut first = fields.pop().unwrap();
+        assert_eq!(&*first.headers.name, "first");
+
+        match first.data.read(&mut [0; 16]) {
+            Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidInput),
+            Ok(read) => panic!("expected error, read {} bytes", read),
+        }
+    }
+}
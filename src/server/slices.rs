@@ -0,0 +1,124 @@
+// Copyright 2016 `multipart` Crate Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//! Reading request bodies delivered as a sequence of chunks.
+
+use std::cmp;
+use std::io::{self, Read};
+
+/// A `Read` adapter over an iterator of byte slices, for servers which deliver the request
+/// body in chunks, e.g. as a `Vec<Bytes>` or an iterator of `&[u8]`.
+///
+/// The chunks are read one after another without being copied into one buffer first, so
+/// the body can be passed to `Multipart::with_body()` as it is. A field or boundary may be
+/// split across any number of chunks.
+pub struct SliceIterReader<I: Iterator> {
+    iter: I,
+    /// The chunk being read, and the position in it.
+    current: Option<I::Item>,
+    pos: usize,
+}
+
+impl<I: Iterator> SliceIterReader<I> where I::Item: AsRef<[u8]> {
+    /// Read the chunks yielded by `slices`, in order.
+    pub fn new<T>(slices: T) -> Self where T: IntoIterator<IntoIter = I> {
+        SliceIterReader {
+            iter: slices.into_iter(),
+            current: None,
+            pos: 0,
+        }
+    }
+
+    /// Unwrap the iterator over the remaining chunks; the rest of the chunk being read
+    /// is discarded.
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+}
+
+impl<I: Iterator> Read for SliceIterReader<I> where I::Item: AsRef<[u8]> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(ref current) = self.current {
+                let rest = &current.as_ref()[self.pos..];
+
+                // skip empty chunks
+                if !rest.is_empty() || buf.is_empty() {
+                    let len = cmp::min(rest.len(), buf.len());
+                    buf[..len].copy_from_slice(&rest[..len]);
+                    self.pos += len;
+                    return Ok(len);
+                }
+            }
+
+            match self.iter.next() {
+                Some(slice) => {
+                    self.current = Some(slice);
+                    self.pos = 0;
+                },
+                None => {
+                    self.current = None;
+                    return Ok(0);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SliceIterReader;
+
+    use server::Multipart;
+
+    use std::io::Read;
+
+    #[test]
+    fn test_slice_iter_reader() {
+        let chunks = vec![b"first ".to_vec(), Vec::new(), b"second".to_vec(), Vec::new()];
+        let mut data = String::new();
+        SliceIterReader::new(chunks).read_to_string(&mut data).unwrap();
+        assert_eq!(data, "first second");
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            some text\r\n\
+                            --boundary\r\n\
+                            Content-Disposition: form-data; name=\"file\"; filename=\"file.txt\"\r\n\r\n\
+                            file contents\r\n\
+                            --boundary--";
+
+        // split in varying small sizes so boundaries and headers straddle chunks
+        let mut slices = Vec::new();
+        let mut rest = body;
+
+        for len in [1, 2, 3, 5, 7].iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+
+            let (slice, next) = rest.split_at(::std::cmp::min(*len, rest.len()));
+            slices.push(slice);
+            rest = next;
+        }
+
+        let mut multipart = Multipart::with_body(SliceIterReader::new(slices), "boundary");
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(&*field.headers.name, "text");
+            assert_eq!(field.read_text().unwrap(), "some text");
+        }
+
+        {
+            let mut field = multipart.read_entry().unwrap().unwrap();
+            assert_eq!(field.headers.filename.as_ref().unwrap(), "file.txt");
+            assert_eq!(field.read_text().unwrap(), "file contents");
+        }
+
+        assert!(multipart.read_entry().unwrap().is_none());
+    }
+}
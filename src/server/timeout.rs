@@ -0,0 +1,196 @@
+// Copyright 2016 `multipart` Crate Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//! Time-based limits for reading request bodies.
+//!
+//! Timing is measured with a `Clock` so it can be mocked in tests; see `mock::MockClock`.
+
+use std::error::Error;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::{Duration, Instant};
+use std::fmt;
+
+/// A source of the current time.
+pub trait Clock {
+    /// Get the current time.
+    fn now(&self) -> Instant;
+}
+
+/// Reading the request took longer than the timeout set with `Multipart::with_total_timeout()`.
+///
+/// Returned wrapped in an `io::Error` of kind `TimedOut`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DeadlineExceeded {
+    /// The timeout for the whole request.
+    pub timeout: Duration,
+}
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (timeout: {:?})", self.description(), self.timeout)
+    }
+}
+
+impl Error for DeadlineExceeded {
+    fn description(&self) -> &str {
+        "reading the request took longer than the timeout"
+    }
+}
+
+/// The real clock, using `Instant::now()`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A `Read` adapter which returns an error of kind `TimedOut` if a single read of the inner
+/// reader takes longer than the set timeout.
+///
+/// The inner read cannot be interrupted, so this can only report a timeout once it returns.
+/// To stop waiting on a stalled connection, the inner reader must support timeouts itself
+/// (e.g. `TcpStream::set_read_timeout()`).
+#[derive(Debug)]
+pub struct TimeoutReader<R, C = SystemClock> {
+    inner: R,
+    clock: C,
+    timeout: Option<Duration>,
+}
+
+impl<R> TimeoutReader<R> {
+    /// Wrap `inner` with the given timeout, or no timeout if `None`.
+    pub fn new(inner: R, timeout: Option<Duration>) -> Self {
+        Self::with_clock(inner, timeout, SystemClock)
+    }
+}
+
+impl<R, C: Clock> TimeoutReader<R, C> {
+    /// Wrap `inner` with the given timeout, measured with `clock`.
+    pub fn with_clock(inner: R, timeout: Option<Duration>, clock: C) -> Self {
+        TimeoutReader {
+            inner: inner,
+            clock: clock,
+            timeout: timeout,
+        }
+    }
+
+    /// Set the timeout, or clear it if `None`.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Measure the timeout with `clock` from now on.
+    pub fn set_clock(&mut self, clock: C) {
+        self.clock = clock;
+    }
+
+    /// Get a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwrap the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, C: Clock> Read for TimeoutReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return self.inner.read(buf),
+        };
+
+        let started = self.clock.now();
+        let read = self.inner.read(buf)?;
+
+        if self.clock.now().duration_since(started) > timeout {
+            return Err(io::Error::new(io::ErrorKind::TimedOut,
+                                      "timed out waiting for the request body"));
+        }
+
+        Ok(read)
+    }
+}
+
+impl<R: Seek, C> Seek for TimeoutReader<R, C> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TimeoutReader;
+
+    use mock::MockClock;
+
+    use std::io::{self, Read};
+    use std::time::Duration;
+
+    /// Advances `clock` by `delay` on every read.
+    struct SlowReader<'a> {
+        data: &'a [u8],
+        clock: MockClock,
+        delay: Duration,
+    }
+
+    impl<'a> Read for SlowReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.clock.advance(self.delay);
+            self.data.read(buf)
+        }
+    }
+
+    fn read_slowly(delay_ms: u64, timeout_ms: u64) -> io::Result<Vec<u8>> {
+        let clock = MockClock::new();
+        let slow = SlowReader { data: b"data", clock: clock.clone(), delay: Duration::from_millis(delay_ms) };
+
+        let mut reader = TimeoutReader::with_clock(slow, Some(Duration::from_millis(timeout_ms)), clock);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).map(|_| data)
+    }
+
+    #[test]
+    fn test_timeout_reader() {
+        assert_eq!(read_slowly(10, 100).unwrap(), b"data");
+        assert_eq!(read_slowly(100, 10).unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_rate_limited_upload() {
+        use mock::RateLimitedReader;
+        use server::Multipart;
+
+        let body: &[u8] = b"--boundary\r\n\
+                            Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                            some text\r\n\
+                            --boundary--";
+
+        fn read_text(body: &[u8], timeout_ms: u64) -> io::Result<String> {
+            let clock = MockClock::new();
+            let slow = RateLimitedReader::new(body, 8, Duration::from_millis(100), clock.clone());
+
+            let mut multipart = Multipart::with_body(slow, "boundary")
+                .with_read_timeout_clock(Duration::from_millis(timeout_ms), clock);
+            let mut field = multipart.read_entry()?.expect("expected a field");
+            field.read_text()
+        }
+
+        assert_eq!(read_text(body, 150).unwrap(), "some text");
+        assert_eq!(read_text(body, 50).unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -23,6 +23,14 @@ impl<'r> HttpRequest for &'r mut TinyHttpRequest {
         Some(&content_type[start .. end])
     }
 
+    fn has_content_type(&self) -> bool {
+        self.headers().iter().any(|header| header.field.equiv("Content-Type"))
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.body_length().map(|len| len as u64)
+    }
+
     fn body(self) -> Self::Body {
         self.as_reader()
     }
@@ -0,0 +1,363 @@
+// Copyright 2016 `multipart` Crate Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Decoding of the `Content-Transfer-Encoding` of fields, for
+//! `Multipart::with_transfer_decoding()`.
+
+use std::ascii::AsciiExt;
+use std::cmp;
+use std::io::{self, BufRead};
+
+/// The transfer encodings which leave the data as-is.
+pub fn is_identity_encoding(encoding: &str) -> bool {
+    ["7bit", "8bit", "binary"].iter().any(|identity| encoding.eq_ignore_ascii_case(identity))
+}
+
+/// Returns `true` if the data of a field with this encoding can be read, either because it
+/// needs no decoding or because it can be decoded.
+pub fn is_supported_encoding(encoding: &str) -> bool {
+    is_identity_encoding(encoding) || TransferDecoder::for_encoding(encoding).is_some()
+}
+
+#[derive(Debug)]
+enum Decoder {
+    Base64(Base64),
+    QuotedPrintable(QuotedPrintable),
+}
+
+/// Decodes the data of a field read from a `BufRead` positioned at its start.
+#[derive(Debug)]
+pub struct TransferDecoder {
+    decoder: Decoder,
+    /// Decoded data not yet returned, starting at `pos`.
+    decoded: Vec<u8>,
+    pos: usize,
+    /// Whether any of the source has been read.
+    started: bool,
+    /// Whether the end of the source was reached.
+    eof: bool,
+}
+
+impl TransferDecoder {
+    /// Get a decoder for `encoding`, or `None` if it isn't `base64` or `quoted-printable`.
+    pub fn for_encoding(encoding: &str) -> Option<Self> {
+        let decoder = if encoding.eq_ignore_ascii_case("base64") {
+            Decoder::Base64(Base64::default())
+        } else if encoding.eq_ignore_ascii_case("quoted-printable") {
+            Decoder::QuotedPrintable(QuotedPrintable::default())
+        } else {
+            return None;
+        };
+
+        Some(TransferDecoder {
+            decoder: decoder,
+            decoded: Vec::new(),
+            pos: 0,
+            started: false,
+            eof: false,
+        })
+    }
+
+    /// Returns `true` if any of the source has been read.
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    /// Decode more of `source` if all the decoded data was consumed, and return the rest of it.
+    pub fn fill_buf<R: BufRead>(&mut self, source: &mut R) -> io::Result<&[u8]> {
+        while self.pos == self.decoded.len() && !self.eof {
+            self.decoded.clear();
+            self.pos = 0;
+            self.started = true;
+
+            let consume_amt = {
+                let raw = source.fill_buf()?;
+                self.eof = raw.is_empty();
+
+                match (&mut self.decoder, self.eof) {
+                    (&mut Decoder::Base64(ref mut base64), false) =>
+                        base64.decode(raw, &mut self.decoded)?,
+                    (&mut Decoder::Base64(ref mut base64), true) => base64.finish(&mut self.decoded)?,
+                    (&mut Decoder::QuotedPrintable(ref mut qp), false) =>
+                        qp.decode(raw, &mut self.decoded)?,
+                    (&mut Decoder::QuotedPrintable(ref mut qp), true) => qp.finish(&mut self.decoded)?,
+                }
+
+                raw.len()
+            };
+
+            source.consume(consume_amt);
+        }
+
+        Ok(&self.decoded[self.pos..])
+    }
+
+    /// Mark `amt` bytes returned by `fill_buf()` as read.
+    pub fn consume(&mut self, amt: usize) {
+        self.pos = cmp::min(self.pos + amt, self.decoded.len());
+    }
+
+    /// Read decoded data from `source` into `out`.
+    pub fn read<R: BufRead>(&mut self, source: &mut R, out: &mut [u8]) -> io::Result<usize> {
+        let read = {
+            let decoded = self.fill_buf(source)?;
+            let read = cmp::min(decoded.len(), out.len());
+            out[..read].copy_from_slice(&decoded[..read]);
+            read
+        };
+
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Decodes `base64` ([IETF RFC 2045, Section 6.8][rfc2045-6.8]); line breaks and other whitespace
+/// are ignored, and the final padding may be omitted.
+///
+/// [rfc2045-6.8]: https://tools.ietf.org/html/rfc2045#section-6.8
+#[derive(Debug, Default)]
+struct Base64 {
+    /// The values of the characters of the current quantum, with `0` for padding.
+    quantum: [u8; 4],
+    /// The number of characters of the current quantum read, including padding.
+    len: usize,
+    /// The number of padding characters in the current quantum.
+    padding: usize,
+    /// Whether a padded quantum ended the data.
+    padded: bool,
+}
+
+impl Base64 {
+    fn decode(&mut self, input: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        for &b in input {
+            match b {
+                b' ' | b'\t' | b'\r' | b'\n' => continue,
+                _ => (),
+            }
+
+            if self.padded {
+                return Err(invalid_data("base64 data after padding"));
+            }
+
+            if b == b'=' {
+                if self.len < 2 {
+                    return Err(invalid_data("unexpected base64 padding"));
+                }
+
+                self.quantum[self.len] = 0;
+                self.padding += 1;
+            } else {
+                if self.padding > 0 {
+                    return Err(invalid_data("base64 data after padding"));
+                }
+
+                self.quantum[self.len] = base64_value(b)
+                    .ok_or_else(|| invalid_data("invalid base64 character"))?;
+            }
+
+            self.len += 1;
+
+            if self.len == 4 {
+                self.padded = self.padding > 0;
+                self.flush(out);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        match self.len {
+            0 => Ok(()),
+            1 => Err(invalid_data("unexpected end of base64 data")),
+            _ => {
+                self.flush(out);
+                Ok(())
+            },
+        }
+    }
+
+    /// Write out the bytes of the current quantum, which has at least two data characters.
+    fn flush(&mut self, out: &mut Vec<u8>) {
+        let q = self.quantum;
+        let bytes = [q[0] << 2 | q[1] >> 4, (q[1] & 0xF) << 4 | q[2] >> 2, (q[2] & 0x3) << 6 | q[3]];
+
+        // 2 characters encode 1 byte, 3 encode 2 and 4 encode 3
+        let data_len = self.len - self.padding;
+        out.extend_from_slice(&bytes[..data_len - 1]);
+
+        self.quantum = [0; 4];
+        self.len = 0;
+        self.padding = 0;
+    }
+}
+
+fn base64_value(b: u8) -> Option<u8> {
+    if b >= b'A' && b <= b'Z' {
+        Some(b - b'A')
+    } else if b >= b'a' && b <= b'z' {
+        Some(b - b'a' + 26)
+    } else if b >= b'0' && b <= b'9' {
+        Some(b - b'0' + 52)
+    } else if b == b'+' {
+        Some(62)
+    } else if b == b'/' {
+        Some(63)
+    } else {
+        None
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum QpState {
+    Data,
+    /// After a `=`.
+    Escape,
+    /// After a `=` and the first hex digit.
+    EscapeHex(u8),
+    /// After a `=` and a CR, which must be a soft line break.
+    SoftBreakCr,
+}
+
+impl Default for QpState {
+    fn default() -> Self {
+        QpState::Data
+    }
+}
+
+/// Decodes `quoted-printable` ([IETF RFC 2045, Section 6.7][rfc2045-6.7]).
+///
+/// Soft line breaks (`=` at the end of a line) are removed, as is whitespace at the end of a
+/// line, which is padding added in transport. Lowercase hex digits are accepted in escapes.
+///
+/// [rfc2045-6.7]: https://tools.ietf.org/html/rfc2045#section-6.7
+#[derive(Debug, Default)]
+struct QuotedPrintable {
+    state: QpState,
+    /// Spaces, tabs and CRs which are only output if something other than a line break
+    /// follows them.
+    pending: Vec<u8>,
+}
+
+impl QuotedPrintable {
+    fn decode(&mut self, input: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        for &b in input {
+            self.state = match (self.state, b) {
+                (QpState::Data, b' ') | (QpState::Data, b'\t') | (QpState::Data, b'\r') => {
+                    self.pending.push(b);
+                    QpState::Data
+                },
+                (QpState::Data, b'\n') => {
+                    // drop the whitespace before the line break, but not the CR of a CRLF
+                    if self.pending.last() == Some(&b'\r') {
+                        out.push(b'\r');
+                    }
+
+                    self.pending.clear();
+                    out.push(b'\n');
+                    QpState::Data
+                },
+                (QpState::Data, b'=') => {
+                    out.extend_from_slice(&self.pending);
+                    self.pending.clear();
+                    QpState::Escape
+                },
+                (QpState::Data, _) => {
+                    out.extend_from_slice(&self.pending);
+                    self.pending.clear();
+                    out.push(b);
+                    QpState::Data
+                },
+                // whitespace may be added between a soft line break and the end of the line
+                (QpState::Escape, b' ') | (QpState::Escape, b'\t') => QpState::Escape,
+                (QpState::Escape, b'\r') => QpState::SoftBreakCr,
+                (QpState::Escape, b'\n') | (QpState::SoftBreakCr, b'\n') => QpState::Data,
+                (QpState::Escape, _) => QpState::EscapeHex(hex_value(b)?),
+                (QpState::EscapeHex(high), _) => {
+                    out.push(high << 4 | hex_value(b)?);
+                    QpState::Data
+                },
+                (QpState::SoftBreakCr, _) => return Err(invalid_data("expected LF after CR")),
+            };
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        if let QpState::EscapeHex(_) = self.state {
+            return Err(invalid_data("unexpected end of quoted-printable escape"));
+        }
+
+        // trailing whitespace is dropped as at the end of a line, but a CR is kept
+        if self.pending.last() == Some(&b'\r') {
+            out.push(b'\r');
+        }
+
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+fn hex_value(b: u8) -> io::Result<u8> {
+    (b as char).to_digit(16).map(|digit| digit as u8)
+        .ok_or_else(|| invalid_data("invalid quoted-printable escape"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::TransferDecoder;
+
+    use std::io::{self, BufReader};
+
+    fn decode(encoding: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decoder = TransferDecoder::for_encoding(encoding).unwrap();
+        // a tiny buffer so the data is decoded in pieces
+        let mut source = BufReader::with_capacity(3, data);
+        let mut decoded = Vec::new();
+        let mut buf = [0; 2];
+
+        loop {
+            match decoder.read(&mut source, &mut buf)? {
+                0 => return Ok(decoded),
+                read => decoded.extend_from_slice(&buf[..read]),
+            }
+        }
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(decode("base64", b"c29tZSB0ZXh0").unwrap(), b"some text");
+        assert_eq!(decode("BASE64", b"c29t\r\nZSB0\r\nZXh0IQ==").unwrap(), b"some text!");
+        assert_eq!(decode("base64", b"c29tZSB0ZXh0IQ").unwrap(), b"some text!");
+        assert_eq!(decode("base64", b"").unwrap(), b"");
+
+        for invalid in &[&b"c29t*"[..], b"c29tZ", b"c2=9", b"c29tZQ==ZQ"] {
+            assert_eq!(decode("base64", invalid).unwrap_err().kind(), io::ErrorKind::InvalidData,
+                       "{:?}", String::from_utf8_lossy(invalid));
+        }
+    }
+
+    #[test]
+    fn test_quoted_printable() {
+        assert_eq!(decode("quoted-printable", b"caf=C3=A9 =3D tea").unwrap(),
+                   "caf\u{E9} = tea".as_bytes());
+        // soft line breaks, and whitespace at the ends of lines
+        assert_eq!(decode("quoted-printable", b"a long=\r\n line  \r\nwrapped= \nhere\t").unwrap(),
+                   b"a long line\r\nwrappedhere");
+        assert_eq!(decode("quoted-printable", b"lower =c3=a9").unwrap(), "lower \u{E9}".as_bytes());
+
+        for invalid in &[&b"=ZZ"[..], b"=\rX", b"end=4"] {
+            assert_eq!(decode("quoted-printable", invalid).unwrap_err().kind(),
+                       io::ErrorKind::InvalidData, "{:?}", String::from_utf8_lossy(invalid));
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Reads a request built with the Hyper 1.x client integration back with the server.
+extern crate hyper_1;
+extern crate multipart;
+
+use hyper_1::body::Body;
+use hyper_1::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper_1::Request;
+
+use multipart::client::hyper1::request;
+use multipart::client::lazy::Multipart as LazyMultipart;
+use multipart::server::{boundary_from_content_type, Multipart};
+
+use std::io::Read;
+use std::pin::Pin;
+use std::ptr;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// The body never returns `Poll::Pending`, so it never needs waking.
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(ptr::null(), &VTABLE)
+    }
+
+    fn noop(_: *const ()) {}
+
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+}
+
+fn collect<B: Body + Unpin>(mut body: B) -> Vec<u8> where B::Error: std::fmt::Debug {
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut collected = Vec::new();
+
+    loop {
+        match Pin::new(&mut body).poll_frame(&mut cx) {
+            Poll::Ready(Some(frame)) => {
+                if let Ok(data) = frame.unwrap().into_data() {
+                    collected.extend_from_slice(&data);
+                }
+            },
+            Poll::Ready(None) => return collected,
+            Poll::Pending => panic!("the body should never be pending"),
+        }
+    }
+}
+
+fn read_fields(content_type: &str, body: &[u8]) -> Vec<(String, String)> {
+    let boundary = boundary_from_content_type(content_type).unwrap();
+    let mut multipart = Multipart::with_body(body, boundary);
+    let mut fields = Vec::new();
+
+    multipart.foreach_entry(|mut field| {
+        let mut data = String::new();
+        field.data.read_to_string(&mut data).unwrap();
+        fields.push((field.headers.name.to_string(), data));
+    }).unwrap();
+
+    fields
+}
+
+#[test]
+fn hyper1_sized_body() {
+    let fields = LazyMultipart::new()
+        .add_text("text", "Hello, world!")
+        .prepare().unwrap();
+
+    let content_len = fields.content_len().unwrap();
+    let req = request(Request::post("http://localhost/"), fields).unwrap();
+
+    assert_eq!(req.headers()[CONTENT_LENGTH], content_len.to_string().as_str());
+    assert_eq!(req.body().size_hint().exact(), Some(content_len));
+
+    let content_type = req.headers()[CONTENT_TYPE].to_str().unwrap().to_owned();
+    let body = collect(req.into_body());
+
+    assert_eq!(body.len() as u64, content_len);
+    assert_eq!(read_fields(&content_type, &body),
+               vec![("text".to_owned(), "Hello, world!".to_owned())]);
+}
+
+#[test]
+fn hyper1_chunked_body() {
+    let mut stream = &b"streamed data"[..];
+
+    let fields = LazyMultipart::new()
+        .add_stream("stream", &mut stream, None as Option<&str>, None)
+        .prepare().unwrap();
+
+    let req = request(Request::post("http://localhost/"), fields).unwrap();
+
+    assert!(req.headers().get(CONTENT_LENGTH).is_none());
+    assert_eq!(req.body().size_hint().exact(), None);
+
+    let content_type = req.headers()[CONTENT_TYPE].to_str().unwrap().to_owned();
+    let body = collect(req.into_body());
+
+    assert_eq!(read_fields(&content_type, &body),
+               vec![("stream".to_owned(), "streamed data".to_owned())]);
+}
@@ -0,0 +1,101 @@
+//! Round-trips a request built with the Hyper 1.x client integration through a local Hyper 1.x
+//! server, which echoes the fields it parses.
+extern crate bytes_1;
+extern crate http_body_util;
+extern crate hyper_1;
+extern crate hyper_util;
+extern crate multipart;
+extern crate tokio;
+
+use bytes_1::Bytes;
+
+use http_body_util::{BodyExt, Full};
+
+use hyper_1::body::Incoming;
+use hyper_1::client::conn::http1 as client_http1;
+use hyper_1::header::{CONTENT_TYPE, HOST};
+use hyper_1::server::conn::http1 as server_http1;
+use hyper_1::service::service_fn;
+use hyper_1::{Request, Response, StatusCode};
+
+use hyper_util::rt::TokioIo;
+
+use multipart::client::hyper1::request;
+use multipart::client::lazy::{Multipart as LazyMultipart, PreparedFields};
+use multipart::server::{boundary_from_content_type, Multipart};
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Builder as RuntimeBuilder;
+use tokio::task::{self, LocalSet};
+
+use std::io::Read;
+
+/// Echoes each field as `name=data\n`.
+async fn echo_fields(req: Request<Incoming>) -> Result<Response<Full<Bytes>>, hyper_1::Error> {
+    let content_type = req.headers()[CONTENT_TYPE].to_str().unwrap().to_owned();
+    let body = req.into_body().collect().await?.to_bytes();
+
+    let boundary = boundary_from_content_type(&content_type).unwrap();
+    let mut multipart = Multipart::with_body(&body[..], boundary);
+    let mut echo = String::new();
+
+    multipart.foreach_entry(|mut field| {
+        let mut data = String::new();
+        field.data.read_to_string(&mut data).unwrap();
+        echo.push_str(&format!("{}={}\n", field.headers.name, data));
+    }).unwrap();
+
+    Ok(Response::new(Full::new(Bytes::from(echo))))
+}
+
+/// Send `fields` to a local server and return its echo of them.
+///
+/// `client::hyper1::Body` isn't `Send`, so the client connection is driven on the current thread.
+fn round_trip(fields: PreparedFields<'static>) -> String {
+    let runtime = RuntimeBuilder::new_current_thread().enable_all().build().unwrap();
+
+    LocalSet::new().block_on(&runtime, async move {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        task::spawn_local(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            server_http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service_fn(echo_fields))
+                .await
+                .unwrap();
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = client_http1::handshake(TokioIo::new(stream)).await.unwrap();
+        task::spawn_local(conn);
+
+        let req = request(Request::post("/").header(HOST, addr.to_string()), fields).unwrap();
+        let res = sender.send_request(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let echo = res.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(echo.to_vec()).unwrap()
+    })
+}
+
+#[test]
+fn hyper1_sized_request() {
+    let fields = LazyMultipart::new()
+        .add_text("text", "Hello, world!")
+        .prepare().unwrap();
+
+    assert!(fields.content_len().is_some());
+    assert_eq!(round_trip(fields), "text=Hello, world!\n");
+}
+
+#[test]
+fn hyper1_chunked_request() {
+    let fields = LazyMultipart::new()
+        .add_text("text", "Hello, world!")
+        .add_stream("stream", &b"streamed data"[..], None as Option<&str>, None)
+        .prepare().unwrap();
+
+    assert!(fields.content_len().is_none());
+    assert_eq!(round_trip(fields), "text=Hello, world!\nstream=streamed data\n");
+}
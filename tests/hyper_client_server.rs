@@ -0,0 +1,64 @@
+//! Round-trips a request through the Hyper client and server integrations over a local socket.
+extern crate hyper;
+extern crate multipart;
+
+use hyper::Client;
+use hyper::server::{Handler, Request, Response, Server};
+use hyper::server::response::Response as HyperResponse;
+use hyper::status::StatusCode;
+
+use multipart::client::lazy::Multipart as LazyMultipart;
+use multipart::server::hyper::{HyperRequest, MultipartHandler, Switch};
+use multipart::server::Multipart;
+
+use std::io::Read;
+
+struct NonMultipart;
+
+impl Handler for NonMultipart {
+    fn handle(&self, _: Request, mut res: Response) {
+        *res.status_mut() = StatusCode::BadRequest;
+        res.send(b"expected a multipart request").unwrap();
+    }
+}
+
+/// Echoes each field as `name=data\n`.
+struct EchoFields;
+
+impl MultipartHandler for EchoFields {
+    fn handle_multipart(&self, mut multipart: Multipart<HyperRequest>, res: HyperResponse) {
+        let mut echo = String::new();
+
+        multipart.foreach_entry(|mut field| {
+            let mut data = String::new();
+            field.data.read_to_string(&mut data).unwrap();
+            echo.push_str(&format!("{}={}\n", field.headers.name, data));
+        }).unwrap();
+
+        res.send(echo.as_bytes()).unwrap();
+    }
+}
+
+#[test]
+fn hyper_client_server() {
+    let mut listening = Server::http("127.0.0.1:0").unwrap()
+        .handle(Switch::new(NonMultipart, EchoFields)).unwrap();
+
+    let url = format!("http://{}/", listening.socket);
+
+    let mut stream = &b"streamed data"[..];
+
+    let mut res = LazyMultipart::new()
+        .add_text("text", "Hello, world!")
+        .add_stream("stream", &mut stream, None as Option<&str>, None)
+        .client_request(&Client::new(), &*url)
+        .unwrap();
+
+    let mut echo = String::new();
+    res.read_to_string(&mut echo).unwrap();
+
+    listening.close().unwrap();
+
+    assert_eq!(res.status, StatusCode::Ok);
+    assert_eq!(echo, "text=Hello, world!\nstream=streamed data\n");
+}
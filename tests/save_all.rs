@@ -0,0 +1,113 @@
+//! Saves a mixed form of text and file fields with `Multipart::save_all()`.
+extern crate multipart;
+
+#[cfg(feature = "json")]
+extern crate serde_json;
+
+use multipart::server::save::SavedData;
+use multipart::server::Multipart;
+
+use std::fs::File;
+use std::io::{self, Read};
+
+const BOUNDARY: &str = "boundary";
+
+fn mixed_form(file_data: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+
+    body.extend_from_slice(b"--boundary\r\n\
+                             Content-Disposition: form-data; name=\"text\"\r\n\r\n\
+                             Hello, world!\r\n\
+                             --boundary\r\n\
+                             Content-Disposition: form-data; name=\"file\"; filename=\"data.bin\"\r\n\
+                             Content-Type: application/octet-stream\r\n\r\n");
+    body.extend_from_slice(file_data);
+    body.extend_from_slice(b"\r\n--boundary--\r\n");
+    body
+}
+
+#[test]
+fn save_all_mixed_form() {
+    // larger than the default memory threshold of 10 KiB, and not valid UTF-8
+    let file_data = vec![0xFFu8; 16 * 1024];
+    let body = mixed_form(&file_data);
+
+    let entries = Multipart::with_body(&body[..], BOUNDARY).save_all().unwrap();
+    assert_eq!(entries.fields_count(), 2);
+
+    for (name, fields) in &entries.fields {
+        assert_eq!(fields.len(), 1);
+        let field = &fields[0];
+
+        match &**name {
+            "text" => match field.data {
+                SavedData::Text(ref text) => assert_eq!(text, "Hello, world!"),
+                ref other => panic!("expected text field in memory, got {:?}", other),
+            },
+            "file" => {
+                assert_eq!(field.headers.filename.as_ref().map(String::as_str), Some("data.bin"));
+
+                match field.data {
+                    SavedData::File(ref path, size) => {
+                        assert_eq!(size, file_data.len() as u64);
+
+                        let mut saved = Vec::new();
+                        File::open(path).unwrap().read_to_end(&mut saved).unwrap();
+                        assert_eq!(saved, file_data);
+                    },
+                    ref other => panic!("expected file field on disk, got {:?}", other),
+                }
+            },
+            other => panic!("unexpected field name {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn save_all_count_limit() {
+    let mut body = Vec::new();
+
+    for _ in 0 .. 300 {
+        body.extend_from_slice(b"--boundary\r\n\
+                                 Content-Disposition: form-data; name=\"field\"\r\n\r\n\
+                                 data\r\n");
+    }
+
+    body.extend_from_slice(b"--boundary--\r\n");
+
+    match Multipart::with_body(&body[..], BOUNDARY).save_all() {
+        Ok(_) => panic!("expected the default count limit to be hit"),
+        Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+    }
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn save_all_summary() {
+    use std::path::Path;
+
+    let file_data = vec![0xFFu8; 16 * 1024];
+    let body = mixed_form(&file_data);
+
+    let entries = Multipart::with_body(&body[..], BOUNDARY).save_all().unwrap();
+    let summary = serde_json::to_value(entries.summary()).unwrap();
+
+    let fields = summary["fields"].as_array().unwrap();
+    assert_eq!(fields.len(), 2);
+
+    // sorted by name
+    let file = &fields[0];
+    assert_eq!(file["name"], "file");
+    assert_eq!(file["filename"], "data.bin");
+    assert_eq!(file["content_type"], "application/octet-stream");
+    assert_eq!(file["size"], file_data.len() as u64);
+
+    assert!(Path::new(file["path"].as_str().unwrap()).is_file());
+
+    let text = &fields[1];
+    assert_eq!(text["name"], "text");
+    assert!(text["filename"].is_null());
+    assert!(text["content_type"].is_null());
+    assert_eq!(text["size"], 13);
+    assert!(text["path"].is_null());
+}